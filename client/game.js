@@ -32,6 +32,13 @@ const VOTING_SEQUENCES = Object.freeze({
   ]),
 });
 
+// Votes that are not cards. They are valid under every sequence.
+const SPECIAL_VOTES = Object.freeze([
+  { value: "Abstain", label: "Abstain" },
+  { value: "Unknown", label: "?" },
+  { value: "Coffee", label: "☕" },
+]);
+
 // Only letters, numbers, and whitespace are allowed in player names.
 const ILLEGAL_NAME_CHARS = /[^\p{L}\p{N}\s]/u;
 
@@ -42,22 +49,35 @@ class Game {
       type: "None",
       player_id: 0,
       name: "",
-      value: 0,
+      value: null,
       previous_player_size: 0,
       current_sequence: "Fibonacci",
     };
-    // Values must match the server-side values
+    // Seat cards in the page; the server may use fewer of them.
     this.max_table_size = 12;
+    // Replaced by the server's values when a seat is assigned.
+    this.room_size = 12;
     this.overflow_index = 100;
+    // The agenda as it was when a pasted import was sent, until the server
+    // answers it.
+    this.pending_import = null;
   }
 
   async run() {
     // read the room parameter from the URL
-    const room_name = new URL(window.location.href).searchParams.get("room");
-    const ws_protocol = window.location.protocol === "https:" ? "wss:" : "ws:";
-    const ws = await this.connect_to_server(
-      `${ws_protocol}//${window.location.host}/ws/${room_name}`,
-    );
+    const url = new URL(window.location.href);
+    const params = url.searchParams;
+    this.room_name = params.get("room");
+    // A shared link may carry the passphrase of a locked room. Take it out of
+    // the address bar so it stays out of the browser history and referrers.
+    if (params.has("passphrase")) {
+      if (params.get("passphrase")) {
+        sessionStorage.setItem(this.passphrase_key(), params.get("passphrase"));
+      }
+      params.delete("passphrase");
+      window.history.replaceState(null, "", url);
+    }
+    await this.connect_to_server();
 
     // Debaouncing is used for the field inputs to limit spamming the server.
     const debounce_time = 10;
@@ -68,7 +88,7 @@ class Game {
     name_input.addEventListener("input", () => {
       clearTimeout(debounce_timer);
       debounce_timer = setTimeout(() => {
-        this.handle_name_change(this.local_state, ws);
+        this.handle_name_change(this.local_state, this.ws);
       }, debounce_time);
     });
     name_input.focus();
@@ -76,35 +96,39 @@ class Game {
     // Add event listener to value input
     const value_input = document.getElementById("player_value");
     value_input.addEventListener("change", () => {
-      this.handle_value_change(this.local_state, ws);
+      this.handle_value_change(this.local_state, this.ws);
     });
 
     // Add event listener to reveal button
     const reveal_button = document.getElementById("reveal-button");
     reveal_button.addEventListener("click", () => {
-      this.handle_reveal_button_click(this.local_state, ws);
+      this.handle_reveal_button_click(this.local_state, this.ws);
     });
 
     // Add click handlers to all player card slots for seat switching.
     // If the clicking player is the captain and clicks their own card, open the
-    // voting-sequence popup instead of attempting a seat switch.
+    // voting-sequence popup instead of attempting a seat switch; clicking
+    // another delegate's card opens the moderation popup.
     for (let i = 0; i < this.max_table_size; i++) {
       const player_card = document.getElementById(`player${i}id`);
       if (player_card) {
         player_card.addEventListener("click", () => {
+          const occupant = this.server_state.players?.find((p) => p.player_id === i);
           if (i === this.local_state.player_id && this.is_captain()) {
             this.open_sequence_popup();
+          } else if (occupant && this.is_captain()) {
+            this.open_player_popup(occupant);
           } else {
-            this.handle_seat_change(i, ws);
+            this.handle_seat_change(i, this.ws);
           }
         });
       }
     }
 
     // Sequence option buttons: send ChangeSequence and close popup
-    document.querySelectorAll(".sequence-option").forEach((btn) => {
+    document.querySelectorAll(".sequence-option[data-sequence]").forEach((btn) => {
       btn.addEventListener("click", () => {
-        ws.send(
+        this.ws.send(
           JSON.stringify({
             type: "ChangeSequence",
             player_id: this.local_state.player_id,
@@ -115,22 +139,204 @@ class Game {
       });
     });
 
+    // Custom sequence: one card per comma-separated label, valued in order
+    document.getElementById("custom-sequence-apply").addEventListener("click", () => {
+      const labels = document
+        .getElementById("custom-sequence-input")
+        .value.split(",")
+        .map((label) => label.trim())
+        .filter((label) => label.length > 0);
+      if (labels.length === 0) return;
+      this.ws.send(
+        JSON.stringify({
+          type: "ChangeSequence",
+          player_id: this.local_state.player_id,
+          sequence: { Custom: labels.map((label, i) => ({ value: i + 1, label })) },
+        }),
+      );
+      document.getElementById("sequence-popup").style.display = "none";
+    });
+
+    document.getElementById("auto-reveal-apply").addEventListener("click", () => {
+      this.ws.send(
+        JSON.stringify({
+          type: "ChangeAutoReveal",
+          player_id: this.local_state.player_id,
+          auto_reveal: {
+            enabled: document.getElementById("auto-reveal-enabled").checked,
+            countdown_secs: parseInt(document.getElementById("auto-reveal-countdown").value) || 0,
+          },
+        }),
+      );
+      document.getElementById("sequence-popup").style.display = "none";
+    });
+
+    document.getElementById("passphrase-lock").addEventListener("click", () => {
+      const input = document.getElementById("passphrase-input");
+      if (!input.value) return;
+      this.send_passphrase(input.value);
+      input.value = "";
+      document.getElementById("sequence-popup").style.display = "none";
+    });
+    document.getElementById("passphrase-unlock").addEventListener("click", () => {
+      this.send_passphrase(null);
+      document.getElementById("sequence-popup").style.display = "none";
+    });
+
+    document.getElementById("timer-start").addEventListener("click", () => {
+      const minutes = parseInt(document.getElementById("timer-minutes").value) || 0;
+      if (minutes <= 0) return;
+      this.ws.send(
+        JSON.stringify({
+          type: "StartTimer",
+          player_id: this.local_state.player_id,
+          seconds: minutes * 60,
+          reveal_on_expiry: document.getElementById("timer-reveal").checked,
+        }),
+      );
+    });
+    document.getElementById("timer-stop").addEventListener("click", () => {
+      this.ws.send(JSON.stringify({ type: "StopTimer", player_id: this.local_state.player_id }));
+    });
+
+    // Tick the countdowns between server updates.
+    setInterval(() => {
+      this.update_reveal_countdown();
+      this.update_timer();
+      this.update_spectators();
+    }, 250);
+
+    this.update_export_links();
+
+    // Agenda controls (captain only; the server enforces this too)
+    document.getElementById("topic-add").addEventListener("click", () => {
+      const title_input = document.getElementById("topic-title");
+      const link_input = document.getElementById("topic-link");
+      if (!title_input.value.trim()) return;
+      this.ws.send(
+        JSON.stringify({
+          type: "AddTopic",
+          player_id: this.local_state.player_id,
+          title: title_input.value,
+          link: link_input.value.trim() || null,
+        }),
+      );
+      title_input.value = "";
+      link_input.value = "";
+    });
+    document.getElementById("topic-paste-import").addEventListener("click", () => {
+      const paste = document.getElementById("topic-paste");
+      if (!paste.value.trim()) return;
+      this.ws.send(
+        JSON.stringify({
+          type: "ImportTopics",
+          player_id: this.local_state.player_id,
+          data: paste.value,
+        }),
+      );
+      // Keep the paste until the import lands, so a rejected one can be fixed.
+      this.pending_import = JSON.stringify(this.server_state.agenda ?? []);
+    });
+    document.getElementById("topic-file").addEventListener("change", async (event) => {
+      const file = event.target.files[0];
+      event.target.value = "";
+      if (!file) return;
+      const response = await fetch(`/rooms/${encodeURIComponent(this.room_name)}/import`, {
+        method: "POST",
+        headers: { Authorization: `Bearer ${sessionStorage.getItem(this.resume_token_key())}` },
+        body: await file.text(),
+      });
+      if (!response.ok) {
+        const result = await response.json().catch(() => ({ errors: [response.statusText] }));
+        alert(`Import failed:\n${result.errors.join("\n")}`);
+      }
+    });
+    document.getElementById("topic-next").addEventListener("click", () => {
+      this.ws.send(
+        JSON.stringify({ type: "AdvanceTopic", player_id: this.local_state.player_id }),
+      );
+    });
+
     // Close button
+    document.getElementById("player-make-captain").addEventListener("click", () => {
+      this.send_moderation("TransferCaptain", { new_captain_id: this.popup_target });
+    });
+    document.getElementById("player-demote").addEventListener("click", () => {
+      this.send_moderation("DemotePlayer", { target_id: this.popup_target });
+    });
+    document.getElementById("player-kick").addEventListener("click", () => {
+      this.send_moderation("KickPlayer", { target_id: this.popup_target });
+    });
+    document.getElementById("player-popup-close").addEventListener("click", () => {
+      document.getElementById("player-popup").style.display = "none";
+    });
+
     document.getElementById("sequence-popup-close").addEventListener("click", () => {
       document.getElementById("sequence-popup").style.display = "none";
     });
   }
 
+  // Captain-only actions on another delegate; the server enforces this too.
+  open_player_popup(player) {
+    this.popup_target = player.player_id;
+    document.getElementById("player-popup-name").textContent = player.player_name;
+    document.getElementById("player-popup").style.display = "flex";
+  }
+
+  send_moderation(type, fields) {
+    this.ws.send(JSON.stringify({ type, player_id: this.local_state.player_id, ...fields }));
+    document.getElementById("player-popup").style.display = "none";
+  }
+
+  update_spectators() {
+    const spectators = (this.server_state.players ?? []).filter(
+      (p) => p.player_id >= this.overflow_index,
+    );
+    const panel = document.getElementById("spectator-panel");
+    panel.style.display = this.is_captain() && spectators.length > 0 ? "block" : "none";
+    document.getElementById("spectator-list").replaceChildren(
+      ...spectators.map((spectator) => {
+        const item = document.createElement("li");
+        item.textContent = spectator.benched
+          ? `${spectator.player_name} (benched) `
+          : `${spectator.player_name} `;
+        const seat = document.createElement("button");
+        seat.textContent = "Seat";
+        seat.addEventListener("click", () =>
+          this.send_moderation("PromoteSpectator", { target_id: spectator.player_id }),
+        );
+        const kick = document.createElement("button");
+        kick.textContent = "Kick";
+        kick.addEventListener("click", () =>
+          this.send_moderation("KickPlayer", { target_id: spectator.player_id }),
+        );
+        item.append(seat, kick);
+        return item;
+      }),
+    );
+  }
+
   open_sequence_popup() {
     const popup = document.getElementById("sequence-popup");
     popup.style.display = "flex";
+    const auto_reveal = this.server_state.auto_reveal ?? { enabled: false, countdown_secs: 0 };
+    document.getElementById("auto-reveal-enabled").checked = auto_reveal.enabled;
+    document.getElementById("auto-reveal-countdown").value = auto_reveal.countdown_secs;
+    document.getElementById("passphrase-status").textContent = this.server_state.locked
+      ? "(locked)"
+      : "(open)";
     // Highlight the currently active sequence
-    document.querySelectorAll(".sequence-option").forEach((btn) => {
-      btn.classList.toggle(
-        "active",
-        btn.dataset.sequence === (this.server_state.voting_sequence ?? "Fibonacci"),
-      );
+    const active = this.server_state.voting_sequence ?? "Fibonacci";
+    document.querySelectorAll(".sequence-option[data-sequence]").forEach((btn) => {
+      btn.classList.toggle("active", btn.dataset.sequence === active);
     });
+    const custom = document.getElementById("custom-sequence");
+    custom.classList.toggle("active", typeof active === "object");
+    if (typeof active === "object") {
+      document.getElementById("custom-sequence-input").value = active.Custom.map(
+        (card) => card.label,
+      ).join(", ");
+    }
   }
 
   handle_seat_change(new_seat, ws) {
@@ -150,39 +356,177 @@ class Game {
   }
 
   is_captain() {
-    const active_players = (this.server_state.players || []).filter(
-      (p) => p.player_id < this.overflow_index,
-    );
-    if (active_players.length === 0) return false;
-    const min_id = Math.min(...active_players.map((p) => p.player_id));
-    return this.local_state.player_id === min_id;
+    return this.server_state.captain_id === this.local_state.player_id;
+  }
+
+  sequence_cards(sequence) {
+    if (typeof sequence === "object" && sequence !== null) return sequence.Custom;
+    return VOTING_SEQUENCES[sequence ?? "Fibonacci"];
   }
 
-  update_vote_options(sequence_name) {
+  update_vote_options(voting_sequence) {
     try {
-      const sequence = VOTING_SEQUENCES[sequence_name];
+      const sequence = this.sequence_cards(voting_sequence);
       const value_input = document.getElementById("player_value");
-      const current_value = parseInt(value_input.value);
+      const current_value = value_input.value;
       value_input.replaceChildren();
       const placeholder = document.createElement("option");
-      placeholder.value = "0";
+      placeholder.value = "";
       placeholder.textContent = "Select a value";
       value_input.appendChild(placeholder);
-      for (const item of sequence) {
+      const options = [...sequence, ...SPECIAL_VOTES];
+      for (const item of options) {
         const option = document.createElement("option");
         option.value = item.value;
         option.textContent = item.label;
         value_input.appendChild(option);
       }
-      value_input.value = sequence.some((item) => item.value === current_value) ? current_value : 0;
+      value_input.value = options.some((item) => String(item.value) === current_value)
+        ? current_value
+        : "";
     } catch (e) {
       console.error("Failed to update vote options:", e);
     }
   }
 
+  topic_element(topic) {
+    if (!topic.link) return document.createTextNode(topic.title);
+    const link = document.createElement("a");
+    link.href = topic.link;
+    link.target = "_blank";
+    link.rel = "noopener noreferrer";
+    link.textContent = topic.title;
+    return link;
+  }
+
+  update_agenda() {
+    const is_captain = this.is_captain();
+    const current = document.getElementById("current-topic");
+    current.replaceChildren(
+      this.server_state.current_topic
+        ? this.topic_element(this.server_state.current_topic)
+        : document.createTextNode("No topic"),
+    );
+    document.getElementById("agenda-controls").style.display = is_captain ? "flex" : "none";
+    document.getElementById("import-controls").style.display = is_captain ? "flex" : "none";
+    document.getElementById("timer-controls").style.display = is_captain ? "flex" : "none";
+
+    const agenda = this.server_state.agenda ?? [];
+    const agenda_list = document.getElementById("agenda-list");
+    agenda_list.replaceChildren(
+      ...agenda.map((topic, index) => {
+        const item = document.createElement("li");
+        if (topic.key) item.append(`${topic.key} `);
+        item.appendChild(this.topic_element(topic));
+        if (topic.description) item.title = topic.description;
+        if (is_captain) {
+          const up = document.createElement("button");
+          up.textContent = "↑";
+          up.disabled = index === 0;
+          up.addEventListener("click", () => {
+            this.ws.send(
+              JSON.stringify({
+                type: "MoveTopic",
+                player_id: this.local_state.player_id,
+                topic_id: topic.id,
+                index: index - 1,
+              }),
+            );
+          });
+          const remove = document.createElement("button");
+          remove.textContent = "✕";
+          remove.addEventListener("click", () => {
+            this.ws.send(
+              JSON.stringify({
+                type: "RemoveTopic",
+                player_id: this.local_state.player_id,
+                topic_id: topic.id,
+              }),
+            );
+          });
+          item.append(up, remove);
+        }
+        return item;
+      }),
+    );
+
+    const history_list = document.getElementById("history-list");
+    history_list.replaceChildren(
+      ...(this.server_state.history ?? []).map((round) => {
+        const item = document.createElement("li");
+        const votes = round.votes
+          .filter((vote) => vote.label)
+          .map((vote) => `${vote.player_name}: ${vote.label}`)
+          .join(", ");
+        item.append(
+          round.topic ? this.topic_element(round.topic) : document.createTextNode("(no topic)"),
+          document.createTextNode(` — ${votes || "no votes"}`),
+        );
+        return item;
+      }),
+    );
+  }
+
+  update_timer() {
+    const timer_element = document.getElementById("timer-display");
+    if (!timer_element) return;
+    const timer = this.server_state.timer;
+    if (!timer) {
+      timer_element.textContent = "";
+      return;
+    }
+    if (timer.expired) {
+      timer_element.textContent = "Time's up!";
+      return;
+    }
+    // Count down from what the server said was left, not the local clock.
+    const elapsed = (Date.now() - this.timer_received_at) / 1000;
+    const remaining = Math.max(0, Math.ceil(timer.remaining_secs - elapsed));
+    const minutes = Math.floor(remaining / 60);
+    const seconds = String(remaining % 60).padStart(2, "0");
+    timer_element.textContent = `⏱ ${minutes}:${seconds}`;
+  }
+
+  update_reveal_countdown() {
+    const countdown_element = document.getElementById("reveal-countdown");
+    if (!countdown_element) return;
+    const reveal_at = this.server_state.auto_reveal_at;
+    if (reveal_at === null || reveal_at === undefined) {
+      countdown_element.textContent = "";
+      return;
+    }
+    const remaining = Math.max(0, Math.ceil(reveal_at - Date.now() / 1000));
+    countdown_element.textContent = `Everyone has voted – revealing in ${remaining}s`;
+  }
+
+  update_round_summary() {
+    const summary_element = document.getElementById("round-summary");
+    const summary = this.server_state.round_summary;
+    if (!summary_element) return;
+    if (!summary) {
+      summary_element.textContent = "";
+      return;
+    }
+    const parts = [`${summary.vote_count} votes`];
+    if (summary.abstentions > 0) parts.push(`${summary.abstentions} abstained`);
+    if (summary.unknown > 0) parts.push(`${summary.unknown} need more info`);
+    if (summary.coffee > 0) parts.push(`${summary.coffee} want a break`);
+    if (summary.not_voted > 0) parts.push(`${summary.not_voted} did not vote`);
+    if (summary.mean !== null && summary.mean !== undefined) {
+      parts.push(`mean ${summary.mean.toFixed(1)}`, `median ${summary.median}`);
+      parts.push(`range ${summary.min}–${summary.max}`);
+    } else {
+      parts.push(
+        ...summary.distribution.filter((c) => c.count > 0).map((c) => `${c.label}: ${c.count}`),
+      );
+    }
+    if (summary.consensus) parts.push("consensus!");
+    summary_element.textContent = parts.join(" · ");
+  }
+
   get_display_label(value) {
-    const sequence = VOTING_SEQUENCES[this.local_state.current_sequence];
-    const item = sequence.find((item) => item.value === value);
+    const sequence = this.sequence_cards(this.local_state.current_sequence);
+    const item = [...sequence, ...SPECIAL_VOTES].find((item) => item.value === value);
     return item ? item.label : String(value);
   }
 
@@ -197,12 +541,12 @@ class Game {
       })
       .slice(0, 5);
     const is_missing_votes = active_players.some(
-      (obj) => obj && typeof obj === "object" && obj.value < 1,
+      (obj) => obj && typeof obj === "object" && !obj.has_voted,
     );
 
     if (this.server_state.all_revealed) {
-      // Set the Select to default value
-      document.getElementById("player_value").value = 0;
+      ws.send(JSON.stringify({ type: "StartNewRound" }));
+      return;
     }
 
     const request = {};
@@ -222,7 +566,15 @@ class Game {
   }
 
   handle_value_change(local_state, ws) {
-    local_state.value = parseInt(document.getElementById("player_value").value);
+    // Cards are sent as numbers, special votes by name.
+    const raw_value = document.getElementById("player_value").value;
+    if (raw_value === "") {
+      // "Select a value" takes the vote back.
+      local_state.value = null;
+      ws.send(JSON.stringify({ type: "WithdrawVote", player_id: local_state.player_id }));
+      return;
+    }
+    local_state.value = /^\d+$/.test(raw_value) ? parseInt(raw_value) : raw_value;
     const request = local_state;
     request.type = "ChangeValue";
     ws.send(JSON.stringify(request));
@@ -248,35 +600,166 @@ class Game {
     ws.send(JSON.stringify(request));
   }
 
-  async connect_to_server(server_address) {
+  resume_token_key() {
+    return `resume_token:${this.room_name}`;
+  }
+
+  passphrase_key() {
+    return `passphrase:${this.room_name}`;
+  }
+
+  send_passphrase(passphrase) {
+    this.ws.send(
+      JSON.stringify({
+        type: "SetPassphrase",
+        player_id: this.local_state.player_id,
+        passphrase: passphrase,
+      }),
+    );
+    // Keep our own reconnects and export links working in the locked room.
+    if (passphrase) {
+      sessionStorage.setItem(this.passphrase_key(), passphrase);
+    } else {
+      sessionStorage.removeItem(this.passphrase_key());
+    }
+    this.update_export_links();
+  }
+
+  update_export_links() {
+    const passphrase = sessionStorage.getItem(this.passphrase_key());
+    const query = passphrase ? `?passphrase=${encodeURIComponent(passphrase)}` : "";
+    document.querySelectorAll(".export-links a").forEach((link) => {
+      link.href = `/rooms/${encodeURIComponent(this.room_name)}/export.${link.dataset.format}${query}`;
+    });
+  }
+
+  server_address() {
+    const ws_protocol = window.location.protocol === "https:" ? "wss:" : "ws:";
+    const address = `${ws_protocol}//${window.location.host}/ws/${this.room_name}`;
+    const query = new URLSearchParams();
+    // Presenting the resume token lets the server hand back our old seat after
+    // a refresh or a dropped connection.
+    const resume_token = sessionStorage.getItem(this.resume_token_key());
+    if (resume_token) query.set("resume", resume_token);
+    const passphrase = sessionStorage.getItem(this.passphrase_key());
+    if (passphrase) query.set("passphrase", passphrase);
+    return query.size ? `${address}?${query}` : address;
+  }
+
+  reconnect() {
+    setTimeout(() => {
+      this.connect_to_server().catch(() => this.reconnect());
+    }, 1000);
+  }
+
+  async connect_to_server() {
     return new Promise((resolve, reject) => {
-      const ws = new WebSocket(server_address);
+      const ws = new WebSocket(this.server_address());
+      // Only the first update on a socket carries the room's whole history,
+      // and it is the one to check the stored passphrase against.
+      let synced = false;
       ws.onopen = () => {
         console.log("Connected to server");
+        this.ws = ws;
         resolve(ws);
       };
 
+      ws.onclose = () => {
+        // Only the live socket reconnects; failed attempts retry via reconnect().
+        if (this.ws !== ws || this.removed) return;
+        console.log("Disconnected from server, reconnecting...");
+        this.reconnect();
+      };
+
       ws.onmessage = (event) => {
         const temp_state = JSON.parse(event.data);
 
         if (temp_state.type === "PlayerAssigned") {
           this.local_state.player_id = temp_state.player_id;
+          sessionStorage.setItem(this.resume_token_key(), temp_state.resume_token);
+          this.room_size = Math.min(temp_state.max_room_size, this.max_table_size);
+          this.overflow_index = temp_state.overflow_index;
         }
 
         if (temp_state.type === "UpdateState") {
+          // The first update carries the whole history and replaces what an
+          // earlier session left; later ones only carry rounds completed since.
+          if (synced) {
+            temp_state.history = [...(this.server_state.history ?? []), ...(temp_state.history ?? [])];
+          }
+          temp_state.history ??= [];
+          // A passphrase only locks a room that does not exist yet; say so
+          // rather than let the user believe an open room is locked.
+          if (!synced && !temp_state.locked && sessionStorage.getItem(this.passphrase_key())) {
+            sessionStorage.removeItem(this.passphrase_key());
+            this.update_export_links();
+            alert("This room is not locked, so the passphrase was not used. The captain can lock it from the settings.");
+          }
+          synced = true;
+          this.timer_received_at = Date.now();
+          // A new round clears every vote, including the one shown here.
+          if (this.server_state.round !== undefined && temp_state.round !== this.server_state.round) {
+            document.getElementById("player_value").value = "";
+          }
           this.server_state = temp_state;
+          if (this.pending_import !== null && JSON.stringify(temp_state.agenda ?? []) !== this.pending_import) {
+            this.pending_import = null;
+            document.getElementById("topic-paste").value = "";
+          }
 
-          if (this.local_state.player_id === temp_state.notify_change.current_id) {
-            if (temp_state.notify_change.current_id !== temp_state.notify_change.new_id) {
-              console.log("Previous Player ID:", this.local_state.player_id);
-              this.local_state.player_id = temp_state.notify_change.new_id;
-              console.log("Updated Player ID:", this.local_state.player_id);
-              this.handle_name_change(this.local_state, ws);
-            }
+          // After resuming a seat, restore the name the server remembers.
+          const name_input = document.getElementById("player_name");
+          const me = temp_state.players.find((p) => p.player_id === this.local_state.player_id);
+          if (name_input && !name_input.value && me && me.player_name !== "Delegate Unknown") {
+            name_input.value = me.player_name;
+            this.local_state.name = me.player_name;
+          }
+
+          // Follow at most one move: a later entry may reuse the seat we left.
+          const moved = (temp_state.notify_changes ?? []).find(
+            (change) => change.current_id === this.local_state.player_id && change.current_id !== change.new_id
+          );
+          if (moved) {
+            console.log("Previous Player ID:", this.local_state.player_id);
+            this.local_state.player_id = moved.new_id;
+            console.log("Updated Player ID:", this.local_state.player_id);
+            this.handle_name_change(this.local_state, ws);
           }
           this.update_dom_from_server_state();
         }
 
+        if (temp_state.type === "PassphraseRequired") {
+          // The server closes the socket next; reconnect with the passphrase
+          // unless the user gives up.
+          const passphrase = prompt(temp_state.message);
+          if (passphrase) {
+            sessionStorage.setItem(this.passphrase_key(), passphrase);
+            this.update_export_links();
+          } else {
+            this.removed = true;
+          }
+        }
+
+        if (temp_state.type === "Removed") {
+          // Do not reconnect into a room we were removed from.
+          this.removed = true;
+          alert(temp_state.message);
+        }
+
+        if (temp_state.type === "ServerRestarting") {
+          // The socket closes next and reconnect() picks our seat back up
+          // once the server is back.
+          console.log(temp_state.message);
+        }
+
+        if (temp_state.type === "ErrorMessage") {
+          console.warn("Server rejected request:", temp_state.message);
+          if (this.pending_import !== null) {
+            this.pending_import = null;
+            alert(`Import failed:\n${temp_state.message}`);
+          }
+        }
+
         if (temp_state.type === "Ping") {
           ws.send(
             JSON.stringify({
@@ -295,10 +778,7 @@ class Game {
   }
 
   get_captain_id() {
-    if (!this.server_state.players || this.server_state.players.length === 0) return null;
-    const active = this.server_state.players.filter((p) => p.player_id < this.overflow_index);
-    if (active.length === 0) return null;
-    return Math.min(...active.map((p) => p.player_id));
+    return this.server_state.captain_id ?? null;
   }
 
   update_dom_from_server_state() {
@@ -307,7 +787,7 @@ class Game {
 
     // Update vote options if the sequence changed
     const server_sequence = this.server_state.voting_sequence;
-    if (server_sequence !== this.local_state.current_sequence) {
+    if (JSON.stringify(server_sequence) !== JSON.stringify(this.local_state.current_sequence)) {
       this.local_state.current_sequence = server_sequence;
       this.update_vote_options(server_sequence);
     }
@@ -325,6 +805,12 @@ class Game {
       const player_value_element = document.getElementById(`player${i}value`);
 
       if (player_card_element) {
+        // Seats past the server's room size never hold a delegate.
+        if (i >= this.room_size) {
+          player_card_element.classList.add("hidden-card");
+          continue;
+        }
+
         // Hide the second row if less than 6 players
         if (i >= 6) {
           if (current_size > 6) {
@@ -342,9 +828,11 @@ class Game {
 
         // Reset state for all cards first
         player_card_element.classList.remove("player-ready");
+        player_card_element.classList.remove("player-away");
         player_card_element.classList.add("player-vacant");
         player_card_element.classList.remove("player-captain");
         player_card_element.classList.remove("player-captain-self");
+        player_card_element.classList.remove("player-special-vote");
 
         // Find if there's a player for this position
         const player = this.server_state.players.find((p) => p.player_id === i);
@@ -352,9 +840,16 @@ class Game {
         if (player) {
           // Update card with player data
           player_card_element.classList.remove("player-vacant");
-          if (player.value > 0) {
+          if (player.has_voted) {
             player_card_element.classList.add("player-ready");
           }
+          if (player.away) {
+            player_card_element.classList.add("player-away");
+          }
+          // Abstain, "?" and coffee votes are names rather than card numbers.
+          if (this.server_state.all_revealed && typeof player.value === "string") {
+            player_card_element.classList.add("player-special-vote");
+          }
           if (player.player_id === captain_id) {
             player_card_element.classList.add("player-captain");
             // player-captain-self marks the local player's card when they are
@@ -370,7 +865,7 @@ class Game {
           }
           if (player_value_element) {
             player_value_element.textContent =
-              this.server_state.all_revealed && player.value
+              this.server_state.all_revealed && player.value != null
                 ? this.get_display_label(player.value)
                 : "?";
           }
@@ -401,7 +896,7 @@ class Game {
         if (player_card_element) {
           // Mark the card as occupied
           player_card_element.classList.remove("player-vacant");
-          if (player.value > 0) {
+          if (player.has_voted) {
             player_card_element.classList.add("player-ready");
           }
         }
@@ -412,7 +907,7 @@ class Game {
         if (player_value_element) {
           // Update the value only if revealed
           if (this.server_state.all_revealed) {
-            player_value_element.textContent = player.value
+            player_value_element.textContent = player.value != null
               ? this.get_display_label(player.value)
               : "?";
           } else {
@@ -423,7 +918,7 @@ class Game {
         const reveal_button_element = document.getElementById("reveal-button");
         if (reveal_button_element) {
           if (this.server_state.all_revealed) {
-            reveal_button_element.textContent = "Reset";
+            reveal_button_element.textContent = "New round";
           } else {
             reveal_button_element.textContent = "Reveal";
           }
@@ -431,6 +926,10 @@ class Game {
       }
     }
 
+    this.update_reveal_countdown();
+    this.update_round_summary();
+    this.update_agenda();
+
     const control_area = document.getElementById("polymorphic-hud");
     const value_input = document.getElementById("player_value");
     const reveal_button = document.getElementById("reveal-button");
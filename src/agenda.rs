@@ -0,0 +1,239 @@
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use uuid::Uuid;
+
+use crate::stats;
+use crate::structs::{GameState, RoundRecord, Topic, VoteRecord};
+
+pub const MAX_TOPICS: usize = 200;
+pub const MAX_TITLE_LEN: usize = 200;
+pub const MAX_LINK_LEN: usize = 2048;
+
+/// Seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Build a topic with a fresh ID, validating its title and link.
+pub fn new_topic(title: &str, link: Option<&str>) -> Result<Topic, String> {
+    let title = title.trim();
+    if title.is_empty() || title.chars().count() > MAX_TITLE_LEN {
+        return Err(format!(
+            "Topic titles must be 1 to {MAX_TITLE_LEN} characters"
+        ));
+    }
+    if title.chars().any(char::is_control) {
+        return Err("Topic titles must not contain control characters".to_string());
+    }
+
+    let link = match link.map(str::trim).filter(|l| !l.is_empty()) {
+        None => None,
+        Some(link) => {
+            if !(link.starts_with("https://") || link.starts_with("http://"))
+                || link.len() > MAX_LINK_LEN
+                || link.chars().any(|c| c.is_whitespace() || c.is_control())
+            {
+                return Err(format!("{link:?} is not a valid http(s) link"));
+            }
+            Some(link.to_string())
+        }
+    };
+
+    Ok(Topic {
+        id: Uuid::new_v4().simple().to_string(),
+        title: title.to_string(),
+        link,
+        ..Topic::default()
+    })
+}
+
+pub fn add_topic(state: &mut GameState, topic: Topic) -> Result<(), String> {
+    add_topics(state, vec![topic])
+}
+
+/// Append all of `topics`, or none of them if they do not fit.
+pub fn add_topics(state: &mut GameState, topics: Vec<Topic>) -> Result<(), String> {
+    if state.agenda.len() + topics.len() > MAX_TOPICS {
+        return Err(format!("The agenda is limited to {MAX_TOPICS} topics"));
+    }
+    state.agenda.extend(topics);
+    Ok(())
+}
+
+/// Move a topic to `index`, clamped to the end of the agenda.
+pub fn move_topic(state: &mut GameState, topic_id: &str, index: usize) -> Result<(), String> {
+    let from = position(state, topic_id)?;
+    let topic = state.agenda.remove(from);
+    let index = index.min(state.agenda.len());
+    state.agenda.insert(index, topic);
+    Ok(())
+}
+
+pub fn remove_topic(state: &mut GameState, topic_id: &str) -> Result<(), String> {
+    let index = position(state, topic_id)?;
+    state.agenda.remove(index);
+    Ok(())
+}
+
+/// Make the first agenda topic the current one. With an empty agenda the
+/// next round has no topic.
+pub fn advance(state: &mut GameState) {
+    state.current_topic = if state.agenda.is_empty() {
+        None
+    } else {
+        Some(state.agenda.remove(0))
+    };
+}
+
+/// Every delegate's vote in the current round, with non-voters as `None`.
+pub fn vote_records(state: &GameState, overflow_index: usize) -> Vec<VoteRecord> {
+    let cards = state.voting_sequence.cards();
+    state
+        .players
+        .iter()
+        .filter(|p| p.player_id < overflow_index)
+        .map(|p| {
+            let value = p.value;
+            let label = value.and_then(|vote| match vote.card() {
+                Some(card) => cards
+                    .iter()
+                    .find(|c| c.value == card)
+                    .map(|c| c.label.clone()),
+                None => vote.special_label().map(str::to_string),
+            });
+            VoteRecord {
+                player_id: p.player_id,
+                player_name: p.player_name.clone(),
+                value,
+                label,
+            }
+        })
+        .collect()
+}
+
+/// Append the current, revealed round to the room's history.
+pub fn record_round(state: &mut GameState, overflow_index: usize, completed_at: u64) {
+    let record = RoundRecord {
+        round: state.round,
+        topic: state.current_topic.clone(),
+        votes: vote_records(state, overflow_index),
+        summary: stats::summarize_round(state, overflow_index),
+        started_at: state.round_started_at,
+        revealed_at: state.revealed_at.unwrap_or(completed_at),
+        completed_at,
+    };
+    Arc::make_mut(&mut state.history).push(record);
+}
+
+fn position(state: &GameState, topic_id: &str) -> Result<usize, String> {
+    state
+        .agenda
+        .iter()
+        .position(|t| t.id == topic_id)
+        .ok_or_else(|| format!("No topic with ID {topic_id:?} on the agenda"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::{PlayerState, Vote};
+
+    fn agenda_of(titles: &[&str]) -> GameState {
+        let mut state = GameState::default();
+        for title in titles {
+            add_topic(&mut state, new_topic(title, None).unwrap()).unwrap();
+        }
+        state
+    }
+
+    fn titles(state: &GameState) -> Vec<&str> {
+        state.agenda.iter().map(|t| t.title.as_str()).collect()
+    }
+
+    // ── Topics ───────────────────────────────────────────────────────────────
+
+    /// Rule: titles are trimmed and must be non-empty; links must be http(s).
+    #[test]
+    fn test_new_topic_validation() {
+        let topic = new_topic("  Login page ", Some("https://tracker/1")).unwrap();
+        assert_eq!(topic.title, "Login page");
+        assert_eq!(topic.link.as_deref(), Some("https://tracker/1"));
+        assert!(new_topic("   ", None).is_err());
+        assert!(new_topic("x", Some("javascript:alert(1)")).is_err());
+        assert!(new_topic(&"x".repeat(MAX_TITLE_LEN + 1), None).is_err());
+        assert_eq!(new_topic("x", Some("  ")).unwrap().link, None);
+    }
+
+    /// Rule: topics can be moved anywhere in the agenda; indexes past the end
+    /// move the topic to the back.
+    #[test]
+    fn test_move_topic_reorders_agenda() {
+        let mut state = agenda_of(&["a", "b", "c"]);
+        let c = state.agenda[2].id.clone();
+        move_topic(&mut state, &c, 0).unwrap();
+        assert_eq!(titles(&state), ["c", "a", "b"]);
+        move_topic(&mut state, &c, 99).unwrap();
+        assert_eq!(titles(&state), ["a", "b", "c"]);
+        assert!(move_topic(&mut state, "missing", 0).is_err());
+    }
+
+    /// Rule: advancing pops the head of the agenda into the current topic.
+    #[test]
+    fn test_advance_pops_agenda() {
+        let mut state = agenda_of(&["a", "b"]);
+        advance(&mut state);
+        assert_eq!(state.current_topic.as_ref().unwrap().title, "a");
+        assert_eq!(titles(&state), ["b"]);
+        advance(&mut state);
+        advance(&mut state);
+        assert_eq!(state.current_topic, None);
+    }
+
+    // ── History ──────────────────────────────────────────────────────────────
+
+    /// Rule: a recorded round keeps the topic and every delegate's vote, with
+    /// non-voters recorded as `None` and spectators left out.
+    #[test]
+    fn test_record_round_captures_votes() {
+        let mut state = agenda_of(&["a"]);
+        advance(&mut state);
+        state.players = vec![
+            PlayerState {
+                player_id: 0,
+                player_name: "Ana".to_string(),
+                value: Some(Vote::Card(5)),
+                ..Default::default()
+            },
+            PlayerState {
+                player_id: 1,
+                player_name: "Ben".to_string(),
+                value: None,
+                ..Default::default()
+            },
+            PlayerState {
+                player_id: 100,
+                value: Some(Vote::Card(8)),
+                ..Default::default()
+            },
+        ];
+        state.round_started_at = 10;
+        state.revealed_at = Some(20);
+
+        record_round(&mut state, 100, 30);
+
+        let record = &state.history[0];
+        assert_eq!(record.topic.as_ref().unwrap().title, "a");
+        assert_eq!(record.votes.len(), 2);
+        assert_eq!(record.votes[0].label.as_deref(), Some("5"));
+        assert_eq!(record.votes[1].value, None);
+        assert_eq!(record.summary.vote_count, 1);
+        assert_eq!(
+            (record.started_at, record.revealed_at, record.completed_at),
+            (10, 20, 30)
+        );
+    }
+}
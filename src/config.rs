@@ -0,0 +1,368 @@
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+/// Server settings.
+///
+/// Values are resolved from, in order of precedence: CLI flags, environment
+/// variables, an optional TOML file, and finally the defaults below.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub port: u16,
+    pub bind_address: IpAddr,
+    /// Directory holding `index.html`, `game.js`, `style.css` and `img/`.
+    pub client_dir: PathBuf,
+    /// Number of delegate seats per room. The bundled client renders at most
+    /// 12 seats.
+    pub max_room_size: usize,
+    /// First player ID handed to spectators, at most 10000.
+    pub overflow_index: usize,
+    pub ping_interval_secs: u64,
+    /// Silence after which a player is flagged as away.
+    pub away_after_secs: u64,
+    /// Silence after which a player is evicted from the room.
+    pub stale_timeout_secs: u64,
+    /// How long a disconnected player's seat is held for them to resume.
+    pub resume_grace_secs: u64,
+    /// How long a room may stay empty before it is reaped.
+    pub room_idle_ttl_secs: u64,
+    /// Capacity of each room's broadcast channel.
+    pub channel_capacity: usize,
+    /// File rooms are saved to and restored from. Without one, rooms only
+    /// live in memory.
+    pub state_file: Option<PathBuf>,
+    /// How often rooms are saved to the state file.
+    pub snapshot_interval_secs: u64,
+    /// How long a shutdown waits, in total, for clients to disconnect and the
+    /// server to stop. The state file is saved either way.
+    pub shutdown_timeout_secs: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            port: 3000,
+            bind_address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            client_dir: PathBuf::from("./client"),
+            max_room_size: 12,
+            overflow_index: 100,
+            ping_interval_secs: 7,
+            away_after_secs: 15,
+            stale_timeout_secs: 60,
+            resume_grace_secs: 30,
+            room_idle_ttl_secs: 300,
+            channel_capacity: 255,
+            state_file: None,
+            snapshot_interval_secs: 30,
+            shutdown_timeout_secs: 5,
+        }
+    }
+}
+
+#[derive(Debug, Parser)]
+#[command(name = "modelun", about = "Model UN consensus-building server")]
+struct Cli {
+    /// Path to a TOML configuration file.
+    #[arg(long, env = "MODELUN_CONFIG")]
+    config: Option<PathBuf>,
+    /// Port to listen on.
+    #[arg(long, env = "MODELUN_PORT")]
+    port: Option<u16>,
+    /// Address to bind to.
+    #[arg(long, env = "MODELUN_BIND_ADDRESS")]
+    bind_address: Option<IpAddr>,
+    /// Directory holding the browser client.
+    #[arg(long, env = "MODELUN_CLIENT_DIR")]
+    client_dir: Option<PathBuf>,
+    /// Number of delegate seats per room (1-12).
+    #[arg(long, env = "MODELUN_MAX_ROOM_SIZE")]
+    max_room_size: Option<usize>,
+    /// First player ID handed to spectators, at most 10000.
+    #[arg(long, env = "MODELUN_OVERFLOW_INDEX")]
+    overflow_index: Option<usize>,
+    /// Seconds between pings to each client.
+    #[arg(long, env = "MODELUN_PING_INTERVAL_SECS")]
+    ping_interval_secs: Option<u64>,
+    /// Seconds of silence before a player is flagged as away.
+    #[arg(long, env = "MODELUN_AWAY_AFTER_SECS")]
+    away_after_secs: Option<u64>,
+    /// Seconds of silence before a player is evicted.
+    #[arg(long, env = "MODELUN_STALE_TIMEOUT_SECS")]
+    stale_timeout_secs: Option<u64>,
+    /// Seconds a disconnected player's seat is held for them.
+    #[arg(long, env = "MODELUN_RESUME_GRACE_SECS")]
+    resume_grace_secs: Option<u64>,
+    /// Seconds an empty room is kept before it is reaped.
+    #[arg(long, env = "MODELUN_ROOM_IDLE_TTL_SECS")]
+    room_idle_ttl_secs: Option<u64>,
+    /// Capacity of each room's broadcast channel.
+    #[arg(long, env = "MODELUN_CHANNEL_CAPACITY")]
+    channel_capacity: Option<usize>,
+    /// File to save rooms to and restore them from.
+    #[arg(long, env = "MODELUN_STATE_FILE")]
+    state_file: Option<PathBuf>,
+    /// Seconds between saves of the state file.
+    #[arg(long, env = "MODELUN_SNAPSHOT_INTERVAL_SECS")]
+    snapshot_interval_secs: Option<u64>,
+    /// Seconds a shutdown waits for clients to disconnect.
+    #[arg(long, env = "MODELUN_SHUTDOWN_TIMEOUT_SECS")]
+    shutdown_timeout_secs: Option<u64>,
+}
+
+impl Config {
+    /// Load the configuration from the process arguments and environment.
+    ///
+    /// `--help` and malformed flags are handled by clap, which exits the
+    /// process.
+    pub fn load() -> Result<Self, String> {
+        Self::from_cli(Cli::parse())
+    }
+
+    /// Load the configuration from an explicit argument list. The first item
+    /// is the program name, as with `std::env::args`.
+    pub fn from_args<I, T>(args: I) -> Result<Self, String>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<std::ffi::OsString> + Clone,
+    {
+        Self::from_cli(Cli::try_parse_from(args).map_err(|e| e.to_string())?)
+    }
+
+    pub fn from_toml_str(contents: &str) -> Result<Self, String> {
+        toml::from_str(contents).map_err(|e| format!("Invalid configuration: {e}"))
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {e}", path.display()))?;
+        Self::from_toml_str(&contents)
+    }
+
+    fn from_cli(cli: Cli) -> Result<Self, String> {
+        let mut config = match &cli.config {
+            Some(path) => Self::from_file(path)?,
+            None => Config::default(),
+        };
+
+        if let Some(port) = cli.port {
+            config.port = port;
+        }
+        if let Some(bind_address) = cli.bind_address {
+            config.bind_address = bind_address;
+        }
+        if let Some(client_dir) = cli.client_dir {
+            config.client_dir = client_dir;
+        }
+        if let Some(max_room_size) = cli.max_room_size {
+            config.max_room_size = max_room_size;
+        }
+        if let Some(overflow_index) = cli.overflow_index {
+            config.overflow_index = overflow_index;
+        }
+        if let Some(ping_interval_secs) = cli.ping_interval_secs {
+            config.ping_interval_secs = ping_interval_secs;
+        }
+        if let Some(away_after_secs) = cli.away_after_secs {
+            config.away_after_secs = away_after_secs;
+        }
+        if let Some(stale_timeout_secs) = cli.stale_timeout_secs {
+            config.stale_timeout_secs = stale_timeout_secs;
+        }
+        if let Some(resume_grace_secs) = cli.resume_grace_secs {
+            config.resume_grace_secs = resume_grace_secs;
+        }
+        if let Some(room_idle_ttl_secs) = cli.room_idle_ttl_secs {
+            config.room_idle_ttl_secs = room_idle_ttl_secs;
+        }
+        if let Some(channel_capacity) = cli.channel_capacity {
+            config.channel_capacity = channel_capacity;
+        }
+        if let Some(state_file) = cli.state_file {
+            config.state_file = Some(state_file);
+        }
+        if let Some(snapshot_interval_secs) = cli.snapshot_interval_secs {
+            config.snapshot_interval_secs = snapshot_interval_secs;
+        }
+        if let Some(shutdown_timeout_secs) = cli.shutdown_timeout_secs {
+            config.shutdown_timeout_secs = shutdown_timeout_secs;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=12).contains(&self.max_room_size) {
+            return Err(format!(
+                "max_room_size must be between 1 and 12, got {}",
+                self.max_room_size
+            ));
+        }
+        if self.overflow_index < self.max_room_size {
+            return Err(format!(
+                "overflow_index ({}) must not be below max_room_size ({})",
+                self.overflow_index, self.max_room_size
+            ));
+        }
+        // Spectator IDs count up from here, so leave them room to do so.
+        if self.overflow_index > 10_000 {
+            return Err(format!(
+                "overflow_index must not be above 10000, got {}",
+                self.overflow_index
+            ));
+        }
+        if self.ping_interval_secs == 0 {
+            return Err("ping_interval_secs must be greater than zero".to_string());
+        }
+        if self.away_after_secs == 0 || self.stale_timeout_secs == 0 {
+            return Err(
+                "away_after_secs and stale_timeout_secs must be greater than zero".to_string(),
+            );
+        }
+        // Both are checked on ping ticks, so shorter ones fire on the first.
+        if self.away_after_secs <= self.ping_interval_secs {
+            return Err(format!(
+                "away_after_secs ({}) must be greater than ping_interval_secs ({})",
+                self.away_after_secs, self.ping_interval_secs
+            ));
+        }
+        if self.stale_timeout_secs <= self.away_after_secs {
+            return Err(format!(
+                "stale_timeout_secs ({}) must be greater than away_after_secs ({})",
+                self.stale_timeout_secs, self.away_after_secs
+            ));
+        }
+        if self.channel_capacity == 0 {
+            return Err("channel_capacity must be greater than zero".to_string());
+        }
+        if self.snapshot_interval_secs == 0 {
+            return Err("snapshot_interval_secs must be greater than zero".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn ping_interval(&self) -> Duration {
+        Duration::from_secs(self.ping_interval_secs)
+    }
+
+    pub fn away_after(&self) -> Duration {
+        Duration::from_secs(self.away_after_secs)
+    }
+
+    pub fn stale_timeout(&self) -> Duration {
+        Duration::from_secs(self.stale_timeout_secs)
+    }
+
+    pub fn resume_grace(&self) -> Duration {
+        Duration::from_secs(self.resume_grace_secs)
+    }
+
+    pub fn room_idle_ttl(&self) -> Duration {
+        Duration::from_secs(self.room_idle_ttl_secs)
+    }
+
+    pub fn snapshot_interval(&self) -> Duration {
+        Duration::from_secs(self.snapshot_interval_secs)
+    }
+
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.shutdown_timeout_secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// With no flags and no file, the defaults match the historical
+    /// hardcoded values.
+    #[test]
+    fn test_defaults_without_arguments() {
+        let config = Config::from_args(["modelun"]).unwrap();
+        assert_eq!(config, Config::default());
+        assert_eq!(config.port, 3000);
+        assert_eq!(config.max_room_size, 12);
+        assert_eq!(config.overflow_index, 100);
+    }
+
+    /// Keys missing from a TOML file fall back to their defaults.
+    #[test]
+    fn test_toml_overrides_only_given_keys() {
+        let config = Config::from_toml_str("port = 8080\nmax_room_size = 8\n").unwrap();
+        assert_eq!(config.port, 8080);
+        assert_eq!(config.max_room_size, 8);
+        assert_eq!(config.ping_interval_secs, 7);
+    }
+
+    /// Unknown keys are rejected so typos do not silently fall back to the
+    /// defaults.
+    #[test]
+    fn test_toml_rejects_unknown_keys() {
+        assert!(Config::from_toml_str("prot = 8080\n").is_err());
+    }
+
+    /// CLI flags take precedence over the configuration file.
+    #[test]
+    fn test_cli_flags_override_file() {
+        let path = std::env::temp_dir().join(format!("modelun-test-{}.toml", std::process::id()));
+        std::fs::write(&path, "port = 8080\nstale_timeout_secs = 90\n").unwrap();
+
+        let config = Config::from_args([
+            "modelun",
+            "--config",
+            path.to_str().unwrap(),
+            "--port",
+            "9090",
+        ])
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.port, 9090);
+        assert_eq!(config.stale_timeout_secs, 90);
+    }
+
+    /// Settings the bundled client cannot render are rejected.
+    #[test]
+    fn test_validate_rejects_oversized_room() {
+        assert!(Config::from_args(["modelun", "--max-room-size", "13"]).is_err());
+        assert!(Config::from_args(["modelun", "--max-room-size", "0"]).is_err());
+    }
+
+    /// Spectator IDs start past the seats, but not so high that there is no
+    /// room left to count up.
+    #[test]
+    fn test_validate_rejects_out_of_range_overflow_index() {
+        assert!(Config::from_args(["modelun", "--overflow-index", "11"]).is_err());
+        assert!(Config::from_args(["modelun", "--overflow-index", "10000"]).is_ok());
+        assert!(Config::from_args(["modelun", "--overflow-index", "10001"]).is_err());
+        let huge = usize::MAX.to_string();
+        assert!(Config::from_args(["modelun", "--overflow-index", &huge]).is_err());
+    }
+
+    /// Presence timeouts of zero are rejected.
+    #[test]
+    fn test_validate_rejects_zero_presence_timeouts() {
+        assert!(Config::from_args(["modelun", "--away-after-secs", "0"]).is_err());
+        assert!(Config::from_args(["modelun", "--stale-timeout-secs", "0"]).is_err());
+    }
+
+    /// A player cannot be flagged away before they have missed a ping.
+    #[test]
+    fn test_validate_rejects_away_within_ping_interval() {
+        let args = ["modelun", "--ping-interval-secs", "10", "--away-after-secs"];
+        assert!(Config::from_args([&args[..], &["10"]].concat()).is_err());
+        assert!(Config::from_args([&args[..], &["11"]].concat()).is_ok());
+    }
+
+    /// A player is flagged away before they are evicted.
+    #[test]
+    fn test_validate_rejects_stale_before_away() {
+        let args = ["modelun", "--away-after-secs", "20", "--stale-timeout-secs"];
+        assert!(Config::from_args([&args[..], &["20"]].concat()).is_err());
+        assert!(Config::from_args([&args[..], &["21"]].concat()).is_ok());
+    }
+}
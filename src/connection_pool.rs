@@ -4,8 +4,12 @@ use std::sync::Arc;
 use tokio::sync::{RwLock, mpsc};
 use warp::ws::Message;
 
+use crate::structs::ServerMessage;
+
+/// The outbox of every open connection, by room and connection ID, so the
+/// server can push messages to a client outside of room broadcasts.
 pub struct ConnectionPool {
-    connections: RwLock<HashMap<String, Vec<mpsc::Sender<Message>>>>,
+    connections: RwLock<HashMap<String, HashMap<String, mpsc::Sender<Message>>>>,
 }
 
 impl ConnectionPool {
@@ -15,19 +19,19 @@ impl ConnectionPool {
         })
     }
 
-    pub async fn add(&self, room: String, sender: mpsc::Sender<Message>) {
+    pub async fn add(&self, room: String, connection_id: String, sender: mpsc::Sender<Message>) {
         self.connections
             .write()
             .await
             .entry(room)
             .or_default()
-            .push(sender);
+            .insert(connection_id, sender);
     }
 
-    pub async fn remove(&self, room: &str, sender: &mpsc::Sender<Message>) {
+    pub async fn remove(&self, room: &str, connection_id: &str) {
         let mut guard = self.connections.write().await;
         let is_empty = if let Some(senders) = guard.get_mut(room) {
-            senders.retain(|s| !s.same_channel(sender));
+            senders.remove(connection_id);
             senders.is_empty()
         } else {
             false
@@ -36,6 +40,76 @@ impl ConnectionPool {
             guard.remove(room);
         }
     }
+
+    /// Queue a frame for one connection. Returns false if the connection is
+    /// not in the pool or its outbox is gone.
+    pub async fn send(&self, room: &str, connection_id: &str, message: Message) -> bool {
+        let sender = self
+            .connections
+            .read()
+            .await
+            .get(room)
+            .and_then(|senders| senders.get(connection_id))
+            .cloned();
+        match sender {
+            Some(sender) => sender.send(message).await.is_ok(),
+            None => false,
+        }
+    }
+
+    /// Push a message to one connection.
+    pub async fn send_to(&self, room: &str, connection_id: &str, message: &ServerMessage) -> bool {
+        let text = serde_json::to_string(message).unwrap();
+        self.send(room, connection_id, Message::text(text)).await
+    }
+
+    /// Push `message` to every connection in every room, then close them all.
+    /// Never waits on an outbox: a connection too far behind to take both
+    /// frames is skipped. Returns how many connections were asked to close.
+    pub async fn close_all(&self, message: &ServerMessage) -> usize {
+        let text = serde_json::to_string(message).unwrap();
+        let senders: Vec<_> = self
+            .connections
+            .read()
+            .await
+            .values()
+            .flat_map(|senders| senders.values().cloned())
+            .collect();
+        let mut closed = 0;
+        for sender in senders {
+            if sender.try_send(Message::text(text.clone())).is_ok()
+                && sender.try_send(Message::close()).is_ok()
+            {
+                closed += 1;
+            }
+        }
+        closed
+    }
+
+    /// Whether no connection is open.
+    pub async fn is_empty(&self) -> bool {
+        self.connections.read().await.is_empty()
+    }
+
+    /// Push a message to every connection in a room. Returns how many
+    /// connections it was queued for.
+    pub async fn send_to_room(&self, room: &str, message: &ServerMessage) -> usize {
+        let text = serde_json::to_string(message).unwrap();
+        let senders: Vec<_> = self
+            .connections
+            .read()
+            .await
+            .get(room)
+            .map(|senders| senders.values().cloned().collect())
+            .unwrap_or_default();
+        let mut sent = 0;
+        for sender in senders {
+            if sender.send(Message::text(text.clone())).await.is_ok() {
+                sent += 1;
+            }
+        }
+        sent
+    }
 }
 
 #[cfg(test)]
@@ -49,7 +123,8 @@ mod tests {
     async fn test_add_connection_to_room() {
         let pool = ConnectionPool::new();
         let (tx, _rx) = mpsc::channel::<Message>(1);
-        pool.add("room-add".to_string(), tx).await;
+        pool.add("room-add".to_string(), "conn-a".to_string(), tx)
+            .await;
         let guard = pool.connections.read().await;
         assert!(guard.contains_key("room-add"));
         assert_eq!(guard["room-add"].len(), 1);
@@ -61,8 +136,10 @@ mod tests {
         let pool = ConnectionPool::new();
         let (tx1, _rx1) = mpsc::channel::<Message>(1);
         let (tx2, _rx2) = mpsc::channel::<Message>(1);
-        pool.add("room-multi".to_string(), tx1).await;
-        pool.add("room-multi".to_string(), tx2).await;
+        pool.add("room-multi".to_string(), "conn-a".to_string(), tx1)
+            .await;
+        pool.add("room-multi".to_string(), "conn-b".to_string(), tx2)
+            .await;
         let guard = pool.connections.read().await;
         assert_eq!(guard["room-multi"].len(), 2);
     }
@@ -72,8 +149,9 @@ mod tests {
     async fn test_remove_last_connection_cleans_up_room() {
         let pool = ConnectionPool::new();
         let (tx, _rx) = mpsc::channel::<Message>(1);
-        pool.add("room-cleanup".to_string(), tx.clone()).await;
-        pool.remove("room-cleanup", &tx).await;
+        pool.add("room-cleanup".to_string(), "conn-a".to_string(), tx)
+            .await;
+        pool.remove("room-cleanup", "conn-a").await;
         let guard = pool.connections.read().await;
         assert!(!guard.contains_key("room-cleanup"));
     }
@@ -84,9 +162,11 @@ mod tests {
         let pool = ConnectionPool::new();
         let (tx1, _rx1) = mpsc::channel::<Message>(1);
         let (tx2, _rx2) = mpsc::channel::<Message>(1);
-        pool.add("room-partial".to_string(), tx1.clone()).await;
-        pool.add("room-partial".to_string(), tx2.clone()).await;
-        pool.remove("room-partial", &tx1).await;
+        pool.add("room-partial".to_string(), "conn-a".to_string(), tx1)
+            .await;
+        pool.add("room-partial".to_string(), "conn-b".to_string(), tx2)
+            .await;
+        pool.remove("room-partial", "conn-a").await;
         let guard = pool.connections.read().await;
         assert_eq!(guard["room-partial"].len(), 1);
     }
@@ -96,7 +176,93 @@ mod tests {
     #[tokio::test]
     async fn test_remove_from_nonexistent_room_is_noop() {
         let pool = ConnectionPool::new();
-        let (tx, _rx) = mpsc::channel::<Message>(1);
-        pool.remove("nonexistent-room", &tx).await;
+        pool.remove("nonexistent-room", "conn-a").await;
+    }
+
+    /// A targeted push reaches only the addressed connection.
+    #[tokio::test]
+    async fn test_send_to_one_connection() {
+        let pool = ConnectionPool::new();
+        let (tx1, mut rx1) = mpsc::channel::<Message>(1);
+        let (tx2, mut rx2) = mpsc::channel::<Message>(1);
+        pool.add("room-send".to_string(), "conn-a".to_string(), tx1)
+            .await;
+        pool.add("room-send".to_string(), "conn-b".to_string(), tx2)
+            .await;
+
+        let message = ServerMessage::Ping { data: 1 };
+        assert!(pool.send_to("room-send", "conn-b", &message).await);
+        assert!(!pool.send_to("room-send", "conn-c", &message).await);
+
+        assert!(rx1.try_recv().is_err());
+        let received = rx2.try_recv().unwrap();
+        assert_eq!(received.to_str().unwrap(), r#"{"type":"Ping","data":1}"#);
+    }
+
+    /// A room push reaches every live connection in that room only.
+    #[tokio::test]
+    async fn test_send_to_room() {
+        let pool = ConnectionPool::new();
+        let (tx1, mut rx1) = mpsc::channel::<Message>(1);
+        let (tx2, rx2) = mpsc::channel::<Message>(1);
+        let (tx3, mut rx3) = mpsc::channel::<Message>(1);
+        pool.add("room-all".to_string(), "conn-a".to_string(), tx1)
+            .await;
+        pool.add("room-all".to_string(), "conn-b".to_string(), tx2)
+            .await;
+        pool.add("room-other".to_string(), "conn-c".to_string(), tx3)
+            .await;
+        drop(rx2);
+
+        let sent = pool
+            .send_to_room("room-all", &ServerMessage::Ping { data: 0 })
+            .await;
+        assert_eq!(sent, 1, "closed outboxes are skipped");
+        assert!(rx1.try_recv().is_ok());
+        assert!(rx3.try_recv().is_err());
+    }
+
+    /// Closing all connections sends each one the message then a close frame,
+    /// across rooms.
+    #[tokio::test]
+    async fn test_close_all() {
+        let pool = ConnectionPool::new();
+        let (tx1, mut rx1) = mpsc::channel::<Message>(2);
+        let (tx2, mut rx2) = mpsc::channel::<Message>(2);
+        pool.add("room-a".to_string(), "conn-a".to_string(), tx1)
+            .await;
+        pool.add("room-b".to_string(), "conn-b".to_string(), tx2)
+            .await;
+        assert!(!pool.is_empty().await);
+
+        let closed = pool.close_all(&ServerMessage::Ping { data: 2 }).await;
+        assert_eq!(closed, 2);
+        for rx in [&mut rx1, &mut rx2] {
+            let message = rx.try_recv().unwrap();
+            assert_eq!(message.to_str().unwrap(), r#"{"type":"Ping","data":2}"#);
+            assert!(rx.try_recv().unwrap().is_close());
+        }
+
+        pool.remove("room-a", "conn-a").await;
+        pool.remove("room-b", "conn-b").await;
+        assert!(pool.is_empty().await);
+    }
+
+    /// Closing all connections does not wait on an outbox that is full.
+    #[tokio::test]
+    async fn test_close_all_skips_full_outbox() {
+        let pool = ConnectionPool::new();
+        let (tx1, _rx1) = mpsc::channel::<Message>(1);
+        let (tx2, mut rx2) = mpsc::channel::<Message>(2);
+        tx1.try_send(Message::text("backlog")).unwrap();
+        pool.add("room-a".to_string(), "conn-a".to_string(), tx1)
+            .await;
+        pool.add("room-a".to_string(), "conn-b".to_string(), tx2)
+            .await;
+
+        let closed = pool.close_all(&ServerMessage::Ping { data: 2 }).await;
+        assert_eq!(closed, 1);
+        assert!(rx2.try_recv().is_ok());
+        assert!(rx2.try_recv().unwrap().is_close());
     }
 }
@@ -2,27 +2,20 @@ use std::sync::atomic::{AtomicUsize, Ordering};
 
 use log::info;
 
+#[derive(Default)]
 pub struct Counter {
     slow_index: AtomicUsize,
     fast_index: AtomicUsize,
 }
 
 impl Counter {
-    fn new() -> Self {
+    pub fn new() -> Self {
         Counter {
             fast_index: AtomicUsize::new(0),
             slow_index: AtomicUsize::new(0),
         }
     }
 
-    pub fn instance() -> &'static Counter {
-        use lazy_static::lazy_static;
-        lazy_static! {
-            static ref COUNTER: Counter = Counter::new();
-        }
-        &COUNTER
-    }
-
     pub fn get_fast_index(&self, fast_array_size: usize) -> usize {
         let current = self.fast_index.fetch_add(1, Ordering::SeqCst);
         if current >= fast_array_size.saturating_sub(1) {
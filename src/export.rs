@@ -0,0 +1,325 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use warp::http::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+use warp::http::{Response, StatusCode};
+
+use crate::agenda;
+use crate::game::{self, Game};
+use crate::room_store::RoomStore;
+use crate::structs::{GameState, RoundRecord, Topic, VoteRecord, VotingSequence};
+
+/// Everything worth keeping from a room once the meeting is over.
+#[derive(Debug, Serialize)]
+pub struct RoomExport {
+    pub room: String,
+    pub exported_at: u64,
+    pub voting_sequence: VotingSequence,
+    pub history: Vec<RoundRecord>,
+    pub current: CurrentRound,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CurrentRound {
+    pub round: u64,
+    pub topic: Option<Topic>,
+    pub revealed: bool,
+    /// Votes are only filled in once the round is revealed.
+    pub votes: Vec<VoteRecord>,
+}
+
+impl RoomExport {
+    pub fn new(room: &str, state: &GameState, overflow_index: usize) -> Self {
+        let mut votes = agenda::vote_records(state, overflow_index);
+        if !state.all_revealed {
+            for vote in &mut votes {
+                vote.value = None;
+                vote.label = None;
+            }
+        }
+
+        RoomExport {
+            room: room.to_string(),
+            exported_at: agenda::now(),
+            voting_sequence: state.voting_sequence.clone(),
+            history: state.history.to_vec(),
+            current: CurrentRound {
+                round: state.round,
+                topic: state.current_topic.clone(),
+                revealed: state.all_revealed,
+                votes,
+            },
+        }
+    }
+
+    /// Completed rounds followed by the current one if it has been revealed,
+    /// with the room's own round numbers.
+    fn rounds(&self) -> impl Iterator<Item = (u64, Option<&Topic>, &[VoteRecord])> {
+        let current = self.current.revealed.then_some((
+            self.current.round,
+            self.current.topic.as_ref(),
+            self.current.votes.as_slice(),
+        ));
+        self.history
+            .iter()
+            .map(|r| (r.round, r.topic.as_ref(), r.votes.as_slice()))
+            .chain(current)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// One row per delegate per round.
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("round,key,topic,link,player_id,player,vote\r\n");
+        for (round, topic, votes) in self.rounds() {
+            let key = topic.and_then(|t| t.key.as_deref()).unwrap_or("");
+            let title = topic.map_or("", |t| t.title.as_str());
+            let link = topic.and_then(|t| t.link.as_deref()).unwrap_or("");
+            for vote in votes {
+                let fields = [
+                    round.to_string(),
+                    csv_field(key),
+                    csv_field(title),
+                    csv_field(link),
+                    vote.player_id.to_string(),
+                    csv_field(&vote.player_name),
+                    csv_field(vote.label.as_deref().unwrap_or("")),
+                ];
+                out.push_str(&fields.join(","));
+                out.push_str("\r\n");
+            }
+        }
+        out
+    }
+
+    /// A heading and a vote table per round.
+    pub fn to_markdown(&self) -> String {
+        let mut out = format!("# {}\n", markdown_text(&self.room));
+        for (round, topic, votes) in self.rounds() {
+            out.push_str(&format!("\n## Round {round}"));
+            if let Some(topic) = topic {
+                out.push_str(": ");
+                if let Some(key) = &topic.key {
+                    out.push_str(&format!("{} ", markdown_text(key)));
+                }
+                out.push_str(&markdown_text(&topic.title));
+                if let Some(link) = &topic.link {
+                    let link = link.replace('<', "%3C").replace('>', "%3E");
+                    out.push_str(&format!(" (<{link}>)"));
+                }
+            }
+            out.push_str("\n\n| Delegate | Vote |\n| --- | --- |\n");
+            for vote in votes {
+                out.push_str(&format!(
+                    "| {} | {} |\n",
+                    markdown_text(&vote.player_name),
+                    markdown_text(vote.label.as_deref().unwrap_or("–")),
+                ));
+            }
+        }
+        out
+    }
+}
+
+/// Quote a CSV field when needed, and defuse values a spreadsheet would
+/// otherwise evaluate as a formula.
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{value}")
+    } else {
+        value.to_string()
+    };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+/// Escape text so it renders literally, including inside a table cell.
+fn markdown_text(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(
+            c,
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#' | '|' | '!'
+        ) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct ExportQuery {
+    /// Needed to export a locked room.
+    pub passphrase: Option<String>,
+}
+
+/// Serve `GET /rooms/{room}/export.{csv,json,md}`.
+pub async fn handle_export<S: RoomStore>(
+    room: String,
+    file: String,
+    query: ExportQuery,
+    game: Arc<Game<S>>,
+) -> Result<Response<String>, warp::Rejection> {
+    let (content_type, extension) = match file.as_str() {
+        "export.csv" => ("text/csv; charset=utf-8", "csv"),
+        "export.json" => ("application/json", "json"),
+        "export.md" => ("text/markdown; charset=utf-8", "md"),
+        _ => return Err(warp::reject::not_found()),
+    };
+
+    let Some(state) = game.get_room_state(&room).await else {
+        return Err(warp::reject::not_found());
+    };
+    if !game::admits(&state, query.passphrase.as_deref(), None) {
+        return Ok(Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .header(CONTENT_TYPE, "text/plain; charset=utf-8")
+            .body("This room needs a passphrase".to_string())
+            .unwrap());
+    }
+    let export = RoomExport::new(&room, &state, game.overflow_index());
+    let body = match extension {
+        "csv" => export.to_csv(),
+        "json" => export.to_json(),
+        _ => export.to_markdown(),
+    };
+
+    let file_stem: String = room
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+        .collect();
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, content_type)
+        .header(
+            CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{file_stem}-export.{extension}\""),
+        )
+        .body(body)
+        .unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::{PlayerState, Vote};
+
+    fn player(player_id: usize, name: &str, value: u8) -> PlayerState {
+        PlayerState {
+            player_id,
+            player_name: name.to_string(),
+            value: Some(Vote::Card(value)),
+            ..Default::default()
+        }
+    }
+
+    fn finished_room() -> GameState {
+        let mut state = GameState {
+            round: 1,
+            players: vec![player(0, "Ana", 5), player(1, "Ben", 8)],
+            current_topic: Some(Topic {
+                id: "t1".to_string(),
+                title: "Login, page".to_string(),
+                link: Some("https://tracker/1".to_string()),
+                key: Some("MUN-1".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        agenda::record_round(&mut state, 100, 30);
+        state.round = 2;
+        state.current_topic = None;
+        state.players = vec![player(0, "Ana", 3)];
+        state
+    }
+
+    // ── Current round ────────────────────────────────────────────────────────
+
+    /// Rule: the current round's votes are only exported once revealed.
+    #[test]
+    fn test_unrevealed_votes_are_not_exported() {
+        let state = finished_room();
+        let export = RoomExport::new("r", &state, 100);
+        assert!(export.current.votes.iter().all(|v| v.value.is_none()));
+        assert!(!export.to_csv().contains(",3\r\n"));
+
+        let revealed = GameState {
+            all_revealed: true,
+            ..state
+        };
+        let export = RoomExport::new("r", &revealed, 100);
+        assert_eq!(export.current.votes[0].value, Some(Vote::Card(3)));
+        assert!(export.to_csv().ends_with("2,,,,0,Ana,3\r\n"));
+    }
+
+    // ── Formats ──────────────────────────────────────────────────────────────
+
+    /// Rule: CSV has a header and one quoted-as-needed row per vote.
+    #[test]
+    fn test_csv_rows() {
+        let csv = RoomExport::new("r", &finished_room(), 100).to_csv();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "round,key,topic,link,player_id,player,vote",
+                "1,MUN-1,\"Login, page\",https://tracker/1,0,Ana,5",
+                "1,MUN-1,\"Login, page\",https://tracker/1,1,Ben,8",
+            ]
+        );
+    }
+
+    /// Rule: CSV fields that a spreadsheet would treat as formulas are
+    /// prefixed so they stay plain text.
+    #[test]
+    fn test_csv_defuses_formulas() {
+        assert_eq!(csv_field("=SUM(A1)"), "'=SUM(A1)");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    /// Rule: rounds keep the room's numbering, so a round abandoned before
+    /// its reveal leaves a gap in every format.
+    #[test]
+    fn test_abandoned_round_keeps_numbering() {
+        let state = GameState {
+            round: 3,
+            all_revealed: true,
+            ..finished_room()
+        };
+        let export = RoomExport::new("r", &state, 100);
+        assert!(export.to_csv().ends_with("3,,,,0,Ana,3\r\n"));
+        let md = export.to_markdown();
+        assert!(md.contains("## Round 1:"));
+        assert!(md.contains("## Round 3\n"));
+        assert!(!md.contains("## Round 2"));
+        let value: serde_json::Value = serde_json::from_str(&export.to_json()).unwrap();
+        assert_eq!(value["history"][0]["round"], 1);
+        assert_eq!(value["current"]["round"], 3);
+    }
+
+    /// Rule: Markdown has a section per round and escapes table syntax.
+    #[test]
+    fn test_markdown_tables() {
+        let mut state = finished_room();
+        Arc::make_mut(&mut state.history)[0].votes[0].player_name = "A|na".to_string();
+        let md = RoomExport::new("r", &state, 100).to_markdown();
+        assert!(md.contains("## Round 1: MUN-1 Login, page (<https://tracker/1>)"));
+        assert!(md.contains("| A\\|na | 5 |"));
+    }
+
+    /// Rule: JSON carries the full history and the current round.
+    #[test]
+    fn test_json_round_trips_history() {
+        let json = RoomExport::new("r", &finished_room(), 100).to_json();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["room"], "r");
+        assert_eq!(value["history"][0]["votes"][1]["label"], "8");
+        assert_eq!(value["current"]["revealed"], false);
+    }
+}
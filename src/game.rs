@@ -1,51 +1,116 @@
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use lazy_static::lazy_static;
-use log::{debug, info};
-use tokio::sync::{Mutex, RwLock};
+use log::{debug, info, warn};
+use tokio::sync::Mutex;
 use uuid::Uuid;
 
-use crate::SharedGameState;
+use crate::agenda;
+use crate::config::Config;
 use crate::counter::Counter;
-use crate::structs::{ClientMessage, GameState, NotifyChange, PlayerState, VotingSequence};
+use crate::import::{self, ImportError};
+use crate::passphrase::{Passphrase, Secret};
+use crate::persistence::{RoomSnapshot, SnapshotStore};
+use crate::room_store::{MemoryRoomStore, RoomStore};
+use crate::stats;
+use crate::structs::{
+    AutoReveal, Card, ClientMessage, ConnectionQuery, GameState, NotifyChange, PlayerState, Timer,
+    Vote, VotingSequence,
+};
+
+/// Whether a room is open, or the caller holds its passphrase or a
+/// resume token for one of its seats.
+pub fn admits(room_state: &GameState, passphrase: Option<&str>, resume: Option<&str>) -> bool {
+    let Some(expected) = &room_state.passphrase else {
+        return true;
+    };
+    passphrase.is_some_and(|p| expected.matches(p))
+        || resume.is_some_and(|token| {
+            room_state
+                .players
+                .iter()
+                .any(|p| !p.resume_token.is_empty() && p.resume_token == token)
+        })
+}
+
+/// Project a room snapshot for the client behind `connection_id`.
+///
+/// Until the room is revealed the recipient only sees its own vote; every
+/// other player is reduced to a `has_voted` flag so votes stay secret on
+/// the wire.
+pub fn state_for_connection(state: &GameState, connection_id: &str) -> GameState {
+    let mut view = state.clone();
+    for player in &mut view.players {
+        player.has_voted = player.value.is_some();
+        if !view.all_revealed && player.connection_id != connection_id {
+            player.value = None;
+        }
+    }
+    if let Some(timer) = &mut view.timer {
+        timer.remaining_secs = timer.ends_at.saturating_sub(agenda::now());
+    }
+    view
+}
+
+/// The earliest pending auto-reveal or timer expiry in a room.
+pub fn next_deadline(room_state: &GameState) -> Option<u64> {
+    let timer_ends_at = room_state
+        .timer
+        .as_ref()
+        .filter(|timer| !timer.expired)
+        .map(|timer| timer.ends_at);
+    match (room_state.auto_reveal_at, timer_ends_at) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
 
-pub struct Game {
-    game_state: SharedGameState,
-    counter: Arc<Mutex<&'static Counter>>,
+pub struct Game<S: RoomStore = MemoryRoomStore> {
+    rooms: S,
+    counter: Mutex<Counter>,
+    max_room_size: usize,
+    overflow_index: usize,
+    /// When each currently empty room was first seen empty by the reaper.
+    idle_rooms: Mutex<HashMap<String, Instant>>,
+    /// The deadline each room has a task waiting on.
+    scheduled_deadlines: Mutex<HashMap<String, u64>>,
 }
 
-impl Game {
-    // The Game object is a singleton
-    fn new() -> Self {
-        let game_state: SharedGameState = Arc::new(RwLock::new(HashMap::new()));
-        let counter = Arc::new(Mutex::new(Counter::instance()));
+impl<S: RoomStore> Game<S> {
+    const MAX_CUSTOM_CARDS: usize = 16;
+    const MAX_CARD_LABEL_LEN: usize = 8;
+    const MAX_AUTO_REVEAL_COUNTDOWN_SECS: u64 = 10;
+    const MAX_TIMER_SECS: u64 = 60 * 60;
 
+    pub fn with_store(config: &Config, rooms: S) -> Self {
         Game {
-            game_state,
-            counter,
+            rooms,
+            counter: Mutex::new(Counter::new()),
+            max_room_size: config.max_room_size,
+            overflow_index: config.overflow_index,
+            idle_rooms: Mutex::new(HashMap::new()),
+            scheduled_deadlines: Mutex::new(HashMap::new()),
         }
     }
 
-    const MAX_ROOM_SIZE: usize = 12;
-    const OVERFLOW_INDEX: usize = 100;
-
-    pub fn instance() -> &'static Game {
-        lazy_static! {
-            static ref GAME: Game = Game::new();
-        }
-        &GAME
+    /// A game starting from an empty store.
+    pub fn new(config: &Config) -> Arc<Self>
+    where
+        S: Default,
+    {
+        Arc::new(Self::with_store(config, S::default()))
     }
 
     fn find_player_in_waiting(&self, players: &[PlayerState]) -> Option<usize> {
         let active_count = players
             .iter()
-            .filter(|p| p.player_id < Self::OVERFLOW_INDEX)
+            .filter(|p| p.player_id < self.overflow_index)
             .count();
-        if active_count < Self::MAX_ROOM_SIZE {
+        if active_count < self.max_room_size {
             players
                 .iter()
-                .find(|player| player.player_id >= Self::OVERFLOW_INDEX)
+                .find(|player| player.player_id >= self.overflow_index && !player.benched)
                 .map(|player| player.player_id)
         } else {
             None
@@ -77,6 +142,9 @@ impl Game {
             if reset_value {
                 moved_player.value = None;
             }
+            if state.captain_id == Some(old_id) {
+                state.captain_id = Some(new_id);
+            }
 
             state.players.remove(player_index);
             state.players.push(moved_player);
@@ -94,42 +162,15 @@ impl Game {
     pub async fn remove_player(&self, room: &str, player_id: usize) {
         debug!("remove_player - Room: {}, Player ID: {}", room, player_id);
 
-        let mut state = self.game_state.write().await;
-        let room_state = state.entry(room.to_string()).or_insert(GameState {
-            players: Vec::new(),
-            all_revealed: false,
-            notify_change: NotifyChange::default(),
-            voting_sequence: VotingSequence::default(),
-        });
-
-        if let Some(index) = room_state
-            .players
-            .iter()
-            .position(|p| p.player_id == player_id)
-        {
-            room_state.players.remove(index);
+        let removed = self
+            .rooms
+            .update(room, |slot| {
+                slot.as_mut()
+                    .is_some_and(|room_state| self.remove_from_room(room_state, player_id))
+            })
+            .await;
+        if removed {
             info!("Player {} disconnected.", player_id);
-
-            let player_in_waiting = self.find_player_in_waiting(&room_state.players);
-
-            let vacant_id = player_id;
-            match player_in_waiting {
-                Some(old_id) => {
-                    if self.move_player(old_id, vacant_id, room_state, None, true) {
-                        room_state.notify_change = NotifyChange {
-                            current_id: old_id,
-                            new_id: player_id,
-                        };
-
-                        debug!("Player {} promoted to position {}", old_id, player_id);
-                    } else {
-                        room_state.notify_change = NotifyChange::default();
-                    }
-                }
-                None => {
-                    room_state.notify_change = NotifyChange::default();
-                }
-            }
         }
 
         debug!(
@@ -138,23 +179,80 @@ impl Game {
         );
     }
 
+    fn set_benched(room_state: &mut GameState, player_id: usize, benched: bool) {
+        if let Some(player) = room_state
+            .players
+            .iter_mut()
+            .find(|p| p.player_id == player_id)
+        {
+            player.benched = benched;
+        }
+    }
+
+    /// Take a player out of the room, promoting a waiting spectator into
+    /// their seat. Returns false if there was no such player.
+    fn remove_from_room(&self, room_state: &mut GameState, player_id: usize) -> bool {
+        let Some(index) = room_state
+            .players
+            .iter()
+            .position(|p| p.player_id == player_id)
+        else {
+            return false;
+        };
+        room_state.players.remove(index);
+        // Whoever is promoted into the seat does not inherit the captaincy.
+        if room_state.captain_id == Some(player_id) {
+            room_state.captain_id = None;
+        }
+
+        // Only a freed seat is backfilled; a spectator leaving frees nothing.
+        room_state.notify_changes = if player_id < self.overflow_index {
+            self.backfill_seat(room_state, player_id)
+                .into_iter()
+                .collect()
+        } else {
+            Vec::new()
+        };
+        self.refresh_captain(room_state);
+        self.refresh_auto_reveal(room_state);
+        self.refresh_round_summary(room_state);
+        true
+    }
+
+    /// Promote the first waiting spectator into the vacant seat, if there is
+    /// one, and return the move so their client can follow it.
+    fn backfill_seat(&self, room_state: &mut GameState, vacant_id: usize) -> Option<NotifyChange> {
+        let old_id = self.find_player_in_waiting(&room_state.players)?;
+        if !self.move_player(old_id, vacant_id, room_state, None, true) {
+            return None;
+        }
+        debug!("Player {} promoted to position {}", old_id, vacant_id);
+        Some(NotifyChange {
+            current_id: old_id,
+            new_id: vacant_id,
+        })
+    }
+
     /// Remove a player from a room by immutable connection ID.
     ///
     /// This decouples socket lifetime from mutable seat/player IDs.
     pub async fn remove_player_by_connection(&self, room: &str, connection_id: &str) {
-        let player_id = {
-            let state = self.game_state.read().await;
-            state.get(room).and_then(|room_state| {
-                room_state
+        let removed = self
+            .rooms
+            .update(room, |slot| {
+                let room_state = slot.as_mut()?;
+                let player_id = room_state
                     .players
                     .iter()
-                    .find(|p| p.connection_id == connection_id)
-                    .map(|p| p.player_id)
+                    .find(|p| p.connection_id == connection_id)?
+                    .player_id;
+                self.remove_from_room(room_state, player_id)
+                    .then_some(player_id)
             })
-        };
+            .await;
 
-        match player_id {
-            Some(player_id) => self.remove_player(room, player_id).await,
+        match removed {
+            Some(player_id) => info!("Player {} disconnected.", player_id),
             None => debug!(
                 "remove_player_by_connection - no player found in room {} for connection {}",
                 room, connection_id
@@ -162,26 +260,205 @@ impl Game {
         }
     }
 
+    /// Flag the player behind `connection_id` as away, or as back again.
+    ///
+    /// Returns `true` when the flag changed, so the caller knows the room
+    /// needs a fresh broadcast.
+    pub async fn set_player_away(&self, room: &str, connection_id: &str, away: bool) -> bool {
+        self.rooms
+            .update(room, |slot| {
+                let Some(player) = slot.as_mut().and_then(|room_state| {
+                    room_state
+                        .players
+                        .iter_mut()
+                        .find(|p| p.connection_id == connection_id)
+                }) else {
+                    return false;
+                };
+
+                if player.away == away {
+                    return false;
+                }
+                player.away = away;
+                info!(
+                    "Player {} is {}.",
+                    player.player_id,
+                    if away { "away" } else { "back" }
+                );
+                true
+            })
+            .await
+    }
+
+    fn empty_room() -> GameState {
+        GameState {
+            round: 1,
+            round_started_at: agenda::now(),
+            ..GameState::default()
+        }
+    }
+
     pub async fn generate_new_room(&self, room: Option<&str>) -> String {
         let room_name = match room {
             Some(room) => room.to_string(),
             None => self.random_name_generator().await,
         };
 
-        let mut game_state = self.game_state.write().await;
-        game_state.insert(
-            room_name.clone(),
-            GameState {
-                players: Vec::new(),
-                all_revealed: false,
-                notify_change: NotifyChange::default(),
-                voting_sequence: VotingSequence::default(),
-            },
-        );
+        self.rooms
+            .update(&room_name, |slot| *slot = Some(Self::empty_room()))
+            .await;
         debug!("generate_new_room - Room Name: {} - finished", room_name);
         room_name
     }
 
+    /// Remove rooms that have had no players for at least `ttl`.
+    ///
+    /// A room is only collected once it has been seen empty on an earlier
+    /// sweep and stayed empty for the whole TTL, so a room that is briefly
+    /// empty between two joins survives. Returns how many rooms were removed.
+    pub async fn reap_idle_rooms(&self, ttl: Duration) -> usize {
+        let now = Instant::now();
+        let mut idle_rooms = self.idle_rooms.lock().await;
+
+        let mut empty_rooms = Vec::new();
+        for room in self.rooms.list().await {
+            let empty = self
+                .rooms
+                .update(&room, |slot| {
+                    slot.as_ref().is_some_and(|s| s.players.is_empty())
+                })
+                .await;
+            if empty {
+                empty_rooms.push(room);
+            }
+        }
+        idle_rooms.retain(|room, _| empty_rooms.contains(room));
+        for room in empty_rooms {
+            idle_rooms.entry(room).or_insert(now);
+        }
+
+        let expired: Vec<String> = idle_rooms
+            .iter()
+            .filter(|(_, since)| now.duration_since(**since) >= ttl)
+            .map(|(room, _)| room.clone())
+            .collect();
+        let mut reaped = 0;
+        for room in &expired {
+            idle_rooms.remove(room);
+            // Someone may have joined since the sweep looked at the room.
+            let removed = self
+                .rooms
+                .update(room, |slot| {
+                    let empty = slot.as_ref().is_some_and(|s| s.players.is_empty());
+                    if empty {
+                        *slot = None;
+                    }
+                    empty
+                })
+                .await;
+            if removed {
+                reaped += 1;
+                debug!("reap_idle_rooms - removed room {}", room);
+            }
+        }
+        reaped
+    }
+
+    /// Sweep for idle rooms forever, once per `ttl`.
+    pub async fn run_room_reaper(&self, ttl: Duration) {
+        let mut interval = tokio::time::interval(ttl.max(Duration::from_secs(1)));
+        loop {
+            interval.tick().await;
+            let reaped = self.reap_idle_rooms(ttl).await;
+            if reaped > 0 {
+                info!("Reaped {} idle room(s).", reaped);
+            } else {
+                debug!("run_room_reaper - no idle rooms to reap");
+            }
+        }
+    }
+
+    /// Copy every room, with the secrets needed to bring it back after a
+    /// restart.
+    pub async fn snapshot(&self) -> HashMap<String, RoomSnapshot> {
+        let mut rooms = HashMap::new();
+        for room in self.rooms.list().await {
+            let Some(room_state) = self.rooms.get(&room).await else {
+                continue;
+            };
+            let snapshot = RoomSnapshot {
+                passphrase: room_state.passphrase.clone(),
+                resume_tokens: room_state
+                    .players
+                    .iter()
+                    .map(|p| (p.player_id, p.resume_token.clone()))
+                    .collect(),
+                state: room_state,
+            };
+            rooms.insert(room, snapshot);
+        }
+        rooms
+    }
+
+    /// Bring back rooms saved by an earlier run.
+    ///
+    /// Their players have no connection yet, so they are shown as away and
+    /// bound to placeholder connections until they resume their seats. The
+    /// returned `(room, connection_id)` pairs are the seats they hold.
+    pub async fn restore(&self, rooms: HashMap<String, RoomSnapshot>) -> Vec<(String, String)> {
+        let count = rooms.len();
+        let mut seats = Vec::new();
+        for (room, snapshot) in rooms {
+            let mut room_state = snapshot.state;
+            room_state.passphrase = snapshot.passphrase;
+            for player in &mut room_state.players {
+                player.connection_id = Uuid::new_v4().to_string();
+                player.resume_token = snapshot
+                    .resume_tokens
+                    .get(&player.player_id)
+                    .cloned()
+                    .unwrap_or_default();
+                player.away = true;
+                seats.push((room.clone(), player.connection_id.clone()));
+            }
+            self.rooms
+                .update(&room, |slot| *slot = Some(room_state))
+                .await;
+        }
+        info!("Restored {} room(s).", count);
+        seats
+    }
+
+    /// Save every room to `store` now.
+    pub async fn save_snapshot(&self, store: &Arc<dyn SnapshotStore>) -> Result<(), String> {
+        let rooms = self.snapshot().await;
+        let store = store.clone();
+        tokio::task::spawn_blocking(move || store.save(&rooms))
+            .await
+            .map_err(|e| e.to_string())?
+    }
+
+    /// Save every room to `store` once per `interval` until `stop`
+    /// resolves. A save already under way is finished first.
+    pub async fn run_snapshots(
+        &self,
+        store: Arc<dyn SnapshotStore>,
+        interval: Duration,
+        stop: impl Future<Output = ()>,
+    ) {
+        let mut interval = tokio::time::interval(interval.max(Duration::from_secs(1)));
+        tokio::pin!(stop);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => match self.save_snapshot(&store).await {
+                    Ok(()) => debug!("run_snapshots - saved"),
+                    Err(e) => warn!("Could not save room snapshot: {}", e),
+                },
+                () = &mut stop => break,
+            }
+        }
+    }
+
     pub async fn random_name_generator(&self) -> String {
         debug!("random_name_generator - entry");
 
@@ -210,63 +487,169 @@ impl Game {
         room_name
     }
 
+    /// First player ID handed to spectators.
+    pub fn overflow_index(&self) -> usize {
+        self.overflow_index
+    }
+
+    /// Number of delegate seats per room.
+    pub fn max_room_size(&self) -> usize {
+        self.max_room_size
+    }
+
     pub async fn get_room_state(&self, room: &str) -> Option<GameState> {
         debug!("get_room_state - Room: {}", room);
 
-        self.game_state.read().await.get(room).cloned()
+        self.rooms.get(room).await
+    }
+
+    /// Decide whether a connection may join the room in `slot`. A locked
+    /// room admits whoever presents its passphrase or the resume token of a
+    /// seat in it. A room that does not exist yet is created, locked if a
+    /// passphrase is given.
+    fn admit(
+        room: &str,
+        slot: &mut Option<GameState>,
+        query: &ConnectionQuery,
+    ) -> Result<(), String> {
+        let Some(room_state) = slot else {
+            if let Some(passphrase) = query.passphrase.as_deref() {
+                let mut room_state = Self::empty_room();
+                Self::set_passphrase(&mut room_state, Some(passphrase))?;
+                *slot = Some(room_state);
+                info!("Room {} created locked", room);
+            }
+            return Ok(());
+        };
+
+        if admits(
+            room_state,
+            query.passphrase.as_deref(),
+            query.resume.as_deref(),
+        ) {
+            Ok(())
+        } else if query.passphrase.is_some() {
+            info!("Refused a connection to room {}: wrong passphrase", room);
+            Err("Wrong passphrase".to_string())
+        } else {
+            Err("This room needs a passphrase".to_string())
+        }
+    }
+
+    fn set_passphrase(room_state: &mut GameState, passphrase: Option<&str>) -> Result<(), String> {
+        room_state.passphrase = passphrase.map(Passphrase::new).transpose()?;
+        room_state.locked = room_state.passphrase.is_some();
+        Ok(())
     }
 
     pub async fn new_player_with_connection(&self, room: &str, connection_id: String) -> usize {
         debug!("new_player - Room: {}", room);
 
-        let mut state = self.game_state.write().await;
-
-        // Create room if it does not exist yet.
-        if !state.contains_key(room) {
-            state.insert(
-                room.to_string(),
-                GameState {
-                    players: Vec::new(),
-                    all_revealed: false,
-                    notify_change: NotifyChange::default(),
-                    voting_sequence: VotingSequence::default(),
-                },
-            );
-        }
+        let player_id = self
+            .rooms
+            .update(room, |slot| self.seat_player(slot, connection_id).0)
+            .await;
+
+        debug!(
+            "new_player - Room: {}, Player ID: {} - finished",
+            room, player_id
+        );
+        player_id
+    }
+
+    /// Seat a connection in a room, resuming an earlier seat when a known
+    /// resume token is presented.
+    ///
+    /// The passphrase is checked in the same update that hands out the seat,
+    /// so a room locked in the meantime still turns the connection away.
+    /// Returns the player ID, the resume token the client should present
+    /// when it reconnects, and the connection a resumed seat was taken from.
+    pub async fn join_with_connection(
+        &self,
+        room: &str,
+        connection_id: String,
+        query: &ConnectionQuery,
+    ) -> Result<(usize, String, Option<String>), String> {
+        debug!("join_with_connection - Room: {}", room);
+
+        self.rooms
+            .update(room, |slot| {
+                Self::admit(room, slot, query)?;
+                if let Some(token) = query.resume.as_deref().filter(|token| !token.is_empty())
+                    && let Some(player) = slot.as_mut().and_then(|room_state| {
+                        room_state
+                            .players
+                            .iter_mut()
+                            .find(|p| p.resume_token == token)
+                    })
+                {
+                    // Rebinding the seat to the new connection also means the
+                    // old connection's delayed removal no longer finds it.
+                    let previous_connection =
+                        std::mem::replace(&mut player.connection_id, connection_id);
+                    // The new connection starts out active; nothing else
+                    // would clear a flag set before a drop or a restart.
+                    player.away = false;
+                    info!("Player {} resumed their seat.", player.player_id);
+                    return Ok((
+                        player.player_id,
+                        player.resume_token.clone(),
+                        Some(previous_connection),
+                    ));
+                }
+
+                let (player_id, resume_token) = self.seat_player(slot, connection_id);
+                Ok((player_id, resume_token, None))
+            })
+            .await
+            .inspect(|(player_id, _, _)| {
+                debug!(
+                    "join_with_connection - Room: {}, Player ID: {} - finished",
+                    room, player_id
+                );
+            })
+    }
 
-        let room_state = state.get_mut(room).unwrap();
+    /// Give a connection the lowest free seat, creating the room if needed.
+    /// Returns the player ID and resume token.
+    fn seat_player(&self, slot: &mut Option<GameState>, connection_id: String) -> (usize, String) {
+        let room_state = slot.get_or_insert_with(Self::empty_room);
 
         let active_player_count = room_state
             .players
             .iter()
-            .filter(|p| p.player_id < Self::OVERFLOW_INDEX)
+            .filter(|p| p.player_id < self.overflow_index)
             .count();
 
-        let player_id = if active_player_count >= Self::MAX_ROOM_SIZE {
-            // Spectator: find the lowest available ID >= OVERFLOW_INDEX.
-            (Self::OVERFLOW_INDEX..)
+        let player_id = if active_player_count >= self.max_room_size {
+            // Spectator: find the lowest available ID >= overflow_index.
+            (self.overflow_index..)
                 .find(|&id| room_state.players.iter().all(|p| p.player_id != id))
                 .unwrap()
         } else {
             // Find the lowest unused active seat ID.
-            (0..Self::MAX_ROOM_SIZE)
+            (0..self.max_room_size)
                 .find(|&i| room_state.players.iter().all(|p| p.player_id != i))
-                .unwrap_or(Self::MAX_ROOM_SIZE)
+                .unwrap_or(self.max_room_size)
         };
 
+        let resume_token = Uuid::new_v4().to_string();
         room_state.players.push(PlayerState {
             player_id,
             player_name: "Delegate Unknown".to_string(),
             value: None,
+            has_voted: false,
+            away: false,
+            benched: false,
             connection_id,
+            resume_token: resume_token.clone(),
         });
+        self.refresh_captain(room_state);
+        // A new delegate has not voted yet, so a running countdown stops.
+        self.refresh_auto_reveal(room_state);
 
         info!("Player {} joined the room.", player_id);
-        debug!(
-            "new_player - Room: {}, Player ID: {} - finished",
-            room, player_id
-        );
-        player_id
+        (player_id, resume_token)
     }
 
     pub async fn new_player(&self, room: &str) -> usize {
@@ -274,26 +657,142 @@ impl Game {
             .await
     }
 
-    /// Process a message received from a client and update the room state.
-    pub async fn process_client_message(&self, room: &str, message: ClientMessage) {
+    /// Process a message as if it came from whoever holds the seats it names.
+    /// Player IDs are trusted, so only tests may use this; connections go
+    /// through [`Self::process_connection_message`].
+    #[cfg(test)]
+    async fn process_client_message(&self, room: &str, message: ClientMessage) {
         debug!(
             "process_client_message - Room: {}, Message: {:?}",
             room, message
         );
 
-        let mut state = self.game_state.write().await;
-        let room_state = state.entry(room.to_string()).or_insert(GameState {
-            players: Vec::new(),
-            all_revealed: false,
-            notify_change: NotifyChange::default(),
-            voting_sequence: VotingSequence::default(),
-        });
+        let result = self
+            .rooms
+            .update(room, |slot| {
+                let room_state = slot
+                    .as_mut()
+                    .ok_or_else(|| format!("Room {room} does not exist"))?;
+                self.apply_client_message(room, room_state, message)
+            })
+            .await;
+        if let Err(reason) = result {
+            debug!("process_client_message - Rejected: {}", reason);
+        }
+    }
+
+    /// Process a message received over the socket identified by
+    /// `connection_id`.
+    ///
+    /// Player IDs carried by the message are not trusted: they must match the
+    /// seat currently owned by the connection, otherwise the message is
+    /// dropped and the reason is returned so it can be reported back to the
+    /// sender.
+    ///
+    /// On success, returns the connection of a player the message removed
+    /// from the room, so the caller can close it.
+    pub async fn process_connection_message(
+        &self,
+        room: &str,
+        connection_id: &str,
+        message: ClientMessage,
+    ) -> Result<Option<String>, String> {
+        debug!(
+            "process_connection_message - Room: {}, Connection: {}, Message: {:?}",
+            room, connection_id, message
+        );
+
+        self.rooms
+            .update(room, |slot| {
+                let room_state = slot
+                    .as_mut()
+                    .ok_or_else(|| format!("Room {room} does not exist"))?;
+                self.apply_connection_message(room, room_state, connection_id, message)
+            })
+            .await
+    }
+
+    fn apply_connection_message(
+        &self,
+        room: &str,
+        room_state: &mut GameState,
+        connection_id: &str,
+        message: ClientMessage,
+    ) -> Result<Option<String>, String> {
+        let owned_id = room_state
+            .players
+            .iter()
+            .find(|p| p.connection_id == connection_id)
+            .map(|p| p.player_id)
+            .ok_or_else(|| "This connection does not hold a seat in the room".to_string())?;
+
+        let claimed_id = match &message {
+            ClientMessage::ChangeValue { player_id, .. }
+            | ClientMessage::WithdrawVote { player_id }
+            | ClientMessage::ChangeName { player_id, .. }
+            | ClientMessage::ChangeSequence { player_id, .. }
+            | ClientMessage::ChangeAutoReveal { player_id, .. }
+            | ClientMessage::StartTimer { player_id, .. }
+            | ClientMessage::StopTimer { player_id }
+            | ClientMessage::TransferCaptain { player_id, .. }
+            | ClientMessage::SetPassphrase { player_id, .. }
+            | ClientMessage::KickPlayer { player_id, .. }
+            | ClientMessage::DemotePlayer { player_id, .. }
+            | ClientMessage::PromoteSpectator { player_id, .. }
+            | ClientMessage::AddTopic { player_id, .. }
+            | ClientMessage::MoveTopic { player_id, .. }
+            | ClientMessage::RemoveTopic { player_id, .. }
+            | ClientMessage::ImportTopics { player_id, .. }
+            | ClientMessage::AdvanceTopic { player_id }
+            | ClientMessage::Pong { player_id } => Some(*player_id),
+            ClientMessage::ChangeSeat { current_id, .. } => Some(*current_id),
+            ClientMessage::RevealNumbers { .. } | ClientMessage::StartNewRound => None,
+        };
+
+        if let Some(claimed_id) = claimed_id
+            && claimed_id != owned_id
+        {
+            info!(
+                "Rejecting message from connection {} claiming player {} (owns {})",
+                connection_id, claimed_id, owned_id
+            );
+            return Err(format!(
+                "Player {claimed_id} does not belong to this connection"
+            ));
+        }
+
+        // Look the target up in the same update that removes them, so a seat
+        // changing hands in between cannot close the wrong connection.
+        let removed_connection = match &message {
+            ClientMessage::KickPlayer { target_id, .. } => room_state
+                .players
+                .iter()
+                .find(|p| p.player_id == *target_id)
+                .map(|p| p.connection_id.clone()),
+            _ => None,
+        };
+        self.apply_client_message(room, room_state, message)?;
+        Ok(removed_connection)
+    }
 
+    fn apply_client_message(
+        &self,
+        room: &str,
+        room_state: &mut GameState,
+        message: ClientMessage,
+    ) -> Result<(), String> {
         match message {
             ClientMessage::Pong { player_id } => {
                 debug!("Player {} ponged.", player_id);
             }
             ClientMessage::ChangeValue { player_id, value } => {
+                if let Some(card) = value.card()
+                    && !room_state.voting_sequence.contains(card)
+                {
+                    return Err(format!(
+                        "{card} is not a card in the current voting sequence"
+                    ));
+                }
                 if let Some(player) = room_state
                     .players
                     .iter_mut()
@@ -302,6 +801,15 @@ impl Game {
                     player.value = Some(value);
                 }
             }
+            ClientMessage::WithdrawVote { player_id } => {
+                if let Some(player) = room_state
+                    .players
+                    .iter_mut()
+                    .find(|p| p.player_id == player_id)
+                {
+                    player.value = None;
+                }
+            }
             ClientMessage::ChangeName { player_id, name } => {
                 if Self::has_illegal_name(&name) {
                     let offending_connection = room_state
@@ -314,7 +822,7 @@ impl Game {
                         "Dropping ChangeName request from connection {} due to illegal characters",
                         offending_connection
                     );
-                    return;
+                    return Ok(());
                 }
 
                 if let Some(player) = room_state
@@ -326,17 +834,145 @@ impl Game {
                 }
             }
             ClientMessage::RevealNumbers { value } => {
-                // Only zero out the values if the user wants to reset and the
+                // Only clear the votes if the user wants to reset and the
                 // previous state was revealed.
-                if !value && room_state.all_revealed {
-                    for player in &mut room_state.players {
-                        if player.value.is_some() {
-                            player.value = Some(0);
-                        }
+                if value {
+                    Self::reveal(room_state);
+                } else if room_state.all_revealed {
+                    self.start_new_round(room_state);
+                }
+            }
+            ClientMessage::StartNewRound => {
+                self.start_new_round(room_state);
+            }
+            ClientMessage::StartTimer {
+                player_id,
+                seconds,
+                reveal_on_expiry,
+            } => {
+                self.require_captain(room_state, player_id, "start the timer")?;
+                if !(1..=Self::MAX_TIMER_SECS).contains(&seconds) {
+                    return Err(format!(
+                        "The timer must run for between 1 and {} seconds",
+                        Self::MAX_TIMER_SECS
+                    ));
+                }
+                let now = agenda::now();
+                room_state.timer = Some(Timer {
+                    started_at: now,
+                    ends_at: now + seconds,
+                    reveal_on_expiry,
+                    ..Timer::default()
+                });
+            }
+            ClientMessage::StopTimer { player_id } => {
+                self.require_captain(room_state, player_id, "stop the timer")?;
+                room_state.timer = None;
+            }
+            ClientMessage::TransferCaptain {
+                player_id,
+                new_captain_id,
+            } => {
+                self.require_captain(room_state, player_id, "hand over the captaincy")?;
+                if new_captain_id >= self.overflow_index
+                    || room_state
+                        .players
+                        .iter()
+                        .all(|p| p.player_id != new_captain_id)
+                {
+                    return Err(format!("Seat {new_captain_id} has no delegate"));
+                }
+                room_state.captain_id = Some(new_captain_id);
+                info!(
+                    "Player {} handed the captaincy to {} in room {}",
+                    player_id, new_captain_id, room
+                );
+            }
+            ClientMessage::SetPassphrase {
+                player_id,
+                passphrase,
+            } => {
+                self.require_captain(room_state, player_id, "lock the room")?;
+                Self::set_passphrase(room_state, passphrase.as_ref().map(Secret::expose))?;
+                info!(
+                    "Room {} {}",
+                    room,
+                    if room_state.locked {
+                        "locked"
+                    } else {
+                        "unlocked"
                     }
+                );
+            }
+            ClientMessage::KickPlayer {
+                player_id,
+                target_id,
+            } => {
+                self.require_captain(room_state, player_id, "kick players")?;
+                if target_id == player_id {
+                    return Err("The captain cannot kick themselves".to_string());
+                }
+                if !self.remove_from_room(room_state, target_id) {
+                    return Err(format!("There is no player {target_id}"));
+                }
+                info!(
+                    "Player {} kicked player {} from room {}",
+                    player_id, target_id, room
+                );
+            }
+            ClientMessage::DemotePlayer {
+                player_id,
+                target_id,
+            } => {
+                self.require_captain(room_state, player_id, "move delegates")?;
+                if target_id == player_id {
+                    return Err("The captain cannot demote themselves".to_string());
+                }
+                if target_id >= self.overflow_index
+                    || room_state.players.iter().all(|p| p.player_id != target_id)
+                {
+                    return Err(format!("Seat {target_id} has no delegate"));
+                }
+                let spectator_id = (self.overflow_index..)
+                    .find(|&id| room_state.players.iter().all(|p| p.player_id != id))
+                    .unwrap();
+                self.move_player(target_id, spectator_id, room_state, None, true);
+                Self::set_benched(room_state, spectator_id, true);
+                // The benched delegate is not eligible, so the seat goes to
+                // whoever has been waiting for one.
+                let promoted = self.backfill_seat(room_state, target_id);
+                room_state.notify_changes = std::iter::once(NotifyChange {
+                    current_id: target_id,
+                    new_id: spectator_id,
+                })
+                .chain(promoted)
+                .collect();
+            }
+            ClientMessage::PromoteSpectator {
+                player_id,
+                target_id,
+                seat,
+            } => {
+                self.require_captain(room_state, player_id, "move delegates")?;
+                if target_id < self.overflow_index
+                    || room_state.players.iter().all(|p| p.player_id != target_id)
+                {
+                    return Err(format!("Player {target_id} is not a spectator"));
                 }
-                // Update the state
-                room_state.all_revealed = value;
+                let is_free = |id: usize| room_state.players.iter().all(|p| p.player_id != id);
+                let seat = match seat {
+                    Some(seat) if seat < self.max_room_size && is_free(seat) => seat,
+                    Some(seat) => return Err(format!("Seat {seat} is not free")),
+                    None => (0..self.max_room_size)
+                        .find(|&id| is_free(id))
+                        .ok_or("Every seat is taken")?,
+                };
+                self.move_player(target_id, seat, room_state, None, true);
+                Self::set_benched(room_state, seat, false);
+                room_state.notify_changes = vec![NotifyChange {
+                    current_id: target_id,
+                    new_id: seat,
+                }];
             }
             ClientMessage::ChangeSeat {
                 name,
@@ -354,815 +990,2781 @@ impl Game {
                         "Dropping ChangeSeat request from connection {} due to illegal characters",
                         offending_connection
                     );
-                    return;
+                    return Ok(());
                 }
 
                 // A seat change is only valid when the requested seat is within
                 // the active range (0–11) AND is not already occupied. Spectator
-                // slots (≥ 100) and out-of-range indices are always rejected.
-                let is_valid = requested_id < Self::MAX_ROOM_SIZE
+                // slots (≥ 100) and out-of-range indices are always rejected, as
+                // are moves by benched spectators.
+                let is_valid = requested_id < self.max_room_size
                     && room_state
                         .players
                         .iter()
-                        .all(|p| p.player_id != requested_id);
+                        .all(|p| p.player_id != requested_id)
+                    && !room_state
+                        .players
+                        .iter()
+                        .any(|p| p.player_id == current_id && p.benched);
 
                 if is_valid {
                     if self.move_player(current_id, requested_id, room_state, Some(name), false) {
-                        room_state.notify_change = NotifyChange {
+                        room_state.notify_changes = vec![NotifyChange {
                             current_id,
                             new_id: requested_id,
-                        };
+                        }];
                         debug!(
                             "Player {} moved to seat {} in room {}",
                             current_id, requested_id, room
                         );
                     } else {
-                        room_state.notify_change = NotifyChange::default();
+                        room_state.notify_changes.clear();
                     }
                 } else {
-                    room_state.notify_change = NotifyChange::default();
+                    room_state.notify_changes.clear();
                 }
             }
             ClientMessage::ChangeSequence {
                 player_id,
                 sequence,
             } => {
-                // Only the captain (lowest active player_id) may change the sequence.
-                let min_id = room_state
-                    .players
-                    .iter()
-                    .filter(|p| p.player_id < 100)
-                    .map(|p| p.player_id)
-                    .min();
-                if Some(player_id) == min_id {
-                    room_state.voting_sequence = sequence;
+                self.require_captain(room_state, player_id, "change the voting sequence")?;
+                let sequence = Self::validate_sequence(sequence)?;
+                // Votes cast against the old cards mean nothing now.
+                for player in room_state.players.iter_mut() {
+                    if player
+                        .value
+                        .and_then(Vote::card)
+                        .is_some_and(|card| !sequence.contains(card))
+                    {
+                        player.value = None;
+                    }
+                }
+                room_state.voting_sequence = sequence;
+            }
+            ClientMessage::ChangeAutoReveal {
+                player_id,
+                auto_reveal,
+            } => {
+                self.require_captain(room_state, player_id, "change the auto-reveal setting")?;
+                room_state.auto_reveal = Self::validate_auto_reveal(auto_reveal)?;
+                // A pending countdown restarts with the new length.
+                room_state.auto_reveal_at = None;
+            }
+            ClientMessage::AddTopic {
+                player_id,
+                title,
+                link,
+            } => {
+                self.require_captain(room_state, player_id, "change the agenda")?;
+                let topic = agenda::new_topic(&title, link.as_deref())?;
+                agenda::add_topic(room_state, topic)?;
+            }
+            ClientMessage::MoveTopic {
+                player_id,
+                topic_id,
+                index,
+            } => {
+                self.require_captain(room_state, player_id, "change the agenda")?;
+                agenda::move_topic(room_state, &topic_id, index)?;
+            }
+            ClientMessage::RemoveTopic {
+                player_id,
+                topic_id,
+            } => {
+                self.require_captain(room_state, player_id, "change the agenda")?;
+                agenda::remove_topic(room_state, &topic_id)?;
+            }
+            ClientMessage::ImportTopics { player_id, data } => {
+                self.require_captain(room_state, player_id, "change the agenda")?;
+                let topics = import::parse_backlog(&data).map_err(|errors| errors.join("\n"))?;
+                agenda::add_topics(room_state, topics)?;
+            }
+            ClientMessage::AdvanceTopic { player_id } => {
+                self.require_captain(room_state, player_id, "change the agenda")?;
+                // A topic skipped before its votes were revealed was never
+                // decided, so it goes to the back of the agenda to come up
+                // again once the others have had their turn.
+                let undecided = if room_state.all_revealed {
+                    None
+                } else {
+                    room_state.current_topic.take()
+                };
+                // Votes cast so far were about the previous topic.
+                self.start_new_round(room_state);
+                agenda::advance(room_state);
+                if let Some(topic) = undecided {
+                    room_state.agenda.push(topic);
                 }
             }
         }
+        self.refresh_captain(room_state);
+        self.refresh_auto_reveal(room_state);
+        self.refresh_round_summary(room_state);
+        Ok(())
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::structs::ClientMessage;
 
-    fn new_game() -> Game {
-        Game::new()
+    /// Import a backlog into a room's agenda on behalf of the player holding
+    /// `resume_token`, who must be the captain.
+    ///
+    /// Returns the number of imported topics.
+    pub async fn import_backlog(
+        &self,
+        room: &str,
+        resume_token: &str,
+        data: &str,
+    ) -> Result<usize, ImportError> {
+        self.rooms
+            .update(room, |slot| {
+                let room_state = slot.as_mut().ok_or(ImportError::UnknownRoom)?;
+
+                let player_id = room_state
+                    .players
+                    .iter()
+                    .find(|p| !p.resume_token.is_empty() && p.resume_token == resume_token)
+                    .map(|p| p.player_id)
+                    .ok_or(ImportError::Forbidden)?;
+                self.require_captain(room_state, player_id, "import a backlog")
+                    .map_err(|_| ImportError::Forbidden)?;
+
+                let topics = import::parse_backlog(data).map_err(ImportError::Invalid)?;
+                let count = topics.len();
+                agenda::add_topics(room_state, topics)
+                    .map_err(|e| ImportError::Invalid(vec![e]))?;
+                info!("Imported {} topics into room {}", count, room);
+                Ok(count)
+            })
+            .await
     }
 
-    // ── Room management ──────────────────────────────────────────────────────
-
-    /// Rule: a room created with a given name is immediately visible in state
-    /// with an empty player list and `all_revealed = false`.
-    #[tokio::test]
-    async fn test_generate_new_room_creates_empty_room() {
-        let game = new_game();
-        let name = game.generate_new_room(Some("g-room-1")).await;
-        assert_eq!(name, "g-room-1");
-        let state = game.get_room_state("g-room-1").await.unwrap();
-        assert!(state.players.is_empty());
-        assert!(!state.all_revealed);
+    /// Hand the captaincy to the delegate in the lowest seat if the room has
+    /// no captain, e.g. because the captain left.
+    fn refresh_captain(&self, room_state: &mut GameState) {
+        let is_delegate = |id: usize| {
+            id < self.overflow_index && room_state.players.iter().any(|p| p.player_id == id)
+        };
+        if room_state.captain_id.is_some_and(is_delegate) {
+            return;
+        }
+        room_state.captain_id = room_state
+            .players
+            .iter()
+            .filter(|p| p.player_id < self.overflow_index)
+            .map(|p| p.player_id)
+            .min();
     }
 
-    /// Rule: querying a room that has never been created must return `None`
-    /// (no implicit room creation).
-    #[tokio::test]
-    async fn test_get_room_state_returns_none_for_nonexistent_room() {
-        let game = new_game();
-        assert!(game.get_room_state("does-not-exist").await.is_none());
+    fn require_captain(
+        &self,
+        room_state: &GameState,
+        player_id: usize,
+        action: &str,
+    ) -> Result<(), String> {
+        if Some(player_id) == room_state.captain_id {
+            Ok(())
+        } else {
+            Err(format!("Only the captain can {action}"))
+        }
     }
 
-    /// Rule: room name generation must always produce a usable (non-empty)
-    /// string so that clients can identify their room.
-    #[tokio::test]
-    async fn test_random_name_generator_returns_nonempty_string() {
-        let game = new_game();
-        let name = game.random_name_generator().await;
-        assert!(!name.is_empty());
+    /// Close the current round, recording it in the history if its votes
+    /// were revealed, and start the next one with every vote cleared.
+    /// Unrevealed votes are discarded rather than recorded so they stay
+    /// secret.
+    fn start_new_round(&self, room_state: &mut GameState) {
+        if room_state.all_revealed {
+            agenda::record_round(room_state, self.overflow_index, agenda::now());
+            room_state.all_revealed = false;
+        }
+        for player in &mut room_state.players {
+            player.value = None;
+        }
+        room_state.round += 1;
+        room_state.timer = None;
+        room_state.round_started_at = agenda::now();
+        room_state.revealed_at = None;
     }
 
-    // ── Player ID assignment ─────────────────────────────────────────────────
-
-    /// Rule: the very first player in a new room always receives ID 0 so that
-    /// they immediately become the captain (lowest active ID).
-    #[tokio::test]
-    async fn test_new_player_in_new_room_gets_id_zero() {
-        let game = new_game();
-        let id = game.new_player("p-room-first").await;
-        assert_eq!(id, 0);
-        let state = game.get_room_state("p-room-first").await.unwrap();
-        assert_eq!(state.players.len(), 1);
-        assert_eq!(state.players[0].player_id, 0);
+    fn reveal(room_state: &mut GameState) {
+        if !room_state.all_revealed {
+            room_state.all_revealed = true;
+            room_state.revealed_at = Some(agenda::now());
+        }
+        room_state.auto_reveal_at = None;
     }
 
-    /// Rule: players joining a room receive consecutive IDs starting at 0 so
-    /// that the seat layout is always predictable.
-    #[tokio::test]
-    async fn test_new_player_assigns_sequential_ids() {
-        let game = new_game();
-        game.generate_new_room(Some("p-room-seq")).await;
-        assert_eq!(game.new_player("p-room-seq").await, 0);
-        assert_eq!(game.new_player("p-room-seq").await, 1);
-        assert_eq!(game.new_player("p-room-seq").await, 2);
+    /// Reveal once every delegate has voted, or start the room's countdown to
+    /// do so. A pending countdown is called off as soon as it no longer
+    /// applies, e.g. because a delegate withdrew their vote.
+    fn refresh_auto_reveal(&self, room_state: &mut GameState) {
+        if !room_state.auto_reveal.enabled
+            || room_state.all_revealed
+            || !self.all_delegates_voted(room_state)
+        {
+            room_state.auto_reveal_at = None;
+        } else if room_state.auto_reveal.countdown_secs == 0 {
+            Self::reveal(room_state);
+        } else if room_state.auto_reveal_at.is_none() {
+            room_state.auto_reveal_at = Some(agenda::now() + room_state.auto_reveal.countdown_secs);
+        }
     }
 
-    /// Rule: when a seat is vacated the lowest-numbered empty slot is reused
-    /// for the next joining player to keep IDs compact.
-    #[tokio::test]
-    async fn test_new_player_reuses_lowest_available_id() {
-        let game = new_game();
-        game.generate_new_room(Some("p-room-reuse")).await;
-        game.new_player("p-room-reuse").await; // id 0
-        game.new_player("p-room-reuse").await; // id 1
-        game.new_player("p-room-reuse").await; // id 2
-        game.remove_player("p-room-reuse", 1).await;
-        // ID 1 is now the lowest vacant slot
-        assert_eq!(game.new_player("p-room-reuse").await, 1);
+    fn all_delegates_voted(&self, room_state: &GameState) -> bool {
+        let mut delegates = room_state
+            .players
+            .iter()
+            .filter(|p| p.player_id < self.overflow_index)
+            .peekable();
+        delegates.peek().is_some() && delegates.all(|p| p.value.is_some())
     }
 
-    /// Rule: once all 12 active seats are filled, additional players are placed
-    /// in spectator mode and receive an overflow ID (≥ 100).
-    #[tokio::test]
-    async fn test_new_player_overflow_id_when_room_full() {
-        let game = new_game();
-        game.generate_new_room(Some("p-room-full")).await;
-        for _ in 0..12 {
-            game.new_player("p-room-full").await;
-        }
-        let overflow_id = game.new_player("p-room-full").await;
-        assert!(
-            overflow_id >= 100,
-            "Expected overflow ID ≥ 100, got {overflow_id}"
-        );
+    /// Claim the job of waking `room` up at `at`. Returns false if a task is
+    /// already waiting on that deadline.
+    pub async fn claim_deadline(&self, room: &str, at: u64) -> bool {
+        let mut scheduled = self.scheduled_deadlines.lock().await;
+        if scheduled.get(room) == Some(&at) {
+            return false;
+        }
+        scheduled.insert(room.to_string(), at);
+        true
     }
 
-    /// Rule: new players start with a placeholder name ("Delegate Unknown") and
-    /// no vote value so that the UI can distinguish un-named players.
-    #[tokio::test]
-    async fn test_new_player_has_default_name_and_no_value() {
-        let game = new_game();
-        game.generate_new_room(Some("p-room-defaults")).await;
-        game.new_player("p-room-defaults").await;
-        let state = game.get_room_state("p-room-defaults").await.unwrap();
-        assert_eq!(state.players[0].player_name, "Delegate Unknown");
-        assert_eq!(state.players[0].value, None);
-    }
+    /// Fire whatever has come due in `room` by the deadline `at`: a pending
+    /// auto-reveal, and the timer, which reveals the round if asked to.
+    /// Returns whether the room changed.
+    pub async fn fire_deadline(&self, room: &str, at: u64) -> bool {
+        {
+            let mut scheduled = self.scheduled_deadlines.lock().await;
+            if scheduled.get(room) == Some(&at) {
+                scheduled.remove(room);
+            }
+        }
 
-    // ── Player removal ───────────────────────────────────────────────────────
-
-    /// Rule: removing a player by ID must eliminate exactly that player from
-    /// the roster; all remaining players are unaffected.
-    #[tokio::test]
-    async fn test_remove_player_removes_player() {
-        let game = new_game();
-        game.generate_new_room(Some("r-room-1")).await;
-        game.new_player("r-room-1").await; // id 0
-        game.new_player("r-room-1").await; // id 1
-        game.remove_player("r-room-1", 0).await;
-        let state = game.get_room_state("r-room-1").await.unwrap();
-        assert_eq!(state.players.len(), 1);
-        assert!(state.players.iter().all(|p| p.player_id != 0));
+        self.rooms
+            .update(room, |slot| {
+                let Some(room_state) = slot else {
+                    return false;
+                };
+                let now = agenda::now();
+                let mut changed = false;
+                if room_state.auto_reveal_at.is_some_and(|at| at <= now) {
+                    // Someone may have joined or withdrawn since the countdown
+                    // started.
+                    if self.all_delegates_voted(room_state) {
+                        debug!("fire_deadline - auto-revealing room {}", room);
+                        Self::reveal(room_state);
+                    } else {
+                        room_state.auto_reveal_at = None;
+                    }
+                    changed = true;
+                }
+                if let Some(timer) = &mut room_state.timer
+                    && !timer.expired
+                    && timer.ends_at <= now
+                {
+                    debug!("fire_deadline - timer expired in room {}", room);
+                    timer.expired = true;
+                    if timer.reveal_on_expiry {
+                        Self::reveal(room_state);
+                    }
+                    changed = true;
+                }
+                if changed {
+                    self.refresh_round_summary(room_state);
+                }
+                changed
+            })
+            .await
     }
 
-    /// Rule: when a full room (12 players) loses a member, the first waiting
-    /// spectator is automatically promoted to fill the vacant seat.
-    #[tokio::test]
-    async fn test_remove_player_promotes_waiting_player() {
-        let game = new_game();
-        game.generate_new_room(Some("r-room-promote")).await;
-        // Fill 12 active players (IDs 0–11)
-        for _ in 0..12 {
-            game.new_player("r-room-promote").await;
-        }
-        // 13th join → spectator with ID ≥ 100
-        let spectator_id = game.new_player("r-room-promote").await;
-        assert!(spectator_id >= 100, "13th player must be a spectator");
-
-        // Remove player 0 (vacancy); spectator should be promoted into slot 0
-        game.remove_player("r-room-promote", 0).await;
-        let state = game.get_room_state("r-room-promote").await.unwrap();
-
-        // Spectator no longer retains its original spectator ID
-        assert!(state.players.iter().all(|p| p.player_id != spectator_id));
-        // Slot 0 is now filled by the promoted player
-        assert!(state.players.iter().any(|p| p.player_id == 0));
-        // notify_change records the promotion
-        assert_eq!(state.notify_change.current_id, spectator_id);
-        assert_eq!(state.notify_change.new_id, 0);
+    /// Recompute the round statistics while votes are revealed, and drop them
+    /// once they are hidden again.
+    fn refresh_round_summary(&self, room_state: &mut GameState) {
+        room_state.round_summary = room_state
+            .all_revealed
+            .then(|| stats::summarize_round(room_state, self.overflow_index));
     }
 
-    /// Rule: after a spectator is promoted into an active seat, the next joiner
-    /// must stay in spectator mode with a fresh overflow ID instead of reusing
-    /// the promoted spectator's old overflow ID.
-    #[tokio::test]
-    async fn test_new_spectator_gets_fresh_id_after_promotion() {
-        let game = new_game();
-        game.generate_new_room(Some("r-room-fresh-spectator")).await;
-        for _ in 0..12 {
-            game.new_player("r-room-fresh-spectator").await;
-        }
-
-        let first_spectator_id = game.new_player("r-room-fresh-spectator").await;
-        assert!(
-            first_spectator_id >= Game::OVERFLOW_INDEX,
-            "13th player must be a spectator"
-        );
+    fn validate_auto_reveal(auto_reveal: AutoReveal) -> Result<AutoReveal, String> {
+        if auto_reveal.countdown_secs > Self::MAX_AUTO_REVEAL_COUNTDOWN_SECS {
+            return Err(format!(
+                "The auto-reveal countdown is limited to {} seconds",
+                Self::MAX_AUTO_REVEAL_COUNTDOWN_SECS
+            ));
+        }
+        Ok(auto_reveal)
+    }
 
-        // Remove player 0; first spectator is promoted into seat 0.
-        game.remove_player("r-room-fresh-spectator", 0).await;
+    /// Check a sequence chosen by the captain. Built-in sequences are always
+    /// valid; custom cards are checked and returned with trimmed labels.
+    fn validate_sequence(sequence: VotingSequence) -> Result<VotingSequence, String> {
+        let VotingSequence::Custom(cards) = sequence else {
+            return Ok(sequence);
+        };
 
-        // The promoted spectator's old overflow ID is now free, so the next
-        // spectator reuses it (lowest available ID >= OVERFLOW_INDEX).
-        let next_join_id = game.new_player("r-room-fresh-spectator").await;
-        assert!(
-            next_join_id >= Game::OVERFLOW_INDEX,
-            "new player should be a spectator"
-        );
+        if cards.is_empty() || cards.len() > Self::MAX_CUSTOM_CARDS {
+            return Err(format!(
+                "A custom sequence needs between 1 and {} cards",
+                Self::MAX_CUSTOM_CARDS
+            ));
+        }
 
-        let state = game.get_room_state("r-room-fresh-spectator").await.unwrap();
-        // Promoted player occupies seat 0.
-        assert!(state.players.iter().any(|p| p.player_id == 0));
-        // New spectator exists.
-        assert!(state.players.iter().any(|p| p.player_id == next_join_id));
+        let mut validated: Vec<Card> = Vec::with_capacity(cards.len());
+        for card in cards {
+            let label = card.label.trim();
+            if label.is_empty()
+                || label.chars().count() > Self::MAX_CARD_LABEL_LEN
+                || label.chars().any(char::is_control)
+            {
+                return Err(format!(
+                    "Card labels must be 1 to {} printable characters",
+                    Self::MAX_CARD_LABEL_LEN
+                ));
+            }
+            if validated
+                .iter()
+                .any(|c| c.value == card.value || c.label == label)
+            {
+                return Err(format!("Card {label:?} appears more than once"));
+            }
+            validated.push(Card {
+                value: card.value,
+                label: label.to_string(),
+            });
+        }
+        Ok(VotingSequence::Custom(validated))
     }
+}
 
-    /// Rule: when the room is not full and has no spectators, removing a player
-    /// leaves `notify_change` at its zero values (no promotion event).
-    #[tokio::test]
-    async fn test_remove_player_no_promotion_with_small_room() {
-        let game = new_game();
-        game.generate_new_room(Some("r-room-small")).await;
-        game.new_player("r-room-small").await; // id 0
-        game.new_player("r-room-small").await; // id 1
-        game.remove_player("r-room-small", 0).await;
-        let state = game.get_room_state("r-room-small").await.unwrap();
-        // No promotion expected; notify_change should be zeroed
-        assert_eq!(state.notify_change.current_id, 0);
-        assert_eq!(state.notify_change.new_id, 0);
-    }
+/// The `Game` tests, run against every `RoomStore`. Instantiate it in a test
+/// module with the store type; it must implement `Default`.
+#[cfg(test)]
+macro_rules! game_tests {
+    ($store:ty) => {
+        use $crate::game::*;
+        use $crate::structs::ClientMessage;
 
-    // ── Message processing ───────────────────────────────────────────────────
-
-    /// Rule: a ChangeValue message updates only the sending player's stored
-    /// vote; other players in the room are unaffected.
-    #[tokio::test]
-    async fn test_process_change_value_updates_player_value() {
-        let game = new_game();
-        game.generate_new_room(Some("m-room-cv")).await;
-        game.new_player("m-room-cv").await; // id 0
-        game.process_client_message(
-            "m-room-cv",
-            ClientMessage::ChangeValue {
-                player_id: 0,
-                value: 5,
-            },
-        )
-        .await;
-        let state = game.get_room_state("m-room-cv").await.unwrap();
-        let player = state.players.iter().find(|p| p.player_id == 0).unwrap();
-        assert_eq!(player.value, Some(5));
-    }
+        type TestGame = Game<$store>;
 
-    /// Rule: a ChangeName message updates only the sending player's display
-    /// name; other players in the room are unaffected.
-    #[tokio::test]
-    async fn test_process_change_name_updates_player_name() {
-        let game = new_game();
-        game.generate_new_room(Some("m-room-cn")).await;
-        game.new_player("m-room-cn").await; // id 0
-        game.process_client_message(
-            "m-room-cn",
-            ClientMessage::ChangeName {
-                player_id: 0,
-                name: "Alice".to_string(),
-            },
-        )
-        .await;
-        let state = game.get_room_state("m-room-cn").await.unwrap();
-        let player = state.players.iter().find(|p| p.player_id == 0).unwrap();
-        assert_eq!(player.player_name, "Alice");
-    }
+        fn new_game() -> Arc<TestGame> {
+            TestGame::new(&Config::default())
+        }
 
-    /// Rule: names containing illegal characters are rejected and leave the
-    /// stored name unchanged.
-    #[tokio::test]
-    async fn test_process_change_name_rejects_illegal_characters() {
-        let game = new_game();
-        game.generate_new_room(Some("m-room-cn-illegal")).await;
-        game.new_player("m-room-cn-illegal").await; // id 0
-        game.process_client_message(
-            "m-room-cn-illegal",
-            ClientMessage::ChangeName {
-                player_id: 0,
-                name: "Valid".to_string(),
-            },
-        )
-        .await;
-        // BAD CHAR: <
-        game.process_client_message(
-            "m-room-cn-illegal",
-            ClientMessage::ChangeName {
-                player_id: 0,
-                name: "Bad<Name".to_string(),
-            },
-        )
-        .await;
-        // BAD CHAR: .
-        let state = game.get_room_state("m-room-cn-illegal").await.unwrap();
-        let player = state.players.iter().find(|p| p.player_id == 0).unwrap();
-        assert_eq!(player.player_name, "Valid");
-
-        game.process_client_message(
-            "m-room-cn-illegal",
-            ClientMessage::ChangeName {
-                player_id: 0,
-                name: "Bad.Name".to_string(),
-            },
-        )
-        .await;
-        let state = game.get_room_state("m-room-cn-illegal").await.unwrap();
-        let player = state.players.iter().find(|p| p.player_id == 0).unwrap();
-        assert_eq!(player.player_name, "Valid");
-        // BAD CHAR: {}
-        let state = game.get_room_state("m-room-cn-illegal").await.unwrap();
-        let player = state.players.iter().find(|p| p.player_id == 0).unwrap();
-        assert_eq!(player.player_name, "Valid");
-
-        game.process_client_message(
-            "m-room-cn-illegal",
-            ClientMessage::ChangeName {
-                player_id: 0,
-                name: "Bad{Name".to_string(),
-            },
-        )
-        .await;
-        let state = game.get_room_state("m-room-cn-illegal").await.unwrap();
-        let player = state.players.iter().find(|p| p.player_id == 0).unwrap();
-        assert_eq!(player.player_name, "Valid");
-    }
+        // ── Room management ──────────────────────────────────────────────────────
+
+        /// Rule: a room created with a given name is immediately visible in state
+        /// with an empty player list and `all_revealed = false`.
+        #[tokio::test]
+        async fn test_generate_new_room_creates_empty_room() {
+            let game = new_game();
+            let name = game.generate_new_room(Some("g-room-1")).await;
+            assert_eq!(name, "g-room-1");
+            let state = game.get_room_state("g-room-1").await.unwrap();
+            assert!(state.players.is_empty());
+            assert!(!state.all_revealed);
+        }
 
-    /// Rule: RevealNumbers { true } transitions the room into the revealed
-    /// state so that all clients can display vote values.
-    #[tokio::test]
-    async fn test_process_reveal_numbers_sets_all_revealed() {
-        let game = new_game();
-        game.generate_new_room(Some("m-room-rn-show")).await;
-        game.process_client_message(
-            "m-room-rn-show",
-            ClientMessage::RevealNumbers { value: true },
-        )
-        .await;
-        let state = game.get_room_state("m-room-rn-show").await.unwrap();
-        assert!(state.all_revealed);
-    }
+        /// Rule: querying a room that has never been created must return `None`
+        /// (no implicit room creation).
+        #[tokio::test]
+        async fn test_get_room_state_returns_none_for_nonexistent_room() {
+            let game = new_game();
+            assert!(game.get_room_state("does-not-exist").await.is_none());
+        }
 
-    /// Rule: RevealNumbers { false } after a reveal clears `all_revealed` and
-    /// resets every player's vote to 0, starting a fresh voting round.
-    #[tokio::test]
-    async fn test_process_reveal_numbers_false_resets_values() {
-        let game = new_game();
-        game.generate_new_room(Some("m-room-rn-hide")).await;
-        game.new_player("m-room-rn-hide").await; // id 0
-        game.process_client_message(
-            "m-room-rn-hide",
-            ClientMessage::ChangeValue {
-                player_id: 0,
-                value: 8,
-            },
-        )
-        .await;
-        game.process_client_message(
-            "m-room-rn-hide",
-            ClientMessage::RevealNumbers { value: true },
-        )
-        .await;
-        game.process_client_message(
-            "m-room-rn-hide",
-            ClientMessage::RevealNumbers { value: false },
-        )
-        .await;
-        let state = game.get_room_state("m-room-rn-hide").await.unwrap();
-        assert!(!state.all_revealed);
-        let player = state.players.iter().find(|p| p.player_id == 0).unwrap();
-        assert_eq!(player.value, Some(0));
-    }
+        /// Rule: room name generation must always produce a usable (non-empty)
+        /// string so that clients can identify their room.
+        #[tokio::test]
+        async fn test_random_name_generator_returns_nonempty_string() {
+            let game = new_game();
+            let name = game.random_name_generator().await;
+            assert!(!name.is_empty());
+        }
 
-    /// Rule: RevealNumbers { false } when no reveal has occurred yet must NOT
-    /// reset existing votes, as no voting round has completed.
-    #[tokio::test]
-    async fn test_hide_without_prior_reveal_keeps_values() {
-        let game = new_game();
-        game.generate_new_room(Some("m-room-rn-noreset")).await;
-        game.new_player("m-room-rn-noreset").await;
-        game.process_client_message(
-            "m-room-rn-noreset",
-            ClientMessage::ChangeValue {
-                player_id: 0,
-                value: 3,
-            },
-        )
-        .await;
-        // Hide without ever revealing – value must stay intact
-        game.process_client_message(
-            "m-room-rn-noreset",
-            ClientMessage::RevealNumbers { value: false },
-        )
-        .await;
-        let state = game.get_room_state("m-room-rn-noreset").await.unwrap();
-        let player = state.players.iter().find(|p| p.player_id == 0).unwrap();
-        assert_eq!(player.value, Some(3));
-    }
+        /// Rule: an empty room is collected once its idle TTL has elapsed, and
+        /// the number of collected rooms is reported.
+        #[tokio::test]
+        async fn test_reap_idle_rooms_removes_empty_rooms() {
+            let game = new_game();
+            game.generate_new_room(Some("g-room-idle")).await;
+            assert_eq!(game.reap_idle_rooms(Duration::ZERO).await, 1);
+            assert!(game.get_room_state("g-room-idle").await.is_none());
+        }
+
+        /// Rule: rooms with players, and empty rooms still inside their TTL, are
+        /// never collected.
+        #[tokio::test]
+        async fn test_reap_idle_rooms_keeps_occupied_and_recent_rooms() {
+            let game = new_game();
+            game.new_player("g-room-busy").await;
+            game.generate_new_room(Some("g-room-recent")).await;
+            assert_eq!(game.reap_idle_rooms(Duration::from_secs(300)).await, 0);
+            assert!(game.get_room_state("g-room-busy").await.is_some());
+            assert!(game.get_room_state("g-room-recent").await.is_some());
+        }
+
+        /// Rule: removing a player from a room that does not exist must not
+        /// create the room.
+        #[tokio::test]
+        async fn test_remove_player_does_not_create_room() {
+            let game = new_game();
+            game.remove_player("g-room-phantom", 0).await;
+            assert!(game.get_room_state("g-room-phantom").await.is_none());
+        }
 
-    /// Rule: a Pong message is a keep-alive reply and must never modify any
-    /// game state.
-    #[tokio::test]
-    async fn test_process_pong_is_noop() {
-        let game = new_game();
-        game.generate_new_room(Some("m-room-pong")).await;
-        game.new_player("m-room-pong").await;
-        let before = game.get_room_state("m-room-pong").await.unwrap();
-        game.process_client_message("m-room-pong", ClientMessage::Pong { player_id: 0 })
+        // ── Player ID assignment ─────────────────────────────────────────────────
+
+        /// Rule: the very first player in a new room always receives ID 0 so that
+        /// they immediately become the captain.
+        #[tokio::test]
+        async fn test_new_player_in_new_room_gets_id_zero() {
+            let game = new_game();
+            let id = game.new_player("p-room-first").await;
+            assert_eq!(id, 0);
+            let state = game.get_room_state("p-room-first").await.unwrap();
+            assert_eq!(state.players.len(), 1);
+            assert_eq!(state.players[0].player_id, 0);
+        }
+
+        /// Rule: players joining a room receive consecutive IDs starting at 0 so
+        /// that the seat layout is always predictable.
+        #[tokio::test]
+        async fn test_new_player_assigns_sequential_ids() {
+            let game = new_game();
+            game.generate_new_room(Some("p-room-seq")).await;
+            assert_eq!(game.new_player("p-room-seq").await, 0);
+            assert_eq!(game.new_player("p-room-seq").await, 1);
+            assert_eq!(game.new_player("p-room-seq").await, 2);
+        }
+
+        /// Rule: when a seat is vacated the lowest-numbered empty slot is reused
+        /// for the next joining player to keep IDs compact.
+        #[tokio::test]
+        async fn test_new_player_reuses_lowest_available_id() {
+            let game = new_game();
+            game.generate_new_room(Some("p-room-reuse")).await;
+            game.new_player("p-room-reuse").await; // id 0
+            game.new_player("p-room-reuse").await; // id 1
+            game.new_player("p-room-reuse").await; // id 2
+            game.remove_player("p-room-reuse", 1).await;
+            // ID 1 is now the lowest vacant slot
+            assert_eq!(game.new_player("p-room-reuse").await, 1);
+        }
+
+        /// Rule: once all 12 active seats are filled, additional players are placed
+        /// in spectator mode and receive an overflow ID (≥ 100).
+        #[tokio::test]
+        async fn test_new_player_overflow_id_when_room_full() {
+            let game = new_game();
+            game.generate_new_room(Some("p-room-full")).await;
+            for _ in 0..12 {
+                game.new_player("p-room-full").await;
+            }
+            let overflow_id = game.new_player("p-room-full").await;
+            assert!(
+                overflow_id >= 100,
+                "Expected overflow ID ≥ 100, got {overflow_id}"
+            );
+        }
+
+        /// Rule: new players start with a placeholder name ("Delegate Unknown") and
+        /// no vote value so that the UI can distinguish un-named players.
+        #[tokio::test]
+        async fn test_new_player_has_default_name_and_no_value() {
+            let game = new_game();
+            game.generate_new_room(Some("p-room-defaults")).await;
+            game.new_player("p-room-defaults").await;
+            let state = game.get_room_state("p-room-defaults").await.unwrap();
+            assert_eq!(state.players[0].player_name, "Delegate Unknown");
+            assert_eq!(state.players[0].value, None);
+        }
+
+        // ── Presence ─────────────────────────────────────────────────────────────
+
+        /// Rule: a player can be flagged away and back, and only actual changes
+        /// are reported so callers do not broadcast redundant updates.
+        #[tokio::test]
+        async fn test_set_player_away_reports_changes_only() {
+            let game = new_game();
+            game.new_player_with_connection("a-room-away", "conn-a".to_string())
+                .await;
+
+            assert!(game.set_player_away("a-room-away", "conn-a", true).await);
+            assert!(!game.set_player_away("a-room-away", "conn-a", true).await);
+            let state = game.get_room_state("a-room-away").await.unwrap();
+            assert!(state.players[0].away);
+
+            assert!(game.set_player_away("a-room-away", "conn-a", false).await);
+            let state = game.get_room_state("a-room-away").await.unwrap();
+            assert!(!state.players[0].away);
+        }
+
+        /// Rule: flagging an unknown connection is a no-op.
+        #[tokio::test]
+        async fn test_set_player_away_unknown_connection_is_noop() {
+            let game = new_game();
+            game.new_player_with_connection("a-room-unknown", "conn-a".to_string())
+                .await;
+            assert!(!game.set_player_away("a-room-unknown", "conn-z", true).await);
+            assert!(!game.set_player_away("no-such-room", "conn-a", true).await);
+        }
+
+        // ── Session resume ───────────────────────────────────────────────────────
+
+        /// Rule: presenting a known resume token returns the original seat with
+        /// its name and vote intact, bound to the new connection.
+        #[tokio::test]
+        async fn test_join_with_resume_token_restores_seat() {
+            let game = new_game();
+            let (player_id, token, _) = game
+                .join_with_connection("j-room-resume", "conn-old".to_string(), &query(None, None))
+                .await
+                .unwrap();
+            game.process_client_message(
+                "j-room-resume",
+                ClientMessage::ChangeValue {
+                    player_id,
+                    value: Vote::Card(8),
+                },
+            )
             .await;
-        let after = game.get_room_state("m-room-pong").await.unwrap();
-        assert_eq!(before, after);
-    }
 
-    // ── Seat switching ───────────────────────────────────────────────────────
-
-    /// Rule: a player may move to any vacant seat in the range 0–11.  Their
-    /// name travels with them and the old seat becomes vacant.
-    #[tokio::test]
-    async fn test_change_seat_moves_player_to_vacant_seat() {
-        let game = new_game();
-        game.generate_new_room(Some("s-room-move")).await;
-        game.new_player("s-room-move").await; // id 0
-        game.new_player("s-room-move").await; // id 1
-
-        // Name the players
-        game.process_client_message(
-            "s-room-move",
-            ClientMessage::ChangeName {
-                player_id: 0,
-                name: "Alice".to_string(),
-            },
-        )
-        .await;
-        game.process_client_message(
-            "s-room-move",
-            ClientMessage::ChangeName {
-                player_id: 1,
-                name: "Bob".to_string(),
-            },
-        )
-        .await;
-
-        // Assert starting positions
-        let pre = game.get_room_state("s-room-move").await.unwrap();
-        assert!(
-            pre.players
+            let (resumed_id, resumed_token, previous_connection) = game
+                .join_with_connection(
+                    "j-room-resume",
+                    "conn-new".to_string(),
+                    &query(None, Some(&token)),
+                )
+                .await
+                .unwrap();
+            assert_eq!(resumed_id, player_id);
+            assert_eq!(resumed_token, token);
+            assert_eq!(previous_connection.as_deref(), Some("conn-old"));
+
+            // The old connection's delayed removal must not evict the seat.
+            game.remove_player_by_connection("j-room-resume", "conn-old")
+                .await;
+            let state = game.get_room_state("j-room-resume").await.unwrap();
+            assert_eq!(state.players.len(), 1);
+            assert_eq!(state.players[0].value, Some(Vote::Card(8)));
+            assert_eq!(state.players[0].connection_id, "conn-new");
+        }
+
+        /// Rule: an unknown resume token is ignored and the connection is seated
+        /// as a new player with a fresh token.
+        #[tokio::test]
+        async fn test_join_with_unknown_resume_token_seats_new_player() {
+            let game = new_game();
+            let (first_id, first_token, _) = game
+                .join_with_connection("j-room-unknown", "conn-a".to_string(), &query(None, None))
+                .await
+                .unwrap();
+            let (second_id, second_token, previous_connection) = game
+                .join_with_connection(
+                    "j-room-unknown",
+                    "conn-b".to_string(),
+                    &query(None, Some("bogus")),
+                )
+                .await
+                .unwrap();
+            assert!(previous_connection.is_none());
+            assert_ne!(first_id, second_id);
+            assert_ne!(first_token, second_token);
+            assert!(!second_token.is_empty());
+        }
+
+        // ── Player removal ───────────────────────────────────────────────────────
+
+        /// Rule: removing a player by ID must eliminate exactly that player from
+        /// the roster; all remaining players are unaffected.
+        #[tokio::test]
+        async fn test_remove_player_removes_player() {
+            let game = new_game();
+            game.generate_new_room(Some("r-room-1")).await;
+            game.new_player("r-room-1").await; // id 0
+            game.new_player("r-room-1").await; // id 1
+            game.remove_player("r-room-1", 0).await;
+            let state = game.get_room_state("r-room-1").await.unwrap();
+            assert_eq!(state.players.len(), 1);
+            assert!(state.players.iter().all(|p| p.player_id != 0));
+        }
+
+        /// Rule: when a full room (12 players) loses a member, the first waiting
+        /// spectator is automatically promoted to fill the vacant seat.
+        #[tokio::test]
+        async fn test_remove_player_promotes_waiting_player() {
+            let game = new_game();
+            game.generate_new_room(Some("r-room-promote")).await;
+            // Fill 12 active players (IDs 0–11)
+            for _ in 0..12 {
+                game.new_player("r-room-promote").await;
+            }
+            // 13th join → spectator with ID ≥ 100
+            let spectator_id = game.new_player("r-room-promote").await;
+            assert!(spectator_id >= 100, "13th player must be a spectator");
+
+            // Remove player 0 (vacancy); spectator should be promoted into slot 0
+            game.remove_player("r-room-promote", 0).await;
+            let state = game.get_room_state("r-room-promote").await.unwrap();
+
+            // Spectator no longer retains its original spectator ID
+            assert!(state.players.iter().all(|p| p.player_id != spectator_id));
+            // Slot 0 is now filled by the promoted player
+            assert!(state.players.iter().any(|p| p.player_id == 0));
+            // notify_changes records the promotion
+            assert_eq!(
+                state.notify_changes,
+                vec![NotifyChange {
+                    current_id: spectator_id,
+                    new_id: 0,
+                }]
+            );
+        }
+
+        /// Rule: after a spectator is promoted into an active seat, the next joiner
+        /// must stay in spectator mode with a fresh overflow ID instead of reusing
+        /// the promoted spectator's old overflow ID.
+        #[tokio::test]
+        async fn test_new_spectator_gets_fresh_id_after_promotion() {
+            let game = new_game();
+            game.generate_new_room(Some("r-room-fresh-spectator")).await;
+            for _ in 0..12 {
+                game.new_player("r-room-fresh-spectator").await;
+            }
+
+            let first_spectator_id = game.new_player("r-room-fresh-spectator").await;
+            assert!(
+                first_spectator_id >= game.overflow_index,
+                "13th player must be a spectator"
+            );
+
+            // Remove player 0; first spectator is promoted into seat 0.
+            game.remove_player("r-room-fresh-spectator", 0).await;
+
+            // The promoted spectator's old overflow ID is now free, so the next
+            // spectator reuses it (lowest available ID >= OVERFLOW_INDEX).
+            let next_join_id = game.new_player("r-room-fresh-spectator").await;
+            assert!(
+                next_join_id >= game.overflow_index,
+                "new player should be a spectator"
+            );
+
+            let state = game.get_room_state("r-room-fresh-spectator").await.unwrap();
+            // Promoted player occupies seat 0.
+            assert!(state.players.iter().any(|p| p.player_id == 0));
+            // New spectator exists.
+            assert!(state.players.iter().any(|p| p.player_id == next_join_id));
+        }
+
+        /// Rule: when the room is not full and has no spectators, removing a player
+        /// leaves `notify_changes` empty (no promotion event).
+        #[tokio::test]
+        async fn test_remove_player_no_promotion_with_small_room() {
+            let game = new_game();
+            game.generate_new_room(Some("r-room-small")).await;
+            game.new_player("r-room-small").await; // id 0
+            game.new_player("r-room-small").await; // id 1
+            game.remove_player("r-room-small", 0).await;
+            let state = game.get_room_state("r-room-small").await.unwrap();
+            // No promotion expected
+            assert!(state.notify_changes.is_empty());
+        }
+
+        /// Rule: a spectator leaving frees no seat, so nobody else is promoted
+        /// into one.
+        #[tokio::test]
+        async fn test_removing_spectator_promotes_nobody() {
+            let game = new_game();
+            let room = "r-room-spectator-leaves";
+            game.new_player(room).await; // id 0
+            game.rooms
+                .update(room, |slot| {
+                    let players = &mut slot.as_mut().unwrap().players;
+                    for player_id in [100, 101] {
+                        players.push(PlayerState {
+                            player_id,
+                            ..Default::default()
+                        });
+                    }
+                })
+                .await;
+
+            game.remove_player(room, 100).await;
+            let state = game.get_room_state(room).await.unwrap();
+            assert!(state.players.iter().any(|p| p.player_id == 101));
+            assert!(state.notify_changes.is_empty());
+        }
+
+        // ── Message processing ───────────────────────────────────────────────────
+
+        /// Rule: a ChangeValue message updates only the sending player's stored
+        /// vote; other players in the room are unaffected.
+        #[tokio::test]
+        async fn test_process_change_value_updates_player_value() {
+            let game = new_game();
+            game.generate_new_room(Some("m-room-cv")).await;
+            game.new_player("m-room-cv").await; // id 0
+            game.process_client_message(
+                "m-room-cv",
+                ClientMessage::ChangeValue {
+                    player_id: 0,
+                    value: Vote::Card(5),
+                },
+            )
+            .await;
+            let state = game.get_room_state("m-room-cv").await.unwrap();
+            let player = state.players.iter().find(|p| p.player_id == 0).unwrap();
+            assert_eq!(player.value, Some(Vote::Card(5)));
+        }
+
+        /// Rule: a ChangeName message updates only the sending player's display
+        /// name; other players in the room are unaffected.
+        #[tokio::test]
+        async fn test_process_change_name_updates_player_name() {
+            let game = new_game();
+            game.generate_new_room(Some("m-room-cn")).await;
+            game.new_player("m-room-cn").await; // id 0
+            game.process_client_message(
+                "m-room-cn",
+                ClientMessage::ChangeName {
+                    player_id: 0,
+                    name: "Alice".to_string(),
+                },
+            )
+            .await;
+            let state = game.get_room_state("m-room-cn").await.unwrap();
+            let player = state.players.iter().find(|p| p.player_id == 0).unwrap();
+            assert_eq!(player.player_name, "Alice");
+        }
+
+        /// Rule: names containing illegal characters are rejected and leave the
+        /// stored name unchanged.
+        #[tokio::test]
+        async fn test_process_change_name_rejects_illegal_characters() {
+            let game = new_game();
+            game.generate_new_room(Some("m-room-cn-illegal")).await;
+            game.new_player("m-room-cn-illegal").await; // id 0
+            game.process_client_message(
+                "m-room-cn-illegal",
+                ClientMessage::ChangeName {
+                    player_id: 0,
+                    name: "Valid".to_string(),
+                },
+            )
+            .await;
+            // BAD CHAR: <
+            game.process_client_message(
+                "m-room-cn-illegal",
+                ClientMessage::ChangeName {
+                    player_id: 0,
+                    name: "Bad<Name".to_string(),
+                },
+            )
+            .await;
+            // BAD CHAR: .
+            let state = game.get_room_state("m-room-cn-illegal").await.unwrap();
+            let player = state.players.iter().find(|p| p.player_id == 0).unwrap();
+            assert_eq!(player.player_name, "Valid");
+
+            game.process_client_message(
+                "m-room-cn-illegal",
+                ClientMessage::ChangeName {
+                    player_id: 0,
+                    name: "Bad.Name".to_string(),
+                },
+            )
+            .await;
+            let state = game.get_room_state("m-room-cn-illegal").await.unwrap();
+            let player = state.players.iter().find(|p| p.player_id == 0).unwrap();
+            assert_eq!(player.player_name, "Valid");
+            // BAD CHAR: {}
+            let state = game.get_room_state("m-room-cn-illegal").await.unwrap();
+            let player = state.players.iter().find(|p| p.player_id == 0).unwrap();
+            assert_eq!(player.player_name, "Valid");
+
+            game.process_client_message(
+                "m-room-cn-illegal",
+                ClientMessage::ChangeName {
+                    player_id: 0,
+                    name: "Bad{Name".to_string(),
+                },
+            )
+            .await;
+            let state = game.get_room_state("m-room-cn-illegal").await.unwrap();
+            let player = state.players.iter().find(|p| p.player_id == 0).unwrap();
+            assert_eq!(player.player_name, "Valid");
+        }
+
+        /// Rule: RevealNumbers { true } transitions the room into the revealed
+        /// state so that all clients can display vote values.
+        #[tokio::test]
+        async fn test_process_reveal_numbers_sets_all_revealed() {
+            let game = new_game();
+            game.generate_new_room(Some("m-room-rn-show")).await;
+            game.process_client_message(
+                "m-room-rn-show",
+                ClientMessage::RevealNumbers { value: true },
+            )
+            .await;
+            let state = game.get_room_state("m-room-rn-show").await.unwrap();
+            assert!(state.all_revealed);
+        }
+
+        /// Rule: RevealNumbers { false } after a reveal clears `all_revealed` and
+        /// every player's vote, starting a fresh voting round.
+        #[tokio::test]
+        async fn test_process_reveal_numbers_false_resets_values() {
+            let game = new_game();
+            game.generate_new_room(Some("m-room-rn-hide")).await;
+            game.new_player("m-room-rn-hide").await; // id 0
+            game.process_client_message(
+                "m-room-rn-hide",
+                ClientMessage::ChangeValue {
+                    player_id: 0,
+                    value: Vote::Card(8),
+                },
+            )
+            .await;
+            game.process_client_message(
+                "m-room-rn-hide",
+                ClientMessage::RevealNumbers { value: true },
+            )
+            .await;
+            game.process_client_message(
+                "m-room-rn-hide",
+                ClientMessage::RevealNumbers { value: false },
+            )
+            .await;
+            let state = game.get_room_state("m-room-rn-hide").await.unwrap();
+            assert!(!state.all_revealed);
+            let player = state.players.iter().find(|p| p.player_id == 0).unwrap();
+            assert_eq!(player.value, None);
+            assert!(!player.has_voted);
+        }
+
+        /// Rule: StartNewRound clears every vote to "not voted", bumps the round
+        /// counter and records the revealed round in the history.
+        #[tokio::test]
+        async fn test_start_new_round_clears_votes_and_records_round() {
+            let game = new_game();
+            game.generate_new_room(Some("m-room-new-round")).await;
+            game.new_player("m-room-new-round").await; // id 0
+            game.new_player("m-room-new-round").await; // id 1
+            game.process_client_message(
+                "m-room-new-round",
+                ClientMessage::ChangeValue {
+                    player_id: 0,
+                    value: Vote::Card(5),
+                },
+            )
+            .await;
+            game.process_client_message(
+                "m-room-new-round",
+                ClientMessage::RevealNumbers { value: true },
+            )
+            .await;
+            assert_eq!(
+                game.get_room_state("m-room-new-round").await.unwrap().round,
+                1
+            );
+
+            game.process_client_message("m-room-new-round", ClientMessage::StartNewRound)
+                .await;
+            let state = game.get_room_state("m-room-new-round").await.unwrap();
+            assert!(!state.all_revealed);
+            assert_eq!(state.round, 2);
+            assert!(state.players.iter().all(|p| p.value.is_none()));
+            assert_eq!(state.history.len(), 1);
+            assert_eq!(state.history[0].round, 1);
+            assert_eq!(state.history[0].votes[0].value, Some(Vote::Card(5)));
+        }
+
+        /// Rule: starting a new round before the reveal discards the votes
+        /// without recording them, so they are never disclosed.
+        #[tokio::test]
+        async fn test_start_new_round_before_reveal_is_not_recorded() {
+            let game = new_game();
+            game.generate_new_room(Some("m-room-new-round-hidden"))
+                .await;
+            game.new_player("m-room-new-round-hidden").await; // id 0
+            game.process_client_message(
+                "m-room-new-round-hidden",
+                ClientMessage::ChangeValue {
+                    player_id: 0,
+                    value: Vote::Card(3),
+                },
+            )
+            .await;
+            game.process_client_message("m-room-new-round-hidden", ClientMessage::StartNewRound)
+                .await;
+            let state = game
+                .get_room_state("m-room-new-round-hidden")
+                .await
+                .unwrap();
+            assert_eq!(state.round, 2);
+            assert_eq!(state.players[0].value, None);
+            assert!(state.history.is_empty());
+        }
+
+        /// Rule: revealing attaches a round summary to the room state, and hiding
+        /// the votes again removes it.
+        #[tokio::test]
+        async fn test_reveal_attaches_round_summary() {
+            let game = new_game();
+            game.generate_new_room(Some("m-room-summary")).await;
+            game.new_player("m-room-summary").await; // id 0
+            game.new_player("m-room-summary").await; // id 1
+            for (player_id, value) in [(0, 5), (1, 8)] {
+                game.process_client_message(
+                    "m-room-summary",
+                    ClientMessage::ChangeValue {
+                        player_id,
+                        value: Vote::Card(value),
+                    },
+                )
+                .await;
+            }
+            assert!(
+                game.get_room_state("m-room-summary")
+                    .await
+                    .unwrap()
+                    .round_summary
+                    .is_none()
+            );
+
+            game.process_client_message(
+                "m-room-summary",
+                ClientMessage::RevealNumbers { value: true },
+            )
+            .await;
+            let state = game.get_room_state("m-room-summary").await.unwrap();
+            let summary = state.round_summary.expect("summary after reveal");
+            assert_eq!(summary.vote_count, 2);
+            assert_eq!(summary.mean, Some(6.5));
+
+            game.process_client_message(
+                "m-room-summary",
+                ClientMessage::RevealNumbers { value: false },
+            )
+            .await;
+            let state = game.get_room_state("m-room-summary").await.unwrap();
+            assert!(state.round_summary.is_none());
+        }
+
+        /// Rule: RevealNumbers { false } when no reveal has occurred yet must NOT
+        /// reset existing votes, as no voting round has completed.
+        #[tokio::test]
+        async fn test_hide_without_prior_reveal_keeps_values() {
+            let game = new_game();
+            game.generate_new_room(Some("m-room-rn-noreset")).await;
+            game.new_player("m-room-rn-noreset").await;
+            game.process_client_message(
+                "m-room-rn-noreset",
+                ClientMessage::ChangeValue {
+                    player_id: 0,
+                    value: Vote::Card(3),
+                },
+            )
+            .await;
+            // Hide without ever revealing – value must stay intact
+            game.process_client_message(
+                "m-room-rn-noreset",
+                ClientMessage::RevealNumbers { value: false },
+            )
+            .await;
+            let state = game.get_room_state("m-room-rn-noreset").await.unwrap();
+            let player = state.players.iter().find(|p| p.player_id == 0).unwrap();
+            assert_eq!(player.value, Some(Vote::Card(3)));
+        }
+
+        /// Rule: a Pong message is a keep-alive reply and must never modify any
+        /// game state.
+        #[tokio::test]
+        async fn test_process_pong_is_noop() {
+            let game = new_game();
+            game.generate_new_room(Some("m-room-pong")).await;
+            game.new_player("m-room-pong").await;
+            let before = game.get_room_state("m-room-pong").await.unwrap();
+            game.process_client_message("m-room-pong", ClientMessage::Pong { player_id: 0 })
+                .await;
+            let after = game.get_room_state("m-room-pong").await.unwrap();
+            assert_eq!(before, after);
+        }
+
+        // ── Connection identity ──────────────────────────────────────────────────
+
+        /// Rule: a connection may act on the seat it owns.
+        #[tokio::test]
+        async fn test_connection_message_accepted_for_owned_seat() {
+            let game = new_game();
+            game.new_player_with_connection("c-room-own", "conn-a".to_string())
+                .await; // id 0
+            let result = game
+                .process_connection_message(
+                    "c-room-own",
+                    "conn-a",
+                    ClientMessage::ChangeValue {
+                        player_id: 0,
+                        value: Vote::Card(5),
+                    },
+                )
+                .await;
+            assert!(result.is_ok());
+            let state = game.get_room_state("c-room-own").await.unwrap();
+            assert_eq!(state.players[0].value, Some(Vote::Card(5)));
+        }
+
+        /// Rule: a connection claiming somebody else's player ID is rejected and
+        /// the other player's vote is left untouched.
+        #[tokio::test]
+        async fn test_connection_message_rejects_spoofed_player_id() {
+            let game = new_game();
+            game.new_player_with_connection("c-room-spoof", "conn-a".to_string())
+                .await; // id 0
+            game.new_player_with_connection("c-room-spoof", "conn-b".to_string())
+                .await; // id 1
+            let result = game
+                .process_connection_message(
+                    "c-room-spoof",
+                    "conn-b",
+                    ClientMessage::ChangeValue {
+                        player_id: 0,
+                        value: Vote::Card(21),
+                    },
+                )
+                .await;
+            assert!(result.is_err());
+            let state = game.get_room_state("c-room-spoof").await.unwrap();
+            let victim = state.players.iter().find(|p| p.player_id == 0).unwrap();
+            assert_eq!(victim.value, None);
+        }
+
+        /// Rule: a delegate cannot impersonate the captain to change the voting
+        /// sequence.
+        #[tokio::test]
+        async fn test_connection_message_rejects_captain_impersonation() {
+            let game = new_game();
+            game.new_player_with_connection("c-room-captain", "conn-a".to_string())
+                .await; // id 0 – captain
+            game.new_player_with_connection("c-room-captain", "conn-b".to_string())
+                .await; // id 1
+            let result = game
+                .process_connection_message(
+                    "c-room-captain",
+                    "conn-b",
+                    ClientMessage::ChangeSequence {
+                        player_id: 0,
+                        sequence: VotingSequence::YeaNea,
+                    },
+                )
+                .await;
+            assert!(result.is_err());
+            let state = game.get_room_state("c-room-captain").await.unwrap();
+            assert_eq!(state.voting_sequence, VotingSequence::Fibonacci);
+        }
+
+        /// Rule: a connection that holds no seat in the room cannot act in it.
+        #[tokio::test]
+        async fn test_connection_message_rejects_unknown_connection() {
+            let game = new_game();
+            game.new_player_with_connection("c-room-unknown", "conn-a".to_string())
+                .await;
+            let result = game
+                .process_connection_message(
+                    "c-room-unknown",
+                    "conn-z",
+                    ClientMessage::RevealNumbers { value: true },
+                )
+                .await;
+            assert!(result.is_err());
+            let state = game.get_room_state("c-room-unknown").await.unwrap();
+            assert!(!state.all_revealed);
+        }
+
+        // ── Vote secrecy ─────────────────────────────────────────────────────────
+
+        /// Rule: before the reveal, a recipient sees its own vote but only a
+        /// `has_voted` flag for everyone else.
+        #[tokio::test]
+        async fn test_state_for_connection_hides_other_votes_before_reveal() {
+            let game = new_game();
+            game.new_player_with_connection("v-room-hidden", "conn-a".to_string())
+                .await; // id 0
+            game.new_player_with_connection("v-room-hidden", "conn-b".to_string())
+                .await; // id 1
+            for (player_id, value) in [(0, 3), (1, 8)] {
+                game.process_client_message(
+                    "v-room-hidden",
+                    ClientMessage::ChangeValue {
+                        player_id,
+                        value: Vote::Card(value),
+                    },
+                )
+                .await;
+            }
+
+            let state = game.get_room_state("v-room-hidden").await.unwrap();
+            let view = state_for_connection(&state, "conn-a");
+            let own = view.players.iter().find(|p| p.player_id == 0).unwrap();
+            let other = view.players.iter().find(|p| p.player_id == 1).unwrap();
+            assert_eq!(own.value, Some(Vote::Card(3)));
+            assert_eq!(other.value, None);
+            assert!(own.has_voted);
+            assert!(other.has_voted);
+        }
+
+        /// Rule: once the room is revealed, every recipient sees every vote.
+        #[tokio::test]
+        async fn test_state_for_connection_shows_all_votes_after_reveal() {
+            let game = new_game();
+            game.new_player_with_connection("v-room-revealed", "conn-a".to_string())
+                .await; // id 0
+            game.new_player_with_connection("v-room-revealed", "conn-b".to_string())
+                .await; // id 1
+            game.process_client_message(
+                "v-room-revealed",
+                ClientMessage::ChangeValue {
+                    player_id: 1,
+                    value: Vote::Card(8),
+                },
+            )
+            .await;
+            game.process_client_message(
+                "v-room-revealed",
+                ClientMessage::RevealNumbers { value: true },
+            )
+            .await;
+
+            let state = game.get_room_state("v-room-revealed").await.unwrap();
+            let view = state_for_connection(&state, "conn-a");
+            let own = view.players.iter().find(|p| p.player_id == 0).unwrap();
+            let other = view.players.iter().find(|p| p.player_id == 1).unwrap();
+            assert_eq!(other.value, Some(Vote::Card(8)));
+            assert!(!own.has_voted);
+        }
+
+        // ── Seat switching ───────────────────────────────────────────────────────
+
+        /// Rule: a player may move to any vacant seat in the range 0–11.  Their
+        /// name travels with them and the old seat becomes vacant.
+        #[tokio::test]
+        async fn test_change_seat_moves_player_to_vacant_seat() {
+            let game = new_game();
+            game.generate_new_room(Some("s-room-move")).await;
+            game.new_player("s-room-move").await; // id 0
+            game.new_player("s-room-move").await; // id 1
+
+            // Name the players
+            game.process_client_message(
+                "s-room-move",
+                ClientMessage::ChangeName {
+                    player_id: 0,
+                    name: "Alice".to_string(),
+                },
+            )
+            .await;
+            game.process_client_message(
+                "s-room-move",
+                ClientMessage::ChangeName {
+                    player_id: 1,
+                    name: "Bob".to_string(),
+                },
+            )
+            .await;
+
+            // Assert starting positions
+            let pre = game.get_room_state("s-room-move").await.unwrap();
+            assert!(
+                pre.players
+                    .iter()
+                    .any(|p| p.player_id == 0 && p.player_name == "Alice")
+            );
+            assert!(
+                pre.players
+                    .iter()
+                    .any(|p| p.player_id == 1 && p.player_name == "Bob")
+            );
+
+            // Alice moves from seat 0 to seat 3
+            game.process_client_message(
+                "s-room-move",
+                ClientMessage::ChangeSeat {
+                    name: "Alice".to_string(),
+                    current_id: 0,
+                    requested_id: 3,
+                },
+            )
+            .await;
+
+            let state = game.get_room_state("s-room-move").await.unwrap();
+            // Alice should now be at seat 3
+            let alice = state.players.iter().find(|p| p.player_id == 3).unwrap();
+            assert_eq!(alice.player_name, "Alice");
+            // Bob should remain at seat 1
+            let bob = state.players.iter().find(|p| p.player_id == 1).unwrap();
+            assert_eq!(bob.player_name, "Bob");
+        }
+
+        /// Rule: a ChangeSeat request targeting an occupied seat is silently
+        /// ignored; both players remain at their original seats.
+        #[tokio::test]
+        async fn test_change_seat_rejects_occupied_seat() {
+            let game = new_game();
+            game.generate_new_room(Some("s-room-occupied")).await;
+            game.new_player("s-room-occupied").await; // id 0
+            game.new_player("s-room-occupied").await; // id 1
+
+            // Name the players
+            game.process_client_message(
+                "s-room-occupied",
+                ClientMessage::ChangeName {
+                    player_id: 0,
+                    name: "Alice".to_string(),
+                },
+            )
+            .await;
+            game.process_client_message(
+                "s-room-occupied",
+                ClientMessage::ChangeName {
+                    player_id: 1,
+                    name: "Bob".to_string(),
+                },
+            )
+            .await;
+
+            // Alice attempts to take Bob's occupied seat
+            game.process_client_message(
+                "s-room-occupied",
+                ClientMessage::ChangeSeat {
+                    name: "Alice".to_string(),
+                    current_id: 0,
+                    requested_id: 1, // seat 1 is Bob's
+                },
+            )
+            .await;
+
+            let state = game.get_room_state("s-room-occupied").await.unwrap();
+            // Alice must still be at seat 0
+            let alice = state.players.iter().find(|p| p.player_id == 0).unwrap();
+            assert_eq!(alice.player_name, "Alice");
+            // Bob must still be at seat 1
+            let bob = state.players.iter().find(|p| p.player_id == 1).unwrap();
+            assert_eq!(bob.player_name, "Bob");
+        }
+
+        /// Rule: seat IDs ≥ 12 fall in the spectator/overflow zone and must be
+        /// rejected; the requesting player stays at their current seat.
+        #[tokio::test]
+        async fn test_change_seat_rejects_overflow_seat() {
+            let game = new_game();
+            game.generate_new_room(Some("s-room-overflow")).await;
+            game.new_player("s-room-overflow").await; // id 0
+
+            game.process_client_message(
+                "s-room-overflow",
+                ClientMessage::ChangeSeat {
+                    name: "Delegate Unknown".to_string(),
+                    current_id: 0,
+                    requested_id: 12,
+                },
+            )
+            .await;
+
+            let state = game.get_room_state("s-room-overflow").await.unwrap();
+            assert!(state.players.iter().any(|p| p.player_id == 0));
+            assert!(state.players.iter().all(|p| p.player_id != 12));
+        }
+
+        /// Rule: when a player changes seats, the name supplied in the ChangeSeat
+        /// message is assigned to the new seat so the player's identity follows
+        /// them.
+        #[tokio::test]
+        async fn test_change_seat_preserves_player_name_and_value() {
+            let game = new_game();
+            game.generate_new_room(Some("s-room-preserve")).await;
+            game.new_player("s-room-preserve").await; // id 0
+            game.new_player("s-room-preserve").await; // id 1
+            game.process_client_message(
+                "s-room-preserve",
+                ClientMessage::ChangeName {
+                    player_id: 0,
+                    name: "Alice".to_string(),
+                },
+            )
+            .await;
+            game.process_client_message(
+                "s-room-preserve",
+                ClientMessage::ChangeName {
+                    player_id: 1,
+                    name: "Bob".to_string(),
+                },
+            )
+            .await;
+
+            // Alice moves to seat 3
+            game.process_client_message(
+                "s-room-preserve",
+                ClientMessage::ChangeSeat {
+                    name: "Alice".to_string(),
+                    current_id: 0,
+                    requested_id: 3,
+                },
+            )
+            .await;
+
+            let state = game.get_room_state("s-room-preserve").await.unwrap();
+            // The player at seat 3 should be Alice
+            let alice = state.players.iter().find(|p| p.player_id == 3).unwrap();
+            assert_eq!(alice.player_name, "Alice");
+        }
+
+        /// Rule: the captaincy follows the captain to a new seat; taking a lower
+        /// seat does not make anyone else captain. Existing votes are unaffected
+        /// by the move.
+        #[tokio::test]
+        async fn test_captain_keeps_role_on_seat_change() {
+            let game = new_game();
+            game.generate_new_room(Some("s-room-captain")).await;
+            game.new_player("s-room-captain").await; // id 0
+            game.new_player("s-room-captain").await; // id 1
+
+            // Name the players
+            game.process_client_message(
+                "s-room-captain",
+                ClientMessage::ChangeName {
+                    player_id: 0,
+                    name: "Alice".to_string(),
+                },
+            )
+            .await;
+            game.process_client_message(
+                "s-room-captain",
+                ClientMessage::ChangeName {
+                    player_id: 1,
+                    name: "Bob".to_string(),
+                },
+            )
+            .await;
+
+            // Alice (id 0) is captain; give her a vote so we can prove it survives
+            game.process_client_message(
+                "s-room-captain",
+                ClientMessage::ChangeValue {
+                    player_id: 0,
+                    value: Vote::Card(5),
+                },
+            )
+            .await;
+
+            // Alice moves to seat 3; Bob (id 1) now holds the lowest ID
+            game.process_client_message(
+                "s-room-captain",
+                ClientMessage::ChangeSeat {
+                    name: "Alice".to_string(),
+                    current_id: 0,
+                    requested_id: 3,
+                },
+            )
+            .await;
+            // A newcomer takes the vacated seat 0
+            game.new_player("s-room-captain").await;
+
+            let state = game.get_room_state("s-room-captain").await.unwrap();
+            assert_eq!(state.captain_id, Some(3), "Alice is still the captain");
+
+            // Alice retains her vote at the new seat
+            let alice = state.players.iter().find(|p| p.player_id == 3).unwrap();
+            assert_eq!(alice.player_name, "Alice");
+            assert_eq!(alice.value, Some(Vote::Card(5)));
+        }
+
+        /// Rule: the captain can hand the captaincy to another delegate, but not
+        /// to an empty seat or a spectator.
+        #[tokio::test]
+        async fn test_transfer_captain() {
+            let game = new_game();
+            let room = "s-room-transfer";
+            game.new_player_with_connection(room, "conn-a".to_string())
+                .await; // id 0 – captain
+            game.new_player_with_connection(room, "conn-b".to_string())
+                .await; // id 1
+
+            let transfer = |player_id, new_captain_id| ClientMessage::TransferCaptain {
+                player_id,
+                new_captain_id,
+            };
+            for (connection_id, message) in [
+                ("conn-b", transfer(1, 1)),
+                ("conn-a", transfer(0, 5)),
+                ("conn-a", transfer(0, 100)),
+            ] {
+                assert!(
+                    game.process_connection_message(room, connection_id, message)
+                        .await
+                        .is_err()
+                );
+            }
+            game.process_connection_message(room, "conn-a", transfer(0, 1))
+                .await
+                .unwrap();
+            assert_eq!(game.get_room_state(room).await.unwrap().captain_id, Some(1));
+        }
+
+        /// Rule: when the captain leaves, the captaincy passes to the delegate in
+        /// the lowest seat, not to a spectator promoted into the vacated seat.
+        #[tokio::test]
+        async fn test_captain_hand_off_when_captain_leaves() {
+            let game = TestGame::new(&Config {
+                max_room_size: 2,
+                ..Config::default()
+            });
+            let room = "s-room-captain-leaves";
+            game.new_player(room).await; // id 0
+            game.new_player(room).await; // id 1
+            game.new_player(room).await; // id 100 – spectator
+            game.process_client_message(
+                room,
+                ClientMessage::TransferCaptain {
+                    player_id: 0,
+                    new_captain_id: 1,
+                },
+            )
+            .await;
+
+            game.remove_player(room, 0).await;
+            assert_eq!(
+                game.get_room_state(room).await.unwrap().captain_id,
+                Some(1),
+                "a delegate leaving does not move the captaincy"
+            );
+
+            game.remove_player(room, 1).await;
+            let state = game.get_room_state(room).await.unwrap();
+            assert_eq!(state.players.len(), 1);
+            assert_eq!(state.players[0].player_id, 0, "spectator promoted");
+            assert_eq!(state.captain_id, Some(0));
+        }
+
+        // ── Voting sequence ──────────────────────────────────────────────────────
+
+        /// Rule: only the captain may change the voting sequence.  The sequence
+        /// is updated immediately and broadcast to all clients via the next state
+        /// update.
+        #[tokio::test]
+        async fn test_process_change_sequence_updates_voting_sequence() {
+            let game = new_game();
+            game.generate_new_room(Some("m-room-cs")).await;
+            game.new_player("m-room-cs").await; // id 0 – captain
+
+            // Default should be Fibonacci
+            let initial_state = game.get_room_state("m-room-cs").await.unwrap();
+            assert_eq!(initial_state.voting_sequence, VotingSequence::Fibonacci);
+
+            // Captain (id 0) changes to Linear
+            game.process_client_message(
+                "m-room-cs",
+                ClientMessage::ChangeSequence {
+                    player_id: 0,
+                    sequence: VotingSequence::Linear,
+                },
+            )
+            .await;
+            let state = game.get_room_state("m-room-cs").await.unwrap();
+            assert_eq!(state.voting_sequence, VotingSequence::Linear);
+
+            // Captain changes to SmMedLgXl
+            game.process_client_message(
+                "m-room-cs",
+                ClientMessage::ChangeSequence {
+                    player_id: 0,
+                    sequence: VotingSequence::SmMedLgXl,
+                },
+            )
+            .await;
+            let state = game.get_room_state("m-room-cs").await.unwrap();
+            assert_eq!(state.voting_sequence, VotingSequence::SmMedLgXl);
+
+            // Captain changes to YeaNea
+            game.process_client_message(
+                "m-room-cs",
+                ClientMessage::ChangeSequence {
+                    player_id: 0,
+                    sequence: VotingSequence::YeaNea,
+                },
+            )
+            .await;
+            let state = game.get_room_state("m-room-cs").await.unwrap();
+            assert_eq!(state.voting_sequence, VotingSequence::YeaNea);
+        }
+
+        /// Rule: after the captaincy is handed over, only the new captain (Bob)
+        /// may change the voting sequence; the former captain (Alice) is refused.
+        #[tokio::test]
+        async fn test_process_change_sequence_refused_for_non_captain() {
+            let game = new_game();
+            game.generate_new_room(Some("m-room-cs-nc")).await;
+            game.new_player("m-room-cs-nc").await; // id 0
+            game.new_player("m-room-cs-nc").await; // id 1
+
+            // Name the players
+            game.process_client_message(
+                "m-room-cs-nc",
+                ClientMessage::ChangeName {
+                    player_id: 0,
+                    name: "Alice".to_string(),
+                },
+            )
+            .await;
+            game.process_client_message(
+                "m-room-cs-nc",
+                ClientMessage::ChangeName {
+                    player_id: 1,
+                    name: "Bob".to_string(),
+                },
+            )
+            .await;
+
+            // Alice (id 0) is currently captain; she hands the captaincy to Bob
+            // (id 1) and moves to seat 3.
+            game.process_client_message(
+                "m-room-cs-nc",
+                ClientMessage::TransferCaptain {
+                    player_id: 0,
+                    new_captain_id: 1,
+                },
+            )
+            .await;
+            game.process_client_message(
+                "m-room-cs-nc",
+                ClientMessage::ChangeSeat {
+                    name: "Alice".to_string(),
+                    current_id: 0,
+                    requested_id: 3,
+                },
+            )
+            .await;
+
+            // Verify the seat change succeeded and captainship transferred.
+            let state = game.get_room_state("m-room-cs-nc").await.unwrap();
+            let alice = state
+                .players
                 .iter()
-                .any(|p| p.player_id == 0 && p.player_name == "Alice")
-        );
-        assert!(
-            pre.players
+                .find(|p| p.player_name == "Alice")
+                .unwrap();
+            assert_eq!(alice.player_id, 3, "Alice should now be at seat 3");
+            assert_eq!(
+                state.captain_id,
+                Some(1),
+                "Bob (id 1) should now be the captain"
+            );
+
+            // Alice (now at id 3) attempts to change the sequence – should be refused.
+            game.process_client_message(
+                "m-room-cs-nc",
+                ClientMessage::ChangeSequence {
+                    player_id: 3,
+                    sequence: VotingSequence::SmMedLgXl,
+                },
+            )
+            .await;
+            let state = game.get_room_state("m-room-cs-nc").await.unwrap();
+            assert_eq!(state.voting_sequence, VotingSequence::Fibonacci);
+
+            // Bob (id 1, the new captain) changes the sequence to Linear.
+            game.process_client_message(
+                "m-room-cs-nc",
+                ClientMessage::ChangeSequence {
+                    player_id: 1,
+                    sequence: VotingSequence::Linear,
+                },
+            )
+            .await;
+            let state = game.get_room_state("m-room-cs-nc").await.unwrap();
+            assert_eq!(state.voting_sequence, VotingSequence::Linear);
+        }
+
+        /// Rule: a delegate trying to change the sequence is told only the
+        /// captain can.
+        #[tokio::test]
+        async fn test_change_sequence_by_delegate_reports_error() {
+            let game = new_game();
+            let room = "m-room-cs-error";
+            moderated_room(&game, room).await;
+
+            let result = game
+                .process_connection_message(
+                    room,
+                    "conn-b",
+                    ClientMessage::ChangeSequence {
+                        player_id: 1,
+                        sequence: VotingSequence::Linear,
+                    },
+                )
+                .await;
+            assert_eq!(
+                result,
+                Err("Only the captain can change the voting sequence".to_string())
+            );
+            let state = game.get_room_state(room).await.unwrap();
+            assert_eq!(state.voting_sequence, VotingSequence::Fibonacci);
+        }
+
+        fn cards(labels: &[&str]) -> Vec<Card> {
+            labels
                 .iter()
-                .any(|p| p.player_id == 1 && p.player_name == "Bob")
-        );
+                .enumerate()
+                .map(|(i, label)| Card {
+                    value: i as u8 + 1,
+                    label: label.to_string(),
+                })
+                .collect()
+        }
 
-        // Alice moves from seat 0 to seat 3
-        game.process_client_message(
-            "s-room-move",
-            ClientMessage::ChangeSeat {
-                name: "Alice".to_string(),
-                current_id: 0,
-                requested_id: 3,
-            },
-        )
-        .await;
-
-        let state = game.get_room_state("s-room-move").await.unwrap();
-        // Alice should now be at seat 3
-        let alice = state.players.iter().find(|p| p.player_id == 3).unwrap();
-        assert_eq!(alice.player_name, "Alice");
-        // Bob should remain at seat 1
-        let bob = state.players.iter().find(|p| p.player_id == 1).unwrap();
-        assert_eq!(bob.player_name, "Bob");
-    }
+        /// Rule: the captain may define a custom sequence of labeled cards, which
+        /// is stored in the room state exactly as defined (labels trimmed).
+        #[tokio::test]
+        async fn test_custom_sequence_accepted_from_captain() {
+            let game = new_game();
+            game.new_player_with_connection("m-room-custom", "conn-a".to_string())
+                .await; // id 0 – captain
+            let result = game
+                .process_connection_message(
+                    "m-room-custom",
+                    "conn-a",
+                    ClientMessage::ChangeSequence {
+                        player_id: 0,
+                        sequence: VotingSequence::Custom(cards(&[
+                            "0", "½", "1", "2", "3", "5", "8", " ? ", "☕",
+                        ])),
+                    },
+                )
+                .await;
+            assert!(result.is_ok());
+
+            let state = game.get_room_state("m-room-custom").await.unwrap();
+            let VotingSequence::Custom(stored) = state.voting_sequence else {
+                panic!("Expected a custom sequence");
+            };
+            assert_eq!(stored.len(), 9);
+            assert_eq!(stored[1].label, "½");
+            assert_eq!(stored[7].label, "?");
+        }
+
+        /// Rule: malformed custom sequences are rejected with a reason and the
+        /// previous sequence is kept.
+        #[tokio::test]
+        async fn test_custom_sequence_rejects_invalid_cards() {
+            let game = new_game();
+            game.new_player_with_connection("m-room-custom-bad", "conn-a".to_string())
+                .await; // id 0 – captain
+
+            let invalid = [
+                Vec::new(),
+                cards(&["1", "1"]),
+                cards(&["1", ""]),
+                cards(&["much too long"]),
+                cards(&["x"; 17]),
+            ];
+            for custom in invalid {
+                let result = game
+                    .process_connection_message(
+                        "m-room-custom-bad",
+                        "conn-a",
+                        ClientMessage::ChangeSequence {
+                            player_id: 0,
+                            sequence: VotingSequence::Custom(custom.clone()),
+                        },
+                    )
+                    .await;
+                assert!(result.is_err(), "{custom:?} must be rejected");
+            }
 
-    /// Rule: a ChangeSeat request targeting an occupied seat is silently
-    /// ignored; both players remain at their original seats.
-    #[tokio::test]
-    async fn test_change_seat_rejects_occupied_seat() {
-        let game = new_game();
-        game.generate_new_room(Some("s-room-occupied")).await;
-        game.new_player("s-room-occupied").await; // id 0
-        game.new_player("s-room-occupied").await; // id 1
-
-        // Name the players
-        game.process_client_message(
-            "s-room-occupied",
-            ClientMessage::ChangeName {
+            let state = game.get_room_state("m-room-custom-bad").await.unwrap();
+            assert_eq!(state.voting_sequence, VotingSequence::Fibonacci);
+        }
+
+        /// Rule: a vote that is not a card in the room's active sequence is
+        /// rejected and the player's previous vote is kept.
+        #[tokio::test]
+        async fn test_change_value_rejects_out_of_sequence_value() {
+            let game = new_game();
+            game.new_player_with_connection("m-room-oos", "conn-a".to_string())
+                .await; // id 0 – captain
+            for (sequence, value) in [
+                (VotingSequence::Fibonacci, 4),
+                (VotingSequence::YeaNea, 200),
+                (VotingSequence::Custom(cards(&["A", "B"])), 3),
+            ] {
+                game.process_connection_message(
+                    "m-room-oos",
+                    "conn-a",
+                    ClientMessage::ChangeSequence {
+                        player_id: 0,
+                        sequence,
+                    },
+                )
+                .await
+                .unwrap();
+                game.process_connection_message(
+                    "m-room-oos",
+                    "conn-a",
+                    ClientMessage::ChangeValue {
+                        player_id: 0,
+                        value: Vote::Card(1),
+                    },
+                )
+                .await
+                .unwrap();
+
+                let result = game
+                    .process_connection_message(
+                        "m-room-oos",
+                        "conn-a",
+                        ClientMessage::ChangeValue {
+                            player_id: 0,
+                            value: Vote::Card(value),
+                        },
+                    )
+                    .await;
+                assert!(result.is_err(), "{value} must be rejected");
+                let state = game.get_room_state("m-room-oos").await.unwrap();
+                assert_eq!(state.players[0].value, Some(Vote::Card(1)));
+            }
+        }
+
+        /// Rule: withdrawing a vote leaves the player unvoted, while a zero card
+        /// is rejected like any other card outside the sequence.
+        #[tokio::test]
+        async fn test_withdraw_vote() {
+            let game = new_game();
+            let room = "m-room-withdraw";
+            game.new_player_with_connection(room, "conn-a".to_string())
+                .await;
+            let send = async |message| {
+                game.process_connection_message(room, "conn-a", message)
+                    .await
+            };
+            send(ClientMessage::ChangeValue {
                 player_id: 0,
-                name: "Alice".to_string(),
-            },
-        )
-        .await;
-        game.process_client_message(
-            "s-room-occupied",
-            ClientMessage::ChangeName {
-                player_id: 1,
-                name: "Bob".to_string(),
-            },
-        )
-        .await;
-
-        // Alice attempts to take Bob's occupied seat
-        game.process_client_message(
-            "s-room-occupied",
-            ClientMessage::ChangeSeat {
-                name: "Alice".to_string(),
-                current_id: 0,
-                requested_id: 1, // seat 1 is Bob's
-            },
-        )
-        .await;
-
-        let state = game.get_room_state("s-room-occupied").await.unwrap();
-        // Alice must still be at seat 0
-        let alice = state.players.iter().find(|p| p.player_id == 0).unwrap();
-        assert_eq!(alice.player_name, "Alice");
-        // Bob must still be at seat 1
-        let bob = state.players.iter().find(|p| p.player_id == 1).unwrap();
-        assert_eq!(bob.player_name, "Bob");
-    }
+                value: Vote::Card(5),
+            })
+            .await
+            .unwrap();
+            assert!(
+                send(ClientMessage::ChangeValue {
+                    player_id: 0,
+                    value: Vote::Card(0),
+                })
+                .await
+                .is_err()
+            );
+            let state = game.get_room_state(room).await.unwrap();
+            assert_eq!(state.players[0].value, Some(Vote::Card(5)));
+
+            send(ClientMessage::WithdrawVote { player_id: 0 })
+                .await
+                .unwrap();
+            let state = game.get_room_state(room).await.unwrap();
+            assert_eq!(state.players[0].value, None);
+        }
 
-    /// Rule: seat IDs ≥ 12 fall in the spectator/overflow zone and must be
-    /// rejected; the requesting player stays at their current seat.
-    #[tokio::test]
-    async fn test_change_seat_rejects_overflow_seat() {
-        let game = new_game();
-        game.generate_new_room(Some("s-room-overflow")).await;
-        game.new_player("s-room-overflow").await; // id 0
+        /// Rule: switching sequence clears votes that are not cards in the new
+        /// sequence and keeps those that still are.
+        #[tokio::test]
+        async fn test_change_sequence_clears_votes_outside_new_sequence() {
+            let game = new_game();
+            game.new_player_with_connection("m-room-switch", "conn-a".to_string())
+                .await; // id 0 – captain
+            game.new_player_with_connection("m-room-switch", "conn-b".to_string())
+                .await; // id 1
+            for (connection_id, player_id, value) in [("conn-a", 0, 2), ("conn-b", 1, 13)] {
+                game.process_connection_message(
+                    "m-room-switch",
+                    connection_id,
+                    ClientMessage::ChangeValue {
+                        player_id,
+                        value: Vote::Card(value),
+                    },
+                )
+                .await
+                .unwrap();
+            }
 
-        game.process_client_message(
-            "s-room-overflow",
-            ClientMessage::ChangeSeat {
-                name: "Delegate Unknown".to_string(),
-                current_id: 0,
-                requested_id: 12,
-            },
-        )
-        .await;
-
-        let state = game.get_room_state("s-room-overflow").await.unwrap();
-        assert!(state.players.iter().any(|p| p.player_id == 0));
-        assert!(state.players.iter().all(|p| p.player_id != 12));
-    }
+            game.process_connection_message(
+                "m-room-switch",
+                "conn-a",
+                ClientMessage::ChangeSequence {
+                    player_id: 0,
+                    sequence: VotingSequence::YeaNea,
+                },
+            )
+            .await
+            .unwrap();
 
-    /// Rule: when a player changes seats, the name supplied in the ChangeSeat
-    /// message is assigned to the new seat so the player's identity follows
-    /// them.
-    #[tokio::test]
-    async fn test_change_seat_preserves_player_name_and_value() {
-        let game = new_game();
-        game.generate_new_room(Some("s-room-preserve")).await;
-        game.new_player("s-room-preserve").await; // id 0
-        game.new_player("s-room-preserve").await; // id 1
-        game.process_client_message(
-            "s-room-preserve",
-            ClientMessage::ChangeName {
+            let state = game.get_room_state("m-room-switch").await.unwrap();
+            assert_eq!(state.players[0].value, Some(Vote::Card(2)));
+            assert_eq!(state.players[1].value, None);
+        }
+
+        /// Rule: abstain, "?" and coffee votes are accepted under any sequence and
+        /// count as voted.
+        #[tokio::test]
+        async fn test_special_votes_are_accepted() {
+            let game = new_game();
+            game.new_player_with_connection("m-room-special", "conn-a".to_string())
+                .await;
+            for vote in [Vote::Abstain, Vote::Unknown, Vote::Coffee] {
+                game.process_connection_message(
+                    "m-room-special",
+                    "conn-a",
+                    ClientMessage::ChangeValue {
+                        player_id: 0,
+                        value: vote,
+                    },
+                )
+                .await
+                .unwrap();
+                let state = game.get_room_state("m-room-special").await.unwrap();
+                let view = state_for_connection(&state, "conn-b");
+                assert_eq!(state.players[0].value, Some(vote));
+                assert!(view.players[0].has_voted);
+            }
+        }
+
+        /// Rule: on the wire a card vote is its number and any other vote is its
+        /// name, so older clients sending plain numbers keep working.
+        #[test]
+        fn test_vote_wire_format() {
+            assert_eq!(serde_json::to_string(&Vote::Card(5)).unwrap(), "5");
+            assert_eq!(serde_json::to_string(&Vote::Coffee).unwrap(), "\"Coffee\"");
+            let message: ClientMessage =
+                serde_json::from_str(r#"{"type":"ChangeValue","player_id":0,"value":"Unknown"}"#)
+                    .unwrap();
+            assert!(matches!(
+                message,
+                ClientMessage::ChangeValue {
+                    value: Vote::Unknown,
+                    ..
+                }
+            ));
+            assert!(serde_json::from_str::<Vote>(r#""Maybe""#).is_err());
+        }
+
+        /// Rule: the built-in card sets match what the bundled client renders.
+        #[test]
+        fn test_builtin_sequence_cards() {
+            let values = |sequence: VotingSequence| -> Vec<u8> {
+                sequence.cards().iter().map(|c| c.value).collect()
+            };
+            assert_eq!(values(VotingSequence::Fibonacci), [1, 2, 3, 5, 8, 13, 21]);
+            assert_eq!(values(VotingSequence::Linear), (1..=10).collect::<Vec<_>>());
+            assert_eq!(values(VotingSequence::SmMedLgXl), [1, 2, 3, 4]);
+            assert_eq!(values(VotingSequence::YeaNea), [1, 2]);
+        }
+
+        // ── Agenda and history ───────────────────────────────────────────────────
+
+        /// Rule: only the captain may add, reorder or remove agenda topics.
+        #[tokio::test]
+        async fn test_agenda_changes_require_captain() {
+            let game = new_game();
+            game.new_player_with_connection("m-room-agenda", "conn-a".to_string())
+                .await; // id 0 – captain
+            game.new_player_with_connection("m-room-agenda", "conn-b".to_string())
+                .await; // id 1
+
+            let add = |player_id| ClientMessage::AddTopic {
+                player_id,
+                title: "Budget".to_string(),
+                link: None,
+            };
+            assert!(
+                game.process_connection_message("m-room-agenda", "conn-b", add(1))
+                    .await
+                    .is_err()
+            );
+            game.process_connection_message("m-room-agenda", "conn-a", add(0))
+                .await
+                .unwrap();
+
+            let state = game.get_room_state("m-room-agenda").await.unwrap();
+            assert_eq!(state.agenda.len(), 1);
+            assert_eq!(state.agenda[0].title, "Budget");
+        }
+
+        /// Rule: resetting a revealed round appends it to the history, and
+        /// advancing closes the round and moves to the next agenda topic.
+        #[tokio::test]
+        async fn test_rounds_are_recorded_in_history() {
+            let game = new_game();
+            game.new_player_with_connection("m-room-history", "conn-a".to_string())
+                .await; // id 0 – captain
+            for title in ["First", "Second"] {
+                game.process_connection_message(
+                    "m-room-history",
+                    "conn-a",
+                    ClientMessage::AddTopic {
+                        player_id: 0,
+                        title: title.to_string(),
+                        link: None,
+                    },
+                )
+                .await
+                .unwrap();
+            }
+            let send = async |message| {
+                game.process_connection_message("m-room-history", "conn-a", message)
+                    .await
+                    .unwrap()
+            };
+
+            send(ClientMessage::AdvanceTopic { player_id: 0 }).await;
+            send(ClientMessage::ChangeValue {
                 player_id: 0,
-                name: "Alice".to_string(),
-            },
-        )
-        .await;
-        game.process_client_message(
-            "s-room-preserve",
-            ClientMessage::ChangeName {
-                player_id: 1,
-                name: "Bob".to_string(),
-            },
-        )
-        .await;
-
-        // Alice moves to seat 3
-        game.process_client_message(
-            "s-room-preserve",
-            ClientMessage::ChangeSeat {
-                name: "Alice".to_string(),
-                current_id: 0,
-                requested_id: 3,
-            },
-        )
-        .await;
-
-        let state = game.get_room_state("s-room-preserve").await.unwrap();
-        // The player at seat 3 should be Alice
-        let alice = state.players.iter().find(|p| p.player_id == 3).unwrap();
-        assert_eq!(alice.player_name, "Alice");
-    }
+                value: Vote::Card(5),
+            })
+            .await;
+            send(ClientMessage::RevealNumbers { value: true }).await;
+            send(ClientMessage::RevealNumbers { value: false }).await;
 
-    /// Rule: captainship belongs to the active player with the lowest seat ID.
-    /// When a captain moves to a higher-numbered seat, the player who now holds
-    /// the lowest ID becomes the new captain.  Existing votes are unaffected by
-    /// the transfer.
-    #[tokio::test]
-    async fn test_captain_transfers_on_seat_change() {
-        let game = new_game();
-        game.generate_new_room(Some("s-room-captain")).await;
-        game.new_player("s-room-captain").await; // id 0
-        game.new_player("s-room-captain").await; // id 1
-
-        // Name the players
-        game.process_client_message(
-            "s-room-captain",
-            ClientMessage::ChangeName {
+            send(ClientMessage::ChangeValue {
                 player_id: 0,
-                name: "Alice".to_string(),
-            },
-        )
-        .await;
-        game.process_client_message(
-            "s-room-captain",
-            ClientMessage::ChangeName {
-                player_id: 1,
-                name: "Bob".to_string(),
-            },
-        )
-        .await;
-
-        // Alice (id 0) is captain; give her a vote so we can prove it survives
-        game.process_client_message(
-            "s-room-captain",
-            ClientMessage::ChangeValue {
+                value: Vote::Card(8),
+            })
+            .await;
+            send(ClientMessage::RevealNumbers { value: true }).await;
+            send(ClientMessage::AdvanceTopic { player_id: 0 }).await;
+
+            let state = game.get_room_state("m-room-history").await.unwrap();
+            assert_eq!(state.history.len(), 2);
+            assert_eq!(state.history[0].votes[0].value, Some(Vote::Card(5)));
+            assert_eq!(state.history[1].votes[0].value, Some(Vote::Card(8)));
+            for record in state.history.iter() {
+                assert_eq!(record.topic.as_ref().unwrap().title, "First");
+            }
+            assert_eq!(state.current_topic.unwrap().title, "Second");
+            assert!(state.agenda.is_empty());
+            assert!(!state.all_revealed);
+        }
+
+        /// Rule: advancing past a topic that was never revealed puts it back at
+        /// the end of the agenda instead of dropping it.
+        #[tokio::test]
+        async fn test_advance_keeps_unrevealed_topic() {
+            let game = new_game();
+            let room = "m-room-skip";
+            game.new_player_with_connection(room, "conn-a".to_string())
+                .await; // id 0 – captain
+            let send = async |message| {
+                game.process_connection_message(room, "conn-a", message)
+                    .await
+                    .unwrap()
+            };
+            for title in ["First", "Second", "Third"] {
+                send(ClientMessage::AddTopic {
+                    player_id: 0,
+                    title: title.to_string(),
+                    link: None,
+                })
+                .await;
+            }
+
+            send(ClientMessage::AdvanceTopic { player_id: 0 }).await;
+            send(ClientMessage::AdvanceTopic { player_id: 0 }).await;
+
+            let state = game.get_room_state(room).await.unwrap();
+            assert_eq!(state.current_topic.unwrap().title, "Second");
+            let agenda: Vec<_> = state.agenda.iter().map(|t| t.title.as_str()).collect();
+            assert_eq!(agenda, ["Third", "First"]);
+            assert!(state.history.is_empty());
+
+            // Skipping again moves on to the next topic rather than back to the
+            // one skipped before.
+            send(ClientMessage::AdvanceTopic { player_id: 0 }).await;
+            let state = game.get_room_state(room).await.unwrap();
+            assert_eq!(state.current_topic.unwrap().title, "Third");
+            let agenda: Vec<_> = state.agenda.iter().map(|t| t.title.as_str()).collect();
+            assert_eq!(agenda, ["First", "Second"]);
+        }
+
+        /// Rule: hiding votes that were never revealed does not create a history
+        /// entry.
+        #[tokio::test]
+        async fn test_unrevealed_round_is_not_recorded() {
+            let game = new_game();
+            game.new_player_with_connection("m-room-no-history", "conn-a".to_string())
+                .await;
+            game.process_connection_message(
+                "m-room-no-history",
+                "conn-a",
+                ClientMessage::RevealNumbers { value: false },
+            )
+            .await
+            .unwrap();
+            let state = game.get_room_state("m-room-no-history").await.unwrap();
+            assert!(state.history.is_empty());
+        }
+
+        /// Rule: a pasted backlog is appended to the agenda, and a backlog with
+        /// invalid rows is rejected as a whole with one line per row.
+        #[tokio::test]
+        async fn test_import_topics_message() {
+            let game = new_game();
+            game.new_player_with_connection("m-room-import", "conn-a".to_string())
+                .await; // id 0 – captain
+            let import = |data: &str| ClientMessage::ImportTopics {
                 player_id: 0,
-                value: 5,
-            },
-        )
-        .await;
-
-        // Alice moves to seat 3; Bob (id 1) now holds the lowest ID
-        game.process_client_message(
-            "s-room-captain",
-            ClientMessage::ChangeSeat {
-                name: "Alice".to_string(),
-                current_id: 0,
-                requested_id: 3,
-            },
-        )
-        .await;
-
-        let state = game.get_room_state("s-room-captain").await.unwrap();
-        // Bob (id 1) is now the captain — lowest active ID
-        let min_id = state
-            .players
-            .iter()
-            .filter(|p| p.player_id < 100)
-            .map(|p| p.player_id)
-            .min()
+                data: data.to_string(),
+            };
+
+            let error = game
+                .process_connection_message("m-room-import", "conn-a", import("id,title\n1,\n2,\n"))
+                .await
+                .unwrap_err();
+            assert_eq!(error.lines().count(), 2);
+
+            game.process_connection_message(
+                "m-room-import",
+                "conn-a",
+                import("id,title\n1,Login\n"),
+            )
+            .await
             .unwrap();
-        assert_eq!(min_id, 1);
-        let bob = state.players.iter().find(|p| p.player_id == 1).unwrap();
-        assert_eq!(bob.player_name, "Bob");
-
-        // Alice retains her vote at the new seat
-        let alice = state.players.iter().find(|p| p.player_id == 3).unwrap();
-        assert_eq!(alice.player_name, "Alice");
-        assert_eq!(alice.value, Some(5));
-    }
+            let state = game.get_room_state("m-room-import").await.unwrap();
+            assert_eq!(state.agenda.len(), 1);
+            assert_eq!(state.agenda[0].key.as_deref(), Some("1"));
+        }
 
-    // ── Voting sequence ──────────────────────────────────────────────────────
+        /// Rule: importing over HTTP requires the captain's resume token.
+        #[tokio::test]
+        async fn test_import_backlog_requires_captain_token() {
+            let game = new_game();
+            let (_, captain_token, _) = game
+                .join_with_connection(
+                    "m-room-import-http",
+                    "conn-a".to_string(),
+                    &query(None, None),
+                )
+                .await
+                .unwrap();
+            let (_, delegate_token, _) = game
+                .join_with_connection(
+                    "m-room-import-http",
+                    "conn-b".to_string(),
+                    &query(None, None),
+                )
+                .await
+                .unwrap();
+            let backlog = r#"[{"title": "Login"}]"#;
+
+            for token in [delegate_token.as_str(), "", "bogus"] {
+                assert_eq!(
+                    game.import_backlog("m-room-import-http", token, backlog)
+                        .await,
+                    Err(ImportError::Forbidden)
+                );
+            }
+            assert_eq!(
+                game.import_backlog("m-room-missing", &captain_token, backlog)
+                    .await,
+                Err(ImportError::UnknownRoom)
+            );
+            assert_eq!(
+                game.import_backlog("m-room-import-http", &captain_token, backlog)
+                    .await,
+                Ok(1)
+            );
+        }
 
-    /// Rule: only the captain (lowest active seat ID) may change the voting
-    /// sequence.  The sequence is updated immediately and broadcast to all
-    /// clients via the next state update.
-    #[tokio::test]
-    async fn test_process_change_sequence_updates_voting_sequence() {
-        let game = new_game();
-        game.generate_new_room(Some("m-room-cs")).await;
-        game.new_player("m-room-cs").await; // id 0 – captain
+        // ── Auto-reveal ──────────────────────────────────────────────────────────
+
+        async fn enable_auto_reveal(game: &TestGame, room: &str, countdown_secs: u64) {
+            game.process_connection_message(
+                room,
+                "conn-a",
+                ClientMessage::ChangeAutoReveal {
+                    player_id: 0,
+                    auto_reveal: AutoReveal {
+                        enabled: true,
+                        countdown_secs,
+                    },
+                },
+            )
+            .await
+            .unwrap();
+        }
 
-        // Default should be Fibonacci
-        let initial_state = game.get_room_state("m-room-cs").await.unwrap();
-        assert_eq!(initial_state.voting_sequence, VotingSequence::Fibonacci);
+        async fn vote(
+            game: &TestGame,
+            room: &str,
+            connection_id: &str,
+            player_id: usize,
+            value: Vote,
+        ) {
+            game.process_connection_message(
+                room,
+                connection_id,
+                ClientMessage::ChangeValue { player_id, value },
+            )
+            .await
+            .unwrap();
+        }
 
-        // Captain (id 0) changes to Linear
-        game.process_client_message(
-            "m-room-cs",
-            ClientMessage::ChangeSequence {
-                player_id: 0,
-                sequence: VotingSequence::Linear,
-            },
-        )
-        .await;
-        let state = game.get_room_state("m-room-cs").await.unwrap();
-        assert_eq!(state.voting_sequence, VotingSequence::Linear);
-
-        // Captain changes to SmMedLgXl
-        game.process_client_message(
-            "m-room-cs",
-            ClientMessage::ChangeSequence {
-                player_id: 0,
-                sequence: VotingSequence::SmMedLgXl,
-            },
-        )
-        .await;
-        let state = game.get_room_state("m-room-cs").await.unwrap();
-        assert_eq!(state.voting_sequence, VotingSequence::SmMedLgXl);
-
-        // Captain changes to YeaNea
-        game.process_client_message(
-            "m-room-cs",
-            ClientMessage::ChangeSequence {
+        /// Rule: with auto-reveal on and no countdown, the round is revealed as
+        /// soon as the last delegate votes; spectators are not waited for.
+        #[tokio::test]
+        async fn test_auto_reveal_when_every_delegate_has_voted() {
+            let game = new_game();
+            let room = "m-room-auto";
+            game.new_player_with_connection(room, "conn-a".to_string())
+                .await; // id 0 – captain
+            game.new_player_with_connection(room, "conn-b".to_string())
+                .await; // id 1
+            game.rooms
+                .update(room, |slot| {
+                    slot.as_mut().unwrap().players.push(PlayerState {
+                        player_id: 100,
+                        ..Default::default()
+                    })
+                })
+                .await;
+            enable_auto_reveal(&game, room, 0).await;
+
+            vote(&game, room, "conn-a", 0, Vote::Card(5)).await;
+            assert!(!game.get_room_state(room).await.unwrap().all_revealed);
+
+            vote(&game, room, "conn-b", 1, Vote::Coffee).await;
+            let state = game.get_room_state(room).await.unwrap();
+            assert!(state.all_revealed);
+            assert!(state.revealed_at.is_some());
+            assert!(state.round_summary.is_some());
+        }
+
+        /// Rule: with a countdown, the last vote sets a deadline instead of
+        /// revealing, and withdrawing a vote calls it off.
+        #[tokio::test]
+        async fn test_auto_reveal_countdown_is_called_off_by_withdrawal() {
+            let game = new_game();
+            let room = "m-room-auto-countdown";
+            game.new_player_with_connection(room, "conn-a".to_string())
+                .await; // id 0 – captain
+            enable_auto_reveal(&game, room, 5).await;
+
+            vote(&game, room, "conn-a", 0, Vote::Card(3)).await;
+            let state = game.get_room_state(room).await.unwrap();
+            assert!(!state.all_revealed);
+            let at = state.auto_reveal_at.expect("countdown must start");
+            assert!(at >= agenda::now() + 4);
+
+            game.process_connection_message(
+                room,
+                "conn-a",
+                ClientMessage::WithdrawVote { player_id: 0 },
+            )
+            .await
+            .unwrap();
+            let state = game.get_room_state(room).await.unwrap();
+            assert_eq!(state.auto_reveal_at, None);
+            assert!(!game.fire_deadline(room, at).await);
+        }
+
+        /// Rule: a delegate joining during a countdown calls it off, and a
+        /// deadline that runs out while a delegate has not voted does not reveal.
+        #[tokio::test]
+        async fn test_auto_reveal_countdown_is_called_off_by_join() {
+            let game = new_game();
+            let room = "m-room-auto-join";
+            game.new_player_with_connection(room, "conn-a".to_string())
+                .await; // id 0 – captain
+            enable_auto_reveal(&game, room, 1).await;
+            vote(&game, room, "conn-a", 0, Vote::Card(3)).await;
+            let at = game
+                .get_room_state(room)
+                .await
+                .unwrap()
+                .auto_reveal_at
+                .expect("countdown must start");
+
+            game.new_player_with_connection(room, "conn-b".to_string())
+                .await; // id 1
+            let state = game.get_room_state(room).await.unwrap();
+            assert_eq!(state.auto_reveal_at, None);
+            assert!(!game.fire_deadline(room, at).await);
+
+            // A deadline left over from before the join must not reveal either.
+            game.rooms
+                .update(room, |slot| slot.as_mut().unwrap().auto_reveal_at = Some(1))
+                .await;
+            assert!(game.fire_deadline(room, 1).await);
+            let state = game.get_room_state(room).await.unwrap();
+            assert!(!state.all_revealed);
+            assert_eq!(state.auto_reveal_at, None);
+            assert_eq!(state.players[1].value, None);
+        }
+
+        /// Rule: a pending countdown reveals the room once it has run out, and
+        /// fires only once.
+        #[tokio::test]
+        async fn test_fire_auto_reveal() {
+            let game = new_game();
+            let room = "m-room-auto-fire";
+            game.new_player_with_connection(room, "conn-a".to_string())
+                .await;
+            enable_auto_reveal(&game, room, 5).await;
+            vote(&game, room, "conn-a", 0, Vote::Card(8)).await;
+            game.rooms
+                .update(room, |slot| slot.as_mut().unwrap().auto_reveal_at = Some(1))
+                .await;
+
+            assert!(game.claim_deadline(room, 1).await);
+            assert!(!game.claim_deadline(room, 1).await, "already scheduled");
+            assert!(game.fire_deadline(room, 1).await);
+            assert!(!game.fire_deadline(room, 1).await, "nothing left to fire");
+
+            let state = game.get_room_state(room).await.unwrap();
+            assert!(state.all_revealed);
+            assert_eq!(state.auto_reveal_at, None);
+            assert_eq!(state.round_summary.unwrap().vote_count, 1);
+        }
+
+        /// Rule: only the captain may change the setting, and the countdown is
+        /// capped.
+        #[tokio::test]
+        async fn test_auto_reveal_setting_is_validated() {
+            let game = new_game();
+            let room = "m-room-auto-setting";
+            game.new_player_with_connection(room, "conn-a".to_string())
+                .await; // id 0 – captain
+            game.new_player_with_connection(room, "conn-b".to_string())
+                .await; // id 1
+
+            let change = |player_id, countdown_secs| ClientMessage::ChangeAutoReveal {
+                player_id,
+                auto_reveal: AutoReveal {
+                    enabled: true,
+                    countdown_secs,
+                },
+            };
+            assert!(
+                game.process_connection_message(room, "conn-b", change(1, 3))
+                    .await
+                    .is_err()
+            );
+            assert!(
+                game.process_connection_message(room, "conn-a", change(0, 60))
+                    .await
+                    .is_err()
+            );
+            game.process_connection_message(room, "conn-a", change(0, 3))
+                .await
+                .unwrap();
+
+            let state = game.get_room_state(room).await.unwrap();
+            assert_eq!(state.auto_reveal.countdown_secs, 3);
+        }
+
+        // ── Timer ────────────────────────────────────────────────────────────────
+
+        async fn start_timer(game: &TestGame, room: &str, seconds: u64, reveal_on_expiry: bool) {
+            game.process_connection_message(
+                room,
+                "conn-a",
+                ClientMessage::StartTimer {
+                    player_id: 0,
+                    seconds,
+                    reveal_on_expiry,
+                },
+            )
+            .await
+            .unwrap();
+        }
+
+        /// Rule: the captain starts a timer whose deadline and remaining time are
+        /// part of the room state, so late joiners see the same countdown.
+        #[tokio::test]
+        async fn test_start_timer() {
+            let game = new_game();
+            let room = "m-room-timer";
+            game.new_player_with_connection(room, "conn-a".to_string())
+                .await; // id 0 – captain
+            start_timer(&game, room, 90, false).await;
+
+            let state = game.get_room_state(room).await.unwrap();
+            let timer = state.timer.clone().expect("timer must be running");
+            assert_eq!(timer.ends_at, timer.started_at + 90);
+            assert_eq!(next_deadline(&state), Some(timer.ends_at));
+
+            let view = state_for_connection(&state, "conn-late");
+            let remaining = view.timer.unwrap().remaining_secs;
+            assert!((89..=90).contains(&remaining));
+        }
+
+        /// Rule: only the captain may start or stop the timer, and its length is
+        /// bounded.
+        #[tokio::test]
+        async fn test_timer_is_validated() {
+            let game = new_game();
+            let room = "m-room-timer-rules";
+            game.new_player_with_connection(room, "conn-a".to_string())
+                .await; // id 0 – captain
+            game.new_player_with_connection(room, "conn-b".to_string())
+                .await; // id 1
+
+            let start = |player_id, seconds| ClientMessage::StartTimer {
+                player_id,
+                seconds,
+                reveal_on_expiry: false,
+            };
+            for (connection_id, message) in [
+                ("conn-b", start(1, 60)),
+                ("conn-a", start(0, 0)),
+                ("conn-a", start(0, 24 * 60 * 60)),
+            ] {
+                assert!(
+                    game.process_connection_message(room, connection_id, message)
+                        .await
+                        .is_err()
+                );
+            }
+
+            start_timer(&game, room, 60, false).await;
+            assert!(
+                game.process_connection_message(
+                    room,
+                    "conn-b",
+                    ClientMessage::StopTimer { player_id: 1 }
+                )
+                .await
+                .is_err()
+            );
+            game.process_connection_message(
+                room,
+                "conn-a",
+                ClientMessage::StopTimer { player_id: 0 },
+            )
+            .await
+            .unwrap();
+            assert!(game.get_room_state(room).await.unwrap().timer.is_none());
+        }
+
+        /// Rule: an expired timer is flagged rather than removed, and reveals the
+        /// round only when asked to.
+        #[tokio::test]
+        async fn test_timer_expiry() {
+            let game = new_game();
+            for (room, reveal_on_expiry) in
+                [("m-room-timer-end", false), ("m-room-timer-reveal", true)]
+            {
+                game.new_player_with_connection(room, "conn-a".to_string())
+                    .await;
+                start_timer(&game, room, 60, reveal_on_expiry).await;
+                game.rooms
+                    .update(room, |slot| {
+                        slot.as_mut().unwrap().timer.as_mut().unwrap().ends_at = 1
+                    })
+                    .await;
+
+                assert!(game.fire_deadline(room, 1).await);
+                let state = game.get_room_state(room).await.unwrap();
+                assert!(state.timer.unwrap().expired);
+                assert_eq!(state.all_revealed, reveal_on_expiry);
+                assert!(!game.fire_deadline(room, 1).await, "expires only once");
+            }
+        }
+
+        /// Rule: starting a new round stops the timer.
+        #[tokio::test]
+        async fn test_new_round_clears_timer() {
+            let game = new_game();
+            let room = "m-room-timer-reset";
+            game.new_player_with_connection(room, "conn-a".to_string())
+                .await;
+            start_timer(&game, room, 60, true).await;
+            game.process_client_message(room, ClientMessage::StartNewRound)
+                .await;
+            assert!(game.get_room_state(room).await.unwrap().timer.is_none());
+        }
+
+        // ── Moderation ───────────────────────────────────────────────────────────
+
+        async fn moderated_room(game: &TestGame, room: &str) {
+            game.new_player_with_connection(room, "conn-a".to_string())
+                .await; // id 0 – captain
+            game.new_player_with_connection(room, "conn-b".to_string())
+                .await; // id 1
+        }
+
+        /// Rule: the captain can kick another player, who leaves the room; other
+        /// players and the captain themselves cannot be kicked by a delegate.
+        #[tokio::test]
+        async fn test_kick_player() {
+            let game = new_game();
+            let room = "mod-room-kick";
+            moderated_room(&game, room).await;
+
+            let kick = |player_id, target_id| ClientMessage::KickPlayer {
+                player_id,
+                target_id,
+            };
+            for (connection_id, message) in [
+                ("conn-b", kick(1, 0)),
+                ("conn-a", kick(0, 0)),
+                ("conn-a", kick(0, 7)),
+            ] {
+                assert!(
+                    game.process_connection_message(room, connection_id, message)
+                        .await
+                        .is_err()
+                );
+            }
+
+            let removed = game
+                .process_connection_message(room, "conn-a", kick(0, 1))
+                .await
+                .unwrap();
+            assert_eq!(removed.as_deref(), Some("conn-b"));
+            let state = game.get_room_state(room).await.unwrap();
+            assert!(state.players.iter().all(|p| p.connection_id != "conn-b"));
+        }
+
+        /// Rule: a demoted delegate becomes a benched spectator who cannot take a
+        /// seat again by themselves, nor be promoted automatically.
+        #[tokio::test]
+        async fn test_demote_player() {
+            let game = new_game();
+            let room = "mod-room-demote";
+            moderated_room(&game, room).await;
+            vote(&game, room, "conn-b", 1, Vote::Card(5)).await;
+
+            game.process_connection_message(
+                room,
+                "conn-a",
+                ClientMessage::DemotePlayer {
+                    player_id: 0,
+                    target_id: 1,
+                },
+            )
+            .await
+            .unwrap();
+            let state = game.get_room_state(room).await.unwrap();
+            let demoted = state.players.iter().find(|p| p.player_id == 100).unwrap();
+            assert!(demoted.benched);
+            assert_eq!(demoted.value, None);
+            assert_eq!(state.notify_changes[0].new_id, 100);
+
+            let _ = game
+                .process_connection_message(
+                    room,
+                    "conn-b",
+                    ClientMessage::ChangeSeat {
+                        name: "Troll".to_string(),
+                        current_id: 100,
+                        requested_id: 1,
+                    },
+                )
+                .await;
+            let state = game.get_room_state(room).await.unwrap();
+            assert!(state.players.iter().any(|p| p.player_id == 100));
+            assert_eq!(game.find_player_in_waiting(&state.players), None);
+        }
+
+        /// Rule: the seat a demoted delegate frees goes to the first spectator
+        /// still waiting for one, and both moves are announced.
+        #[tokio::test]
+        async fn test_demote_seats_waiting_spectator() {
+            let game = TestGame::new(&Config {
+                max_room_size: 2,
+                ..Config::default()
+            });
+            let room = "mod-room-demote-backfill";
+            moderated_room(&game, room).await;
+            game.new_player_with_connection(room, "conn-c".to_string())
+                .await; // id 100
+
+            game.process_connection_message(
+                room,
+                "conn-a",
+                ClientMessage::DemotePlayer {
+                    player_id: 0,
+                    target_id: 1,
+                },
+            )
+            .await
+            .unwrap();
+            let state = game.get_room_state(room).await.unwrap();
+            let seat_of = |connection_id: &str| {
+                state
+                    .players
+                    .iter()
+                    .find(|p| p.connection_id == connection_id)
+                    .map(|p| (p.player_id, p.benched))
+            };
+            assert_eq!(seat_of("conn-b"), Some((101, true)));
+            assert_eq!(seat_of("conn-c"), Some((1, false)));
+            assert_eq!(
+                state.notify_changes,
+                vec![
+                    NotifyChange {
+                        current_id: 1,
+                        new_id: 101,
+                    },
+                    NotifyChange {
+                        current_id: 100,
+                        new_id: 1,
+                    },
+                ]
+            );
+        }
+
+        /// Rule: the captain can seat a chosen spectator, in a given free seat or
+        /// the lowest one, which also lifts a bench.
+        #[tokio::test]
+        async fn test_promote_spectator() {
+            let game = TestGame::new(&Config {
+                max_room_size: 2,
+                ..Config::default()
+            });
+            let room = "mod-room-promote";
+            moderated_room(&game, room).await;
+            game.new_player_with_connection(room, "conn-c".to_string())
+                .await; // id 100
+
+            let promote = |target_id, seat| ClientMessage::PromoteSpectator {
                 player_id: 0,
-                sequence: VotingSequence::YeaNea,
-            },
-        )
-        .await;
-        let state = game.get_room_state("m-room-cs").await.unwrap();
-        assert_eq!(state.voting_sequence, VotingSequence::YeaNea);
-    }
+                target_id,
+                seat,
+            };
+            assert!(
+                game.process_connection_message(room, "conn-a", promote(100, None))
+                    .await
+                    .is_err(),
+                "every seat is taken"
+            );
 
-    /// Rule: captainship transfers when a player moves to a higher-numbered
-    /// seat.  After the transfer, only the new captain (Bob) may change the
-    /// voting sequence; the former captain (Alice) is ignored.
-    #[tokio::test]
-    async fn test_process_change_sequence_ignored_for_non_captain() {
-        let game = new_game();
-        game.generate_new_room(Some("m-room-cs-nc")).await;
-        game.new_player("m-room-cs-nc").await; // id 0
-        game.new_player("m-room-cs-nc").await; // id 1
-
-        // Name the players
-        game.process_client_message(
-            "m-room-cs-nc",
-            ClientMessage::ChangeName {
+            let demote = ClientMessage::DemotePlayer {
                 player_id: 0,
-                name: "Alice".to_string(),
-            },
-        )
-        .await;
-        game.process_client_message(
-            "m-room-cs-nc",
-            ClientMessage::ChangeName {
-                player_id: 1,
-                name: "Bob".to_string(),
-            },
-        )
-        .await;
-
-        // Alice (id 0) is currently captain; move her to seat 3 so Bob (id 1)
-        // becomes the new captain.
-        game.process_client_message(
-            "m-room-cs-nc",
-            ClientMessage::ChangeSeat {
-                name: "Alice".to_string(),
-                current_id: 0,
-                requested_id: 3,
-            },
-        )
-        .await;
-
-        // Verify the seat change succeeded and captainship transferred.
-        let state = game.get_room_state("m-room-cs-nc").await.unwrap();
-        let alice = state
-            .players
-            .iter()
-            .find(|p| p.player_name == "Alice")
-            .unwrap();
-        assert_eq!(alice.player_id, 3, "Alice should now be at seat 3");
-        let captain_id = state
-            .players
-            .iter()
-            .filter(|p| p.player_id < 100)
-            .map(|p| p.player_id)
-            .min()
+                target_id: 1,
+            };
+            // conn-b is benched as 101 and conn-c takes seat 1, then conn-c is
+            // benched as 100 with nobody left waiting for the seat.
+            for _ in 0..2 {
+                game.process_connection_message(room, "conn-a", demote.clone())
+                    .await
+                    .unwrap();
+            }
+            assert!(
+                game.process_connection_message(room, "conn-a", promote(101, Some(0)))
+                    .await
+                    .is_err(),
+                "seat 0 is taken"
+            );
+            game.process_connection_message(room, "conn-a", promote(101, None))
+                .await
+                .unwrap();
+
+            let state = game.get_room_state(room).await.unwrap();
+            let seat = |connection_id: &str| {
+                state
+                    .players
+                    .iter()
+                    .find(|p| p.connection_id == connection_id)
+                    .map(|p| (p.player_id, p.benched))
+            };
+            assert_eq!(
+                seat("conn-b"),
+                Some((1, false)),
+                "the chosen spectator is seated"
+            );
+            assert_eq!(seat("conn-c"), Some((100, true)));
+        }
+
+        // ── Passphrases ──────────────────────────────────────────────────────────
+
+        fn query(passphrase: Option<&str>, resume: Option<&str>) -> ConnectionQuery {
+            ConnectionQuery {
+                passphrase: passphrase.map(str::to_string),
+                resume: resume.map(str::to_string),
+            }
+        }
+
+        /// Rule: joining a new room with a passphrase creates it locked, and the
+        /// room then refuses anyone without the passphrase instead of seating them.
+        #[tokio::test]
+        async fn test_join_locked_room() {
+            let game = new_game();
+            let room = "pw-room-create";
+            let join = async |connection_id: &str, passphrase| {
+                let query = query(passphrase, None);
+                game.join_with_connection(room, connection_id.to_string(), &query)
+                    .await
+            };
+            join("conn-a", Some("hunter2")).await.unwrap();
+            assert!(game.get_room_state(room).await.unwrap().locked);
+
+            assert!(join("conn-b", None).await.is_err());
+            assert!(join("conn-c", Some("hunter3")).await.is_err());
+            assert!(join("conn-d", Some("hunter2")).await.is_ok());
+            let state = game.get_room_state(room).await.unwrap();
+            let connections: Vec<_> = state
+                .players
+                .iter()
+                .map(|p| p.connection_id.as_str())
+                .collect();
+            assert_eq!(connections, ["conn-a", "conn-d"]);
+        }
+
+        /// Rule: a player resuming their seat needs no passphrase.
+        #[tokio::test]
+        async fn test_join_locked_room_with_resume_token() {
+            let game = new_game();
+            let room = "pw-room-resume";
+            let (player_id, token, _) = game
+                .join_with_connection(room, "conn-a".to_string(), &query(Some("hunter2"), None))
+                .await
+                .unwrap();
+
+            let (resumed_id, _, _) = game
+                .join_with_connection(room, "conn-b".to_string(), &query(None, Some(&token)))
+                .await
+                .unwrap();
+            assert_eq!(resumed_id, player_id);
+            assert!(
+                game.join_with_connection(room, "conn-c".to_string(), &query(None, Some("forged")))
+                    .await
+                    .is_err()
+            );
+        }
+
+        /// Rule: only the captain may lock or unlock a room.
+        #[tokio::test]
+        async fn test_set_passphrase() {
+            let game = new_game();
+            let room = "pw-room-set";
+            moderated_room(&game, room).await;
+
+            let set = |player_id, passphrase: Option<&str>| ClientMessage::SetPassphrase {
+                player_id,
+                passphrase: passphrase.map(Secret::from),
+            };
+            assert!(
+                game.process_connection_message(room, "conn-b", set(1, Some("x")))
+                    .await
+                    .is_err()
+            );
+            assert!(
+                game.process_connection_message(room, "conn-a", set(0, Some("")))
+                    .await
+                    .is_err()
+            );
+            game.process_connection_message(room, "conn-a", set(0, Some("hunter2")))
+                .await
+                .unwrap();
+            assert!(
+                game.join_with_connection(room, "conn-c".to_string(), &query(None, None))
+                    .await
+                    .is_err()
+            );
+
+            game.process_connection_message(room, "conn-a", set(0, None))
+                .await
+                .unwrap();
+            let state = game.get_room_state(room).await.unwrap();
+            assert!(!state.locked);
+            assert!(
+                game.join_with_connection(room, "conn-c".to_string(), &query(None, None))
+                    .await
+                    .is_ok()
+            );
+        }
+
+        // ── Persistence ──────────────────────────────────────────────────────────
+
+        /// Rule: a restored room keeps its history and lock, and its players can
+        /// reclaim their seats with the resume tokens they already hold.
+        #[tokio::test]
+        async fn test_snapshot_restore_round_trip() {
+            let game = new_game();
+            let room = "persist-room";
+            let (player_id, token, _) = game
+                .join_with_connection(room, "conn-a".to_string(), &query(Some("hunter2"), None))
+                .await
+                .unwrap();
+            game.process_client_message(room, ClientMessage::RevealNumbers { value: true })
+                .await;
+            game.process_client_message(room, ClientMessage::StartNewRound)
+                .await;
+            let saved = game.get_room_state(room).await.unwrap();
+
+            let restored = new_game();
+            let seats = restored.restore(game.snapshot().await).await;
+            assert_eq!(seats.len(), 1);
+            let state = restored.get_room_state(room).await.unwrap();
+            assert_eq!(state.history, saved.history);
+            assert_eq!(state.round, 2);
+            assert!(state.players[0].away);
+            assert!(
+                restored
+                    .join_with_connection(room, "conn-c".to_string(), &query(None, None))
+                    .await
+                    .is_err()
+            );
+
+            let (resumed_id, _, _) = restored
+                .join_with_connection(room, "conn-b".to_string(), &query(None, Some(&token)))
+                .await
+                .unwrap();
+            assert_eq!(resumed_id, player_id);
+            let state = restored.get_room_state(room).await.unwrap();
+            assert!(!state.players[0].away, "a resumed seat is no longer away");
+        }
+
+        /// Rule: the snapshot loop keeps saving until it is stopped, then
+        /// returns.
+        #[tokio::test]
+        async fn test_run_snapshots_until_stopped() {
+            let game = new_game();
+            game.new_player("persist-loop").await;
+            let path = std::env::temp_dir()
+                .join(format!("modelun-run-snapshots-{}.json", std::process::id()));
+            let store: Arc<dyn SnapshotStore> =
+                Arc::new($crate::persistence::JsonFileStore::new(&path));
+
+            let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+            let task = tokio::spawn({
+                let game = game.clone();
+                let store = store.clone();
+                async move {
+                    let stop = async {
+                        stopped.await.ok();
+                    };
+                    game.run_snapshots(store, Duration::from_secs(60), stop)
+                        .await
+                }
+            });
+            // The first save happens right away.
+            tokio::time::timeout(Duration::from_secs(5), async {
+                while !path.exists() {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            })
+            .await
             .unwrap();
-        assert_eq!(captain_id, 1, "Bob (id 1) should now be the captain");
 
-        // Alice (now at id 3) attempts to change the sequence – should be ignored.
-        game.process_client_message(
-            "m-room-cs-nc",
-            ClientMessage::ChangeSequence {
-                player_id: 3,
-                sequence: VotingSequence::SmMedLgXl,
-            },
-        )
-        .await;
-        let state = game.get_room_state("m-room-cs-nc").await.unwrap();
-        assert_eq!(state.voting_sequence, VotingSequence::Fibonacci);
-
-        // Bob (id 1, the new captain) changes the sequence to Linear.
-        game.process_client_message(
-            "m-room-cs-nc",
-            ClientMessage::ChangeSequence {
-                player_id: 1,
-                sequence: VotingSequence::Linear,
-            },
-        )
-        .await;
-        let state = game.get_room_state("m-room-cs-nc").await.unwrap();
-        assert_eq!(state.voting_sequence, VotingSequence::Linear);
+            stop.send(()).unwrap();
+            tokio::time::timeout(Duration::from_secs(5), task)
+                .await
+                .expect("the loop must return once stopped")
+                .unwrap();
+            let rooms = store.load().unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert!(rooms.contains_key("persist-loop"));
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    mod memory {
+        game_tests!(crate::room_store::MemoryRoomStore);
     }
 }
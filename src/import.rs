@@ -0,0 +1,283 @@
+use std::sync::Arc;
+
+use serde_json::{Value, json};
+use warp::http::StatusCode;
+
+use crate::agenda;
+use crate::game::Game;
+use crate::room_registry::RoomRegistry;
+use crate::room_store::RoomStore;
+use crate::structs::Topic;
+
+pub const MAX_IMPORT_BYTES: usize = 256 * 1024;
+pub const MAX_KEY_LEN: usize = 64;
+pub const MAX_DESCRIPTION_LEN: usize = 2000;
+
+/// Parse a backlog exported from a tracker into agenda topics.
+///
+/// A body starting with `[` is read as a JSON array of objects, anything
+/// else as CSV with a header row. Items need a `title` and may carry an `id`,
+/// a `description` and a `link`. Either every item is valid and all are
+/// returned, or one message per offending row is.
+pub fn parse_backlog(data: &str) -> Result<Vec<Topic>, Vec<String>> {
+    if data.len() > MAX_IMPORT_BYTES {
+        return Err(vec![format!(
+            "Backlog files are limited to {} KiB",
+            MAX_IMPORT_BYTES / 1024
+        )]);
+    }
+
+    let rows = if data.trim_start().starts_with('[') {
+        json_rows(data)?
+    } else {
+        csv_rows(data)?
+    };
+    if rows.is_empty() {
+        return Err(vec!["The backlog has no items".to_string()]);
+    }
+
+    let mut topics = Vec::with_capacity(rows.len());
+    let mut errors = Vec::new();
+    for (row, item) in rows.into_iter().enumerate() {
+        match item.and_then(topic_from_item) {
+            Ok(topic) => topics.push(topic),
+            Err(e) => errors.push(format!("Row {}: {e}", row + 1)),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(topics)
+    } else {
+        Err(errors)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ImportError {
+    UnknownRoom,
+    /// The caller is not the room's captain.
+    Forbidden,
+    /// One message per problem found in the backlog.
+    Invalid(Vec<String>),
+}
+
+/// Serve `POST /rooms/{room}/import`. The captain authenticates with their
+/// resume token as a bearer token.
+pub async fn handle_import<S: RoomStore>(
+    room: String,
+    authorization: Option<String>,
+    body: bytes::Bytes,
+    rooms: Arc<RoomRegistry>,
+    game: Arc<Game<S>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let token = authorization
+        .as_deref()
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or("");
+
+    let result = match std::str::from_utf8(&body) {
+        Ok(data) => game.import_backlog(&room, token, data).await,
+        Err(_) => Err(ImportError::Invalid(vec![
+            "The backlog must be UTF-8 text".to_string(),
+        ])),
+    };
+
+    let (status, body) = match result {
+        Ok(imported) => {
+            if let Some(state) = game.get_room_state(&room).await {
+                rooms.broadcast(&room, state).await;
+            }
+            (StatusCode::OK, json!({ "imported": imported }))
+        }
+        Err(ImportError::UnknownRoom) => (
+            StatusCode::NOT_FOUND,
+            json!({ "errors": [format!("Room {room:?} does not exist")] }),
+        ),
+        Err(ImportError::Forbidden) => (
+            StatusCode::FORBIDDEN,
+            json!({ "errors": ["Only the captain can import a backlog"] }),
+        ),
+        Err(ImportError::Invalid(errors)) => (StatusCode::BAD_REQUEST, json!({ "errors": errors })),
+    };
+    Ok(warp::reply::with_status(warp::reply::json(&body), status))
+}
+
+#[derive(Debug, Default)]
+struct BacklogItem {
+    id: Option<String>,
+    title: Option<String>,
+    description: Option<String>,
+    link: Option<String>,
+}
+
+fn topic_from_item(item: BacklogItem) -> Result<Topic, String> {
+    let title = item.title.ok_or("title is missing")?;
+    let mut topic = agenda::new_topic(&title, item.link.as_deref())?;
+
+    topic.key = match item.id.as_deref().map(str::trim) {
+        None | Some("") => None,
+        Some(id) if id.chars().count() > MAX_KEY_LEN || id.chars().any(char::is_control) => {
+            return Err(format!(
+                "id must be at most {MAX_KEY_LEN} printable characters"
+            ));
+        }
+        Some(id) => Some(id.to_string()),
+    };
+    topic.description = match item.description.as_deref().map(str::trim) {
+        None | Some("") => None,
+        Some(description) if description.chars().count() > MAX_DESCRIPTION_LEN => {
+            return Err(format!(
+                "description must be at most {MAX_DESCRIPTION_LEN} characters"
+            ));
+        }
+        Some(description) => Some(description.to_string()),
+    };
+    Ok(topic)
+}
+
+/// The backlog's items in order. A row that cannot be read is kept as its
+/// error so it is reported along with the other rows.
+type Rows = Vec<Result<BacklogItem, String>>;
+
+fn json_rows(data: &str) -> Result<Rows, Vec<String>> {
+    let items: Vec<Value> =
+        serde_json::from_str(data).map_err(|e| vec![format!("Invalid JSON backlog: {e}")])?;
+
+    Ok(items
+        .iter()
+        .map(|item| {
+            let object = item
+                .as_object()
+                .ok_or_else(|| "expected an object".to_string())?;
+            // Trackers disagree on whether IDs are numbers or strings.
+            let field = |name: &str| match object.get(name) {
+                Some(Value::String(s)) => Some(s.clone()),
+                Some(Value::Number(n)) => Some(n.to_string()),
+                _ => None,
+            };
+            Ok(BacklogItem {
+                id: field("id"),
+                title: field("title"),
+                description: field("description"),
+                link: field("link"),
+            })
+        })
+        .collect())
+}
+
+fn csv_rows(data: &str) -> Result<Rows, Vec<String>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(data.as_bytes());
+
+    let headers = reader
+        .headers()
+        .map_err(|e| vec![format!("Invalid CSV backlog: {e}")])?
+        .clone();
+    let column = |name: &str| headers.iter().position(|h| h.eq_ignore_ascii_case(name));
+    let Some(title_column) = column("title") else {
+        return Err(vec!["The CSV header has no title column".to_string()]);
+    };
+    let (id_column, description_column, link_column) =
+        (column("id"), column("description"), column("link"));
+
+    Ok(reader
+        .records()
+        .map(|record| {
+            let record = record.map_err(|e| e.to_string())?;
+            let field = |column: Option<usize>| {
+                column
+                    .and_then(|c| record.get(c))
+                    .filter(|value| !value.is_empty())
+                    .map(str::to_string)
+            };
+            Ok(BacklogItem {
+                id: field(id_column),
+                title: field(Some(title_column)),
+                description: field(description_column),
+                link: field(link_column),
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ── CSV ──────────────────────────────────────────────────────────────────
+
+    /// Rule: CSV columns are matched by header name, case-insensitively and
+    /// in any order; quoted fields may contain commas.
+    #[test]
+    fn test_csv_backlog() {
+        let topics = parse_backlog(
+            "Title,ID,Description\n\
+             \"Login, with SSO\",MUN-1,Use the company IdP\n\
+             Logout,MUN-2,\n",
+        )
+        .unwrap();
+        assert_eq!(topics.len(), 2);
+        assert_eq!(topics[0].title, "Login, with SSO");
+        assert_eq!(topics[0].key.as_deref(), Some("MUN-1"));
+        assert_eq!(
+            topics[0].description.as_deref(),
+            Some("Use the company IdP")
+        );
+        assert_eq!(topics[1].description, None);
+    }
+
+    /// Rule: a CSV without a title column is rejected outright.
+    #[test]
+    fn test_csv_requires_title_column() {
+        assert!(parse_backlog("id,summary\n1,Login\n").is_err());
+    }
+
+    // ── JSON ─────────────────────────────────────────────────────────────────
+
+    /// Rule: JSON items may use numeric or string IDs.
+    #[test]
+    fn test_json_backlog() {
+        let topics = parse_backlog(
+            r#"[{"id": 17, "title": "Login", "link": "https://tracker/17"},
+                {"id": "MUN-2", "title": "Logout"}]"#,
+        )
+        .unwrap();
+        assert_eq!(topics[0].key.as_deref(), Some("17"));
+        assert_eq!(topics[0].link.as_deref(), Some("https://tracker/17"));
+        assert_eq!(topics[1].key.as_deref(), Some("MUN-2"));
+    }
+
+    // ── Validation ───────────────────────────────────────────────────────────
+
+    /// Rule: every invalid row is reported by number, and nothing is
+    /// imported if any row is invalid.
+    #[test]
+    fn test_invalid_rows_are_reported_individually() {
+        let errors = parse_backlog(
+            r#"[{"title": "Fine"}, {"id": 2}, "oops", {"title": "x", "link": "ftp://x"}]"#,
+        )
+        .unwrap_err();
+        assert_eq!(errors.len(), 3);
+        assert!(errors[0].starts_with("Row 2: title is missing"));
+        assert_eq!(errors[1], "Row 3: expected an object");
+        assert!(errors[2].starts_with("Row 4:"));
+
+        let errors =
+            parse_backlog(r#"[{"title": "Fine"}, {"id": 2}, {"title": "x", "link": "ftp://x"}]"#)
+                .unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].starts_with("Row 2: title is missing"));
+        assert!(errors[1].starts_with("Row 3:"));
+    }
+
+    /// Rule: an empty backlog and oversized uploads are rejected.
+    #[test]
+    fn test_empty_and_oversized_backlogs() {
+        assert!(parse_backlog("[]").is_err());
+        assert!(parse_backlog("title\n").is_err());
+        let huge = format!("title\n{}", "x\n".repeat(MAX_IMPORT_BYTES));
+        assert!(parse_backlog(&huge).is_err());
+    }
+}
@@ -1,73 +1,118 @@
+use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use futures::{FutureExt, SinkExt, StreamExt};
-use log::{debug, error};
-use tokio::sync::broadcast::Sender;
+use log::{debug, error, info};
 use tokio::sync::mpsc;
 use uuid::Uuid;
 use warp::ws::{Message, WebSocket};
 use warp::{Rejection, Reply};
 
+use crate::agenda;
+use crate::config::Config;
 use crate::connection_pool::ConnectionPool;
-use crate::game::Game;
-use crate::structs::{ClientMessage, ConnectionContext, NotifyChange, RoomUpdate, ServerMessage};
+use crate::game::{self, Game};
+use crate::persistence::RoomSnapshot;
+use crate::room_registry::RoomRegistry;
+use crate::room_store::RoomStore;
+use crate::structs::{ClientMessage, ConnectionContext, ConnectionQuery, GameState, ServerMessage};
 
 pub struct GameWebSocket;
 
 impl GameWebSocket {
-    pub async fn handle_connection(
+    pub async fn handle_connection<S: RoomStore>(
         room: String,
+        query: ConnectionQuery,
         ws: warp::ws::Ws,
-        tx: Sender<RoomUpdate>,
+        game: Arc<Game<S>>,
+        rooms: Arc<RoomRegistry>,
         pool: Arc<ConnectionPool>,
+        config: Arc<Config>,
     ) -> Result<impl Reply, Rejection> {
         debug!("Room: {:?}", room);
+        // The passphrase is checked when the seat is handed out. The socket is
+        // always upgraded so a refused client can be told why it was turned away.
         Ok(ws.on_upgrade(move |socket| {
-            let tx = tx.clone();
-            async move {
-                GameWebSocket::manage_client_connection(socket, room, tx, pool).await;
-            }
+            GameWebSocket::manage_client_connection(socket, room, query, game, rooms, pool, config)
         }))
     }
 
-    pub async fn manage_client_connection(
+    async fn refuse_connection(websocket: WebSocket, message: String) {
+        let (mut ws_tx, _) = websocket.split();
+        let refusal =
+            serde_json::to_string(&ServerMessage::PassphraseRequired { message }).unwrap();
+        let _ = ws_tx.send(Message::text(refusal)).await;
+        let _ = ws_tx.close().await;
+    }
+
+    pub async fn manage_client_connection<S: RoomStore>(
         websocket: WebSocket,
         room: String,
-        tx: Sender<RoomUpdate>,
+        query: ConnectionQuery,
+        game_state: Arc<Game<S>>,
+        rooms: Arc<RoomRegistry>,
         pool: Arc<ConnectionPool>,
+        config: Arc<Config>,
     ) {
-        let game_state = Game::instance();
-
-        let (mut ws_tx, ws_rx) = websocket.split();
-        let (sender, _) = mpsc::channel::<Message>(32);
-        let rx = tx.subscribe();
-
-        pool.add(room.clone(), sender.clone()).await;
+        let (sender, outbox) = mpsc::channel::<Message>(32);
+        let rx = rooms.subscribe(&room).await;
 
         let connection_id = Uuid::new_v4().to_string();
-        let player_id = game_state
-            .new_player_with_connection(&room, connection_id.clone())
+        pool.add(room.clone(), connection_id.clone(), sender).await;
+        let (player_id, resume_token, previous_connection) = match game_state
+            .join_with_connection(&room, connection_id.clone(), &query)
+            .await
+        {
+            Ok(seat) => seat,
+            Err(message) => {
+                pool.remove(&room, &connection_id).await;
+                drop(rx);
+                rooms.release(&room).await;
+                GameWebSocket::refuse_connection(websocket, message).await;
+                return;
+            }
+        };
+        let (mut ws_tx, ws_rx) = websocket.split();
+        // A token reused while its first socket is still open moves the seat
+        // here; the old socket would otherwise keep a seat it no longer owns.
+        if let Some(previous_connection) = previous_connection {
+            Self::disconnect(
+                &pool,
+                &room,
+                &previous_connection,
+                "Your seat was resumed from another window",
+            )
             .await;
+        }
         debug!(
             "Room Player State: {:?}",
             game_state.get_room_state(&room).await.unwrap().players
         );
 
         // Send initial player assignment
-        let msg = serde_json::to_string(&ServerMessage::PlayerAssigned { player_id }).unwrap();
+        let msg = serde_json::to_string(&ServerMessage::PlayerAssigned {
+            player_id,
+            resume_token,
+            max_room_size: game_state.max_room_size(),
+            overflow_index: game_state.overflow_index(),
+        })
+        .unwrap();
         let _ = ws_tx.send(Message::text(msg)).await;
 
         let mut room_state = (game_state.get_room_state(&room).await).unwrap_or_default();
-        room_state.notify_change = NotifyChange::default();
-        let msg = serde_json::to_string(&ServerMessage::UpdateState(room_state.clone())).unwrap();
+        room_state.notify_changes.clear();
+        let mut history_sent = None;
+        let msg = Self::state_update(&room_state, &connection_id, &mut history_sent);
         let _ = ws_tx.send(Message::text(msg)).await;
 
         let connection_context = ConnectionContext {
-            tx,
+            rooms,
             rx,
             ws_tx,
             ws_rx,
+            outbox,
+            history_sent,
         };
 
         GameWebSocket::connection_driver(
@@ -75,26 +120,32 @@ impl GameWebSocket {
             room.clone(),
             game_state,
             pool,
-            sender,
             connection_id,
+            config,
         )
         .await;
     }
 
-    async fn connection_driver(
+    async fn connection_driver<S: RoomStore>(
         connection_context: ConnectionContext,
         room: String,
-        game_state: &'static Game,
+        game_state: Arc<Game<S>>,
         pool: Arc<ConnectionPool>,
-        sender: mpsc::Sender<Message>,
         connection_id: String,
+        config: Arc<Config>,
     ) {
-        let mut interval = tokio::time::interval(Duration::from_secs(7));
+        let mut interval = tokio::time::interval(config.ping_interval());
+        // Any traffic from the client proves it is alive; pongs are simply the
+        // traffic we ask for.
+        let mut last_seen = Instant::now();
+        let mut is_away = false;
 
-        let tx = connection_context.tx;
+        let rooms = connection_context.rooms;
         let mut rx = connection_context.rx;
         let mut ws_tx = connection_context.ws_tx;
         let mut ws_rx = connection_context.ws_rx;
+        let mut outbox = connection_context.outbox;
+        let mut history_sent = connection_context.history_sent;
 
         loop {
             tokio::select! {
@@ -102,17 +153,36 @@ impl GameWebSocket {
                 msg_result = ws_rx.next() => {
                     match msg_result {
                         Some(Ok(msg)) => {
+                            last_seen = Instant::now();
+                            if is_away {
+                                is_away = false;
+                                if game_state.set_player_away(&room, &connection_id, false).await {
+                                    Self::broadcast_room_state(&game_state, &room, &rooms).await;
+                                }
+                            }
                             if let Ok(text) = msg.to_str()
                                 && let Ok(client_message) =
                                     serde_json::from_str::<ClientMessage>(text)
                             {
                                 debug!("Client Message: {:?}", client_message);
-                                game_state.process_client_message(&room, client_message).await;
-                                if let Some(room_state) = game_state.get_room_state(&room).await {
-                                    let _ = tx.send(RoomUpdate {
-                                        room: room.to_string(),
-                                        state: room_state.clone(),
-                                    });
+                                match game_state
+                                    .process_connection_message(&room, &connection_id, client_message)
+                                    .await
+                                {
+                                    Ok(removed) => {
+                                        if let Some(kicked) = removed {
+                                            Self::disconnect(&pool, &room, &kicked, "You were removed from the room").await;
+                                        }
+                                        Self::broadcast_room_state(&game_state, &room, &rooms).await;
+                                    }
+                                    Err(reason) => {
+                                        let error_message = serde_json::to_string(&ServerMessage::ErrorMessage { message: reason })
+                                            .unwrap();
+                                        if let Err(e) = ws_tx.send(Message::text(error_message)).await {
+                                            debug!("WebSocket send (error) error for room {}: {:?}", room, e);
+                                            break;
+                                        }
+                                    }
                                 }
                             }
                         },
@@ -129,12 +199,27 @@ impl GameWebSocket {
                 },
                 // Sending ping messages to the client
                 _ = interval.tick().fuse() => {
+                    let silent_for = last_seen.elapsed();
+                    if silent_for >= config.stale_timeout() {
+                        info!(
+                            "Evicting connection {} from room {} after {:?} without a pong",
+                            connection_id, room, silent_for
+                        );
+                        game_state.remove_player_by_connection(&room, &connection_id).await;
+                        Self::broadcast_room_state(&game_state, &room, &rooms).await;
+                        break;
+                    }
+                    if !is_away && silent_for >= config.away_after() {
+                        is_away = true;
+                        if game_state.set_player_away(&room, &connection_id, true).await {
+                            Self::broadcast_room_state(&game_state, &room, &rooms).await;
+                        }
+                    }
+
                     let ping_message = serde_json::to_string(&ServerMessage::Ping { data: 0 })
                         .unwrap();
-                    // TODO: Handle if a client goes stale and does not reply to a ping.
                     if let Err(e) = ws_tx.send(Message::text(ping_message)).await {
                         debug!("WebSocket send (ping) error for room {}: {:?}", room, e);
-                        game_state.remove_player_by_connection(&room, &connection_id).await;
                         break;
                     }
                 },
@@ -142,14 +227,11 @@ impl GameWebSocket {
                 update_result = rx.recv().fuse() => {
                     match update_result {
                         Ok(room_update) => {
-                            if room_update.room == room {
-                                let serialized = serde_json::to_string(&ServerMessage::UpdateState(room_update.state))
-                                    .unwrap();
-                                debug!("State Change for room {}: {:#?}", room, &serialized);
-                                if let Err(e) = ws_tx.send(Message::text(serialized)).await {
-                                    debug!("WebSocket send (state update) error for room {}: {:?}", room, e);
-                                    break;
-                                }
+                            let serialized = Self::state_update(&room_update.state, &connection_id, &mut history_sent);
+                            debug!("State Change for room {}: {:#?}", room, &serialized);
+                            if let Err(e) = ws_tx.send(Message::text(serialized)).await {
+                                debug!("WebSocket send (state update) error for room {}: {:?}", room, e);
+                                break;
                             }
                         },
                         Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
@@ -158,8 +240,7 @@ impl GameWebSocket {
                                 room, skipped
                             );
                             if let Some(room_state) = game_state.get_room_state(&room).await {
-                                let serialized = serde_json::to_string(&ServerMessage::UpdateState(room_state))
-                                    .unwrap();
+                                let serialized = Self::state_update(&room_state, &connection_id, &mut history_sent);
                                 if let Err(e) = ws_tx.send(Message::text(serialized)).await {
                                     debug!("WebSocket send (state resync) error for room {}: {:?}", room, e);
                                     break;
@@ -172,21 +253,202 @@ impl GameWebSocket {
                         }
                     }
                 },
+                // Forwarding messages pushed to this connection
+                Some(message) = outbox.recv() => {
+                    if message.is_close() {
+                        info!("Connection {} closed by the server in room {}", connection_id, room);
+                        let _ = ws_tx.close().await;
+                        break;
+                    }
+                    if let Err(e) = ws_tx.send(message).await {
+                        debug!("WebSocket send (push) error for room {}: {:?}", room, e);
+                        break;
+                    }
+                },
             }; // tokio::select!
         } // loop
         debug!("Connection driver finished for room: {}", room);
-        pool.remove(&room, &sender).await;
-        game_state
-            .remove_player_by_connection(&room, &connection_id)
-            .await;
+        pool.remove(&room, &connection_id).await;
+        drop(rx);
+        rooms.release(&room).await;
+        // Show the seat as away while it is held, as for a silent client.
+        if game_state
+            .set_player_away(&room, &connection_id, true)
+            .await
+        {
+            Self::broadcast_room_state(&game_state, &room, &rooms).await;
+        }
 
-        // Broadcast updated state so remaining clients learn about the
-        // removal (and any spectator promotion that occurred).
-        if let Some(room_state) = game_state.get_room_state(&room).await {
-            let _ = tx.send(RoomUpdate {
-                room: room.to_string(),
-                state: room_state,
-            });
+        // Hold the seat for a grace period so a refresh or a network blip can
+        // resume it with the token. A resumed seat is bound to its new
+        // connection, so the removal below no longer finds it.
+        tokio::spawn(async move {
+            tokio::time::sleep(config.resume_grace()).await;
+            game_state
+                .remove_player_by_connection(&room, &connection_id)
+                .await;
+
+            // Broadcast updated state so remaining clients learn about the
+            // removal (and any spectator promotion that occurred).
+            Self::broadcast_room_state(&game_state, &room, &rooms).await;
+        });
+    }
+
+    /// Tell a connection why it is being dropped, then close it.
+    async fn disconnect(pool: &ConnectionPool, room: &str, connection_id: &str, reason: &str) {
+        let removed = ServerMessage::Removed {
+            message: reason.to_string(),
+        };
+        if pool.send_to(room, connection_id, &removed).await {
+            pool.send(room, connection_id, Message::close()).await;
         }
     }
+
+    /// Serialize `state` as seen by `connection_id`. A connection's first
+    /// update carries the whole round history; later ones only carry the
+    /// rounds completed since the previous update.
+    fn state_update(
+        state: &GameState,
+        connection_id: &str,
+        history_sent: &mut Option<usize>,
+    ) -> String {
+        let mut view = game::state_for_connection(state, connection_id);
+        let total = view.history.len();
+        let previous = *history_sent;
+        // A stale state must not make rounds already sent look new again.
+        *history_sent = Some(previous.map_or(total, |sent| sent.max(total)));
+        if let Some(sent) = previous {
+            view.history = Arc::new(view.history.get(sent..).unwrap_or_default().to_vec());
+        } else if view.history.is_empty() {
+            // An empty history is left out like an unchanged one, but the
+            // client must drop whatever it kept from an earlier session.
+            let mut update =
+                serde_json::to_value(ServerMessage::UpdateState(Box::new(view))).unwrap();
+            update["history"] = serde_json::Value::Array(Vec::new());
+            return update.to_string();
+        }
+        serde_json::to_string(&ServerMessage::UpdateState(Box::new(view))).unwrap()
+    }
+
+    async fn broadcast_room_state<S: RoomStore>(
+        game_state: &Arc<Game<S>>,
+        room: &str,
+        rooms: &Arc<RoomRegistry>,
+    ) {
+        if let Some(room_state) = game_state.get_room_state(room).await {
+            if let Some(at) = game::next_deadline(&room_state) {
+                Self::schedule_deadline(game_state, room, rooms, at).await;
+            }
+            rooms.broadcast(room, room_state).await;
+        }
+    }
+
+    /// Bring back rooms saved by an earlier run and resume their pending
+    /// auto-reveals and timers. The returned `(room, connection_id)` pairs are
+    /// for [`GameWebSocket::release_unclaimed_seats`].
+    pub async fn restore_rooms<S: RoomStore>(
+        game_state: &Arc<Game<S>>,
+        rooms: &Arc<RoomRegistry>,
+        snapshot: HashMap<String, RoomSnapshot>,
+    ) -> Vec<(String, String)> {
+        let restored: Vec<String> = snapshot.keys().cloned().collect();
+        let seats = game_state.restore(snapshot).await;
+        for room in &restored {
+            Self::broadcast_room_state(game_state, room, rooms).await;
+        }
+        seats
+    }
+
+    /// Free the restored seats nobody resumed within `grace`, as if their
+    /// connections had dropped, and tell each room who took the seat.
+    pub async fn release_unclaimed_seats<S: RoomStore>(
+        game_state: Arc<Game<S>>,
+        rooms: Arc<RoomRegistry>,
+        seats: Vec<(String, String)>,
+        grace: Duration,
+    ) {
+        tokio::time::sleep(grace).await;
+        for (room, connection_id) in seats {
+            game_state
+                .remove_player_by_connection(&room, &connection_id)
+                .await;
+            Self::broadcast_room_state(&game_state, &room, &rooms).await;
+        }
+    }
+
+    /// Make sure a task is waiting to wake `room` up at `at`, to fire an
+    /// auto-reveal or end the timer, and at each deadline after that.
+    async fn schedule_deadline<S: RoomStore>(
+        game_state: &Arc<Game<S>>,
+        room: &str,
+        rooms: &Arc<RoomRegistry>,
+        mut at: u64,
+    ) {
+        if !game_state.claim_deadline(room, at).await {
+            return;
+        }
+        let game_state = game_state.clone();
+        let room = room.to_string();
+        let rooms = rooms.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_secs(at.saturating_sub(agenda::now()))).await;
+                if !game_state.fire_deadline(&room, at).await {
+                    break;
+                }
+                let Some(room_state) = game_state.get_room_state(&room).await else {
+                    break;
+                };
+                let next = game::next_deadline(&room_state);
+                rooms.broadcast(&room, room_state).await;
+                match next {
+                    Some(next) if game_state.claim_deadline(&room, next).await => at = next,
+                    _ => break,
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::RoundRecord;
+
+    fn state_with_rounds(rounds: u64) -> GameState {
+        GameState {
+            history: Arc::new(
+                (1..=rounds)
+                    .map(|round| RoundRecord {
+                        round,
+                        ..Default::default()
+                    })
+                    .collect(),
+            ),
+            ..Default::default()
+        }
+    }
+
+    fn sent_rounds(update: &str) -> Vec<u64> {
+        match serde_json::from_str(update).unwrap() {
+            ServerMessage::UpdateState(state) => state.history.iter().map(|r| r.round).collect(),
+            other => panic!("expected UpdateState, got {other:?}"),
+        }
+    }
+
+    /// An update built from an older state does not rewind what the
+    /// connection has already been sent, so no round is sent twice.
+    #[test]
+    fn test_history_sent_never_moves_backwards() {
+        let mut history_sent = None;
+        let update = GameWebSocket::state_update(&state_with_rounds(2), "conn", &mut history_sent);
+        assert_eq!(sent_rounds(&update), [1, 2]);
+
+        let update = GameWebSocket::state_update(&state_with_rounds(1), "conn", &mut history_sent);
+        assert!(sent_rounds(&update).is_empty());
+        assert_eq!(history_sent, Some(2));
+
+        let update = GameWebSocket::state_update(&state_with_rounds(3), "conn", &mut history_sent);
+        assert_eq!(sent_rounds(&update), [3]);
+    }
 }
@@ -1,78 +1,148 @@
+pub mod agenda;
+pub mod config;
 pub mod connection_pool;
 pub mod counter;
+pub mod export;
 pub mod game;
+pub mod import;
 pub mod interface;
+pub mod passphrase;
+pub mod persistence;
+pub mod room_registry;
+pub mod room_store;
+pub mod shutdown;
+pub mod stats;
 pub mod structs;
 
-use std::collections::HashMap;
 use std::sync::Arc;
 
+use config::Config;
 use game::Game;
-use structs::{GameState, RoomUpdate};
-use tokio::sync::{RwLock, broadcast};
+use structs::ConnectionQuery;
 use warp::Filter;
 
 use crate::connection_pool::ConnectionPool;
 use crate::interface::GameWebSocket;
-
-pub type SharedGameState = Arc<RwLock<HashMap<String, GameState>>>;
+use crate::room_registry::RoomRegistry;
+use crate::room_store::RoomStore;
 
 /// Build the WebSocket route used by both the binary and
-/// integration tests.
-pub fn build_ws_route() -> (
+/// integration tests, with the registry and pool its connections share.
+pub fn build_ws_route<S: RoomStore>(
+    config: Config,
+    game: Arc<Game<S>>,
+) -> (
     impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone,
-    broadcast::Sender<RoomUpdate>,
+    Arc<RoomRegistry>,
+    Arc<ConnectionPool>,
 ) {
-    let (tx, _rx) = broadcast::channel::<RoomUpdate>(255);
-    let tx_filter = warp::any().map({
-        let tx = tx.clone();
-        move || tx.clone()
+    let rooms = RoomRegistry::with_capacity(config.channel_capacity);
+    let game_filter = warp::any().map(move || game.clone());
+    let rooms_filter = warp::any().map({
+        let rooms = rooms.clone();
+        move || rooms.clone()
+    });
+
+    let pool = ConnectionPool::new();
+    let pool_filter = warp::any().map({
+        let pool = pool.clone();
+        move || pool.clone()
     });
 
-    let pool_filter = warp::any().map(ConnectionPool::new);
+    let config = Arc::new(config);
+    let config_filter = warp::any().map(move || config.clone());
 
     let ws_route = warp::path("ws")
         .and(warp::path::param::<String>())
+        .and(warp::query::<ConnectionQuery>())
         .and(warp::ws())
-        .and(tx_filter)
+        .and(game_filter)
+        .and(rooms_filter)
         .and(pool_filter)
+        .and(config_filter)
         .and_then(GameWebSocket::handle_connection);
 
-    (ws_route, tx)
+    (ws_route, rooms, pool)
 }
 
-/// Build all routes (index redirect, static files, ws).
-pub fn build_routes() -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone
-{
-    let game_state = Game::instance();
+/// Build the `GET /rooms/{room}/export.{csv,json,md}` route.
+pub fn build_export_route<S: RoomStore>(
+    game: Arc<Game<S>>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let game_filter = warp::any().map(move || game.clone());
+    warp::path!("rooms" / String / String)
+        .and(warp::query::<export::ExportQuery>())
+        .and(game_filter)
+        .and_then(export::handle_export)
+}
 
-    let index_route = warp::path::end().and_then(async move || {
-        let room_name = game_state.random_name_generator().await;
-        Ok::<_, warp::Rejection>(warp::redirect(
-            warp::http::Uri::from_maybe_shared(format!("/index.html?room={room_name}")).unwrap(),
+/// Build the `POST /rooms/{room}/import` route.
+pub fn build_import_route<S: RoomStore>(
+    rooms: Arc<RoomRegistry>,
+    game: Arc<Game<S>>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let rooms_filter = warp::any().map(move || rooms.clone());
+    let game_filter = warp::any().map(move || game.clone());
+    warp::post()
+        .and(warp::path!("rooms" / String / "import"))
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::body::content_length_limit(
+            import::MAX_IMPORT_BYTES as u64,
         ))
+        .and(warp::body::bytes())
+        .and(rooms_filter)
+        .and(game_filter)
+        .and_then(import::handle_import)
+}
+
+/// Build all routes (index redirect, static files, exports, import, ws)
+/// serving `game`, with the registry and pool of their WebSocket
+/// connections.
+pub fn build_routes<S: RoomStore>(
+    config: Config,
+    game: Arc<Game<S>>,
+) -> (
+    impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone,
+    Arc<RoomRegistry>,
+    Arc<ConnectionPool>,
+) {
+    let game_state = game.clone();
+    let index_route = warp::path::end().and_then(move || {
+        let game_state = game_state.clone();
+        async move {
+            let room_name = game_state.random_name_generator().await;
+            Ok::<_, warp::Rejection>(warp::redirect(
+                warp::http::Uri::from_maybe_shared(format!("/index.html?room={room_name}"))
+                    .unwrap(),
+            ))
+        }
     });
 
-    let (ws_route, _tx) = build_ws_route();
+    let client_dir = config.client_dir.clone();
+    let (ws_route, rooms, pool) = build_ws_route(config, game.clone());
 
     let img_route = warp::path("img").and(
         warp::path("portraits.png")
-            .and(warp::fs::file("./client/img/portraits.png"))
-            .or(warp::path("atlas.png").and(warp::fs::file("./client/img/atlas.png"))),
+            .and(warp::fs::file(client_dir.join("img/portraits.png")))
+            .or(warp::path("atlas.png").and(warp::fs::file(client_dir.join("img/atlas.png")))),
     );
 
-    let client_code = warp::path("game.js").and(warp::fs::file("./client/game.js"));
+    let client_code = warp::path("game.js").and(warp::fs::file(client_dir.join("game.js")));
 
-    let client_style = warp::path("style.css").and(warp::fs::file("./client/style.css"));
+    let client_style = warp::path("style.css").and(warp::fs::file(client_dir.join("style.css")));
 
-    let client_html = warp::path("index.html").and(warp::fs::file("./client/index.html"));
+    let client_html = warp::path("index.html").and(warp::fs::file(client_dir.join("index.html")));
 
-    warp::get().and(
-        index_route
-            .or(ws_route)
-            .or(img_route)
-            .or(client_code)
-            .or(client_style)
-            .or(client_html),
-    )
+    let routes = warp::get()
+        .and(
+            index_route
+                .or(ws_route)
+                .or(img_route)
+                .or(client_code)
+                .or(client_style)
+                .or(client_html)
+                .or(build_export_route(game.clone())),
+        )
+        .or(build_import_route(rooms.clone(), game));
+    (routes, rooms, pool)
 }
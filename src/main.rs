@@ -1,15 +1,101 @@
-use log::info;
-use model_un::build_routes;
+use std::sync::Arc;
 
-static PORT: u16 = 3000;
-static BIND_ADDRESS: [u8; 4] = [0, 0, 0, 0];
+use log::{error, info, warn};
+use model_un::build_routes;
+use model_un::config::Config;
+use model_un::game::Game;
+use model_un::interface::GameWebSocket;
+use model_un::persistence::{JsonFileStore, SnapshotStore};
+use model_un::shutdown;
+use tokio::sync::oneshot;
+use tokio::time::Instant;
 
 #[tokio::main]
 async fn main() {
     env_logger::init();
 
-    let routes = build_routes();
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(2);
+        }
+    };
+
+    let game: Arc<Game> = Game::new(&config);
+
+    let (routes, rooms, pool) = build_routes(config.clone(), game.clone());
+
+    let store: Option<Arc<dyn SnapshotStore>> = config
+        .state_file
+        .as_ref()
+        .map(|state_file| Arc::new(JsonFileStore::new(state_file)) as _);
+    let mut snapshots = None;
+    if let Some(store) = store.clone() {
+        let snapshot = match store.load() {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                error!("{e}");
+                std::process::exit(1);
+            }
+        };
+        let seats = GameWebSocket::restore_rooms(&game, &rooms, snapshot).await;
+        let (grace, interval) = (config.resume_grace(), config.snapshot_interval());
+        tokio::spawn(GameWebSocket::release_unclaimed_seats(
+            game.clone(),
+            rooms.clone(),
+            seats,
+            grace,
+        ));
+        let (stop_snapshots, snapshots_stopped) = oneshot::channel::<()>();
+        let task = tokio::spawn({
+            let game = game.clone();
+            let stop = async {
+                snapshots_stopped.await.ok();
+            };
+            async move { game.run_snapshots(store, interval, stop).await }
+        });
+        snapshots = Some((stop_snapshots, task));
+    }
+
+    let ttl = config.room_idle_ttl();
+    tokio::spawn({
+        let game = game.clone();
+        async move { game.run_room_reaper(ttl).await }
+    });
+
+    let (stop_accepting, stopped) = oneshot::channel::<()>();
+    let server = warp::serve(routes)
+        .bind((config.bind_address, config.port))
+        .await
+        .graceful(async {
+            stopped.await.ok();
+        });
+    let server = tokio::spawn(server.run());
+    info!(
+        "Model UN Server Running on {}:{}.",
+        config.bind_address, config.port
+    );
 
-    info!("Model UN Server Running.");
-    warp::serve(routes).run((BIND_ADDRESS, PORT)).await;
+    shutdown::signal().await;
+    info!("Shutting down");
+    // One deadline covers draining the clients and stopping the server; the
+    // final save always runs.
+    let deadline = Instant::now() + config.shutdown_timeout();
+    stop_accepting.send(()).ok();
+    shutdown::drain_connections(&pool, deadline).await;
+    // Let a periodic save in progress finish, so it cannot race the last one.
+    if let Some((stop_snapshots, task)) = snapshots {
+        stop_snapshots.send(()).ok();
+        task.await.ok();
+    }
+    if let Some(store) = &store {
+        match game.save_snapshot(store).await {
+            Ok(()) => info!("Saved rooms"),
+            Err(e) => error!("Could not save room snapshot: {e}"),
+        }
+    }
+    if tokio::time::timeout_at(deadline, server).await.is_err() {
+        warn!("Server did not stop by the shutdown deadline");
+    }
 }
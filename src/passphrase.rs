@@ -0,0 +1,95 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+pub const MAX_PASSPHRASE_LEN: usize = 128;
+
+/// A room's passphrase, kept only as a salted hash.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Passphrase {
+    salt: String,
+    hash: String,
+}
+
+/// A passphrase as the user typed it. Its `Debug` output is redacted so
+/// logging a message that carries one does not undo the hashing.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for Secret {
+    fn from(secret: &str) -> Self {
+        Secret(secret.to_string())
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\"***\"")
+    }
+}
+
+impl Passphrase {
+    pub fn new(passphrase: &str) -> Result<Self, String> {
+        if passphrase.is_empty() || passphrase.chars().count() > MAX_PASSPHRASE_LEN {
+            return Err(format!(
+                "A passphrase must be between 1 and {MAX_PASSPHRASE_LEN} characters"
+            ));
+        }
+        let salt = Uuid::new_v4().simple().to_string();
+        let hash = Self::digest(&salt, passphrase);
+        Ok(Passphrase { salt, hash })
+    }
+
+    pub fn matches(&self, passphrase: &str) -> bool {
+        Self::digest(&self.salt, passphrase) == self.hash
+    }
+
+    fn digest(salt: &str, passphrase: &str) -> String {
+        let digest = Sha256::new()
+            .chain_update(salt)
+            .chain_update(passphrase)
+            .finalize();
+        digest.iter().map(|b| format!("{b:02x}")).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Rule: only the exact passphrase matches, and the same passphrase
+    /// hashes differently in different rooms.
+    #[test]
+    fn test_passphrase_matches() {
+        let a = Passphrase::new("open sesame").unwrap();
+        let b = Passphrase::new("open sesame").unwrap();
+        assert!(a.matches("open sesame"));
+        assert!(!a.matches("open sesame "));
+        assert!(!a.matches(""));
+        assert_ne!(a.hash, b.hash);
+    }
+
+    /// Rule: a secret is left out of debug output but kept on the wire.
+    #[test]
+    fn test_secret_is_redacted() {
+        let secret = Secret::from("open sesame");
+        assert_eq!(format!("{secret:?}"), "\"***\"");
+        assert_eq!(serde_json::to_string(&secret).unwrap(), "\"open sesame\"");
+    }
+
+    /// Rule: empty and overlong passphrases are rejected.
+    #[test]
+    fn test_passphrase_length() {
+        assert!(Passphrase::new("").is_err());
+        assert!(Passphrase::new(&"x".repeat(MAX_PASSPHRASE_LEN + 1)).is_err());
+    }
+}
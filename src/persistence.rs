@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::passphrase::Passphrase;
+use crate::structs::GameState;
+
+/// What is kept of a room across a restart: its state plus the secrets the
+/// state leaves out on the wire.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct RoomSnapshot {
+    pub state: GameState,
+    #[serde(default)]
+    pub passphrase: Option<Passphrase>,
+    /// Each player's resume token, so clients can reclaim their seats after
+    /// the restart.
+    #[serde(default)]
+    pub resume_tokens: HashMap<usize, String>,
+}
+
+/// Somewhere to keep room snapshots between runs of the server.
+pub trait SnapshotStore: Send + Sync {
+    /// Read the rooms saved by an earlier run. Nothing saved yet is not an
+    /// error.
+    fn load(&self) -> Result<HashMap<String, RoomSnapshot>, String>;
+
+    /// Replace whatever was saved with `rooms`.
+    fn save(&self, rooms: &HashMap<String, RoomSnapshot>) -> Result<(), String>;
+}
+
+/// Keeps every room in a single JSON file.
+pub struct JsonFileStore {
+    path: PathBuf,
+}
+
+impl JsonFileStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        JsonFileStore { path: path.into() }
+    }
+}
+
+impl SnapshotStore for JsonFileStore {
+    fn load(&self) -> Result<HashMap<String, RoomSnapshot>, String> {
+        let contents = match std::fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(e) => return Err(format!("Could not read {}: {e}", self.path.display())),
+        };
+        serde_json::from_str(&contents)
+            .map_err(|e| format!("Invalid state file {}: {e}", self.path.display()))
+    }
+
+    fn save(&self, rooms: &HashMap<String, RoomSnapshot>) -> Result<(), String> {
+        let contents = serde_json::to_string(rooms).map_err(|e| e.to_string())?;
+        // Write aside and rename, so a crash mid-write leaves the last good
+        // snapshot in place.
+        let temp = self.path.with_extension("tmp");
+        std::fs::write(&temp, contents)
+            .and_then(|()| std::fs::rename(&temp, &self.path))
+            .map_err(|e| format!("Could not write {}: {e}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::structs::{PlayerState, RoundRecord, Vote};
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("modelun-{name}-{}.json", std::process::id()))
+    }
+
+    /// Rule: a saved room reads back unchanged, including its passphrase and
+    /// resume tokens.
+    #[test]
+    fn test_json_file_round_trip() {
+        let path = temp_path("round-trip");
+        let store = JsonFileStore::new(&path);
+        let room = RoomSnapshot {
+            state: GameState {
+                players: vec![PlayerState {
+                    player_id: 3,
+                    player_name: "Ana".to_string(),
+                    value: Some(Vote::Card(5)),
+                    ..Default::default()
+                }],
+                history: Arc::new(vec![RoundRecord {
+                    round: 1,
+                    ..Default::default()
+                }]),
+                locked: true,
+                ..Default::default()
+            },
+            passphrase: Some(Passphrase::new("hunter2").unwrap()),
+            resume_tokens: HashMap::from([(3, "token".to_string())]),
+        };
+
+        store
+            .save(&HashMap::from([("r".to_string(), room.clone())]))
+            .unwrap();
+        let loaded = store.load().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded["r"], room);
+        assert!(loaded["r"].passphrase.as_ref().unwrap().matches("hunter2"));
+    }
+
+    /// Rule: a missing file is an empty store, a corrupt one an error.
+    #[test]
+    fn test_json_file_missing_or_corrupt() {
+        let path = temp_path("corrupt");
+        let store = JsonFileStore::new(&path);
+        assert!(store.load().unwrap().is_empty());
+
+        std::fs::write(&path, "{ not json").unwrap();
+        let result = store.load();
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+}
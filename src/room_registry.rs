@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use tokio::sync::{RwLock, broadcast};
+
+use crate::structs::{GameState, RoomUpdate};
+
+/// Owns one broadcast channel per room so state updates only reach the
+/// connections that are actually in that room.
+pub struct RoomRegistry {
+    capacity: usize,
+    channels: RwLock<HashMap<String, broadcast::Sender<RoomUpdate>>>,
+}
+
+impl RoomRegistry {
+    pub fn with_capacity(capacity: usize) -> Arc<Self> {
+        Arc::new(Self {
+            capacity,
+            channels: RwLock::new(HashMap::new()),
+        })
+    }
+
+    /// Subscribe to a room's updates, creating its channel on first join.
+    pub async fn subscribe(&self, room: &str) -> broadcast::Receiver<RoomUpdate> {
+        // Subscribing under the write lock means `release` can never drop a
+        // channel between its creation and the first receiver attaching.
+        let mut channels = self.channels.write().await;
+        channels
+            .entry(room.to_string())
+            .or_insert_with(|| broadcast::channel(self.capacity).0)
+            .subscribe()
+    }
+
+    /// Send a state update to everyone in the room. Rooms without a channel
+    /// have nobody listening, so the update is dropped.
+    pub async fn broadcast(&self, room: &str, state: GameState) {
+        if let Some(tx) = self.channels.read().await.get(room) {
+            let _ = tx.send(RoomUpdate {
+                room: room.to_string(),
+                state,
+            });
+        }
+    }
+
+    /// Drop a room's channel once its last receiver has gone away.
+    pub async fn release(&self, room: &str) {
+        let mut channels = self.channels.write().await;
+        if channels
+            .get(room)
+            .is_some_and(|tx| tx.receiver_count() == 0)
+        {
+            channels.remove(room);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn new_registry() -> Arc<RoomRegistry> {
+        RoomRegistry::with_capacity(Config::default().channel_capacity)
+    }
+
+    /// Subscribing to a room creates its channel.
+    #[tokio::test]
+    async fn test_subscribe_creates_channel() {
+        let registry = new_registry();
+        let _rx = registry.subscribe("room-create").await;
+        assert!(registry.channels.read().await.contains_key("room-create"));
+    }
+
+    /// Updates only reach subscribers of the room they were sent to.
+    #[tokio::test]
+    async fn test_broadcast_is_scoped_to_room() {
+        let registry = new_registry();
+        let mut rx_a = registry.subscribe("room-a").await;
+        let mut rx_b = registry.subscribe("room-b").await;
+
+        registry.broadcast("room-a", GameState::default()).await;
+
+        let update = rx_a.try_recv().expect("room-a must receive its update");
+        assert_eq!(update.room, "room-a");
+        assert!(
+            rx_b.try_recv().is_err(),
+            "room-b must not see room-a traffic"
+        );
+    }
+
+    /// Broadcasting to a room nobody has joined is a no-op and does not create
+    /// a channel.
+    #[tokio::test]
+    async fn test_broadcast_to_unknown_room_is_noop() {
+        let registry = new_registry();
+        registry.broadcast("room-ghost", GameState::default()).await;
+        assert!(!registry.channels.read().await.contains_key("room-ghost"));
+    }
+
+    /// Releasing a room drops its channel once the last receiver is gone.
+    #[tokio::test]
+    async fn test_release_drops_channel_when_room_empties() {
+        let registry = new_registry();
+        let rx = registry.subscribe("room-empty").await;
+        drop(rx);
+        registry.release("room-empty").await;
+        assert!(!registry.channels.read().await.contains_key("room-empty"));
+    }
+
+    /// Releasing a room that still has receivers keeps its channel.
+    #[tokio::test]
+    async fn test_release_keeps_channel_with_receivers() {
+        let registry = new_registry();
+        let _rx1 = registry.subscribe("room-busy").await;
+        let rx2 = registry.subscribe("room-busy").await;
+        drop(rx2);
+        registry.release("room-busy").await;
+        assert!(registry.channels.read().await.contains_key("room-busy"));
+    }
+}
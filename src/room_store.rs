@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use std::future::Future;
+
+use tokio::sync::RwLock;
+
+use crate::structs::GameState;
+
+/// Where `Game` keeps its rooms.
+///
+/// Every change goes through [`RoomStore::update`], which must run its
+/// closure atomically with respect to other calls for the same room.
+pub trait RoomStore: Send + Sync + 'static {
+    /// A copy of the room's state, if it exists.
+    fn get(&self, room: &str) -> impl Future<Output = Option<GameState>> + Send;
+
+    /// The names of all rooms, in no particular order.
+    fn list(&self) -> impl Future<Output = Vec<String>> + Send;
+
+    /// Read-modify-write a room. The closure sees `None` for a room that does
+    /// not exist; leaving `Some` behind creates or updates it, leaving `None`
+    /// deletes it.
+    fn update<R: Send>(
+        &self,
+        room: &str,
+        f: impl FnOnce(&mut Option<GameState>) -> R + Send,
+    ) -> impl Future<Output = R> + Send;
+
+    /// Remove a room, returning its last state.
+    fn delete(&self, room: &str) -> impl Future<Output = Option<GameState>> + Send {
+        self.update(room, Option::take)
+    }
+}
+
+/// Rooms in a map in memory, lost when the process exits.
+#[derive(Default)]
+pub struct MemoryRoomStore {
+    rooms: RwLock<HashMap<String, GameState>>,
+}
+
+impl RoomStore for MemoryRoomStore {
+    async fn get(&self, room: &str) -> Option<GameState> {
+        self.rooms.read().await.get(room).cloned()
+    }
+
+    async fn list(&self) -> Vec<String> {
+        self.rooms.read().await.keys().cloned().collect()
+    }
+
+    async fn update<R: Send>(
+        &self,
+        room: &str,
+        f: impl FnOnce(&mut Option<GameState>) -> R + Send,
+    ) -> R {
+        let mut rooms = self.rooms.write().await;
+        let mut slot = rooms.remove(room);
+        let result = f(&mut slot);
+        if let Some(state) = slot {
+            rooms.insert(room.to_string(), state);
+        }
+        result
+    }
+}
+
+/// The behavior every `RoomStore` must share. Instantiate it in a test
+/// module with a constructor for the store under test, next to a
+/// `game_tests!` instantiation for the same store.
+#[cfg(test)]
+macro_rules! room_store_contract {
+    ($new_store:expr) => {
+        use std::sync::Arc;
+
+        use $crate::room_store::RoomStore;
+        use $crate::structs::GameState;
+
+        /// Rule: updates create, change and delete rooms as the closure says.
+        #[tokio::test]
+        async fn test_update_creates_changes_and_deletes() {
+            let store = $new_store;
+            assert!(store.get("r").await.is_none());
+
+            let existed = store
+                .update("r", |slot| {
+                    let existed = slot.is_some();
+                    *slot = Some(GameState::default());
+                    existed
+                })
+                .await;
+            assert!(!existed);
+            store
+                .update("r", |slot| slot.as_mut().unwrap().round = 7)
+                .await;
+            assert_eq!(store.get("r").await.unwrap().round, 7);
+            assert_eq!(store.list().await, ["r"]);
+
+            assert_eq!(store.delete("r").await.unwrap().round, 7);
+            assert!(store.get("r").await.is_none());
+            assert!(store.list().await.is_empty());
+        }
+
+        /// Rule: a closure that leaves a missing room alone does not create it.
+        #[tokio::test]
+        async fn test_update_of_missing_room_is_a_no_op() {
+            let store = $new_store;
+            store.update("r", |slot| assert!(slot.is_none())).await;
+            assert!(store.list().await.is_empty());
+        }
+
+        /// Rule: concurrent read-modify-writes of a room are not lost.
+        #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+        async fn test_concurrent_updates_are_atomic() {
+            let store = Arc::new($new_store);
+            let tasks: Vec<_> = (0..50)
+                .map(|_| {
+                    let store = store.clone();
+                    tokio::spawn(async move {
+                        store
+                            .update("r", |slot| {
+                                slot.get_or_insert_with(GameState::default).round += 1
+                            })
+                            .await
+                    })
+                })
+                .collect();
+            for task in tasks {
+                task.await.unwrap();
+            }
+            assert_eq!(store.get("r").await.unwrap().round, 50);
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    mod memory {
+        room_store_contract!(crate::room_store::MemoryRoomStore::default());
+    }
+}
@@ -0,0 +1,110 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use log::{info, warn};
+
+use crate::connection_pool::ConnectionPool;
+use crate::structs::ServerMessage;
+
+/// Resolves on the first SIGINT (Ctrl-C) or, on Unix, SIGTERM.
+pub async fn signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!("Could not listen for Ctrl-C: {e}");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut stream) => {
+                stream.recv().await;
+            }
+            Err(e) => {
+                warn!("Could not listen for SIGTERM: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => {}
+        () = terminate => {}
+    }
+}
+
+/// Tell every client the server is restarting, close their sockets, and wait
+/// until `deadline` for the connections to wind down. Returns whether they
+/// all did.
+pub async fn drain_connections(pool: &ConnectionPool, deadline: Instant) -> bool {
+    let message = ServerMessage::ServerRestarting {
+        message: "The server is restarting. Reconnecting shortly...".to_string(),
+    };
+    let closing = pool.close_all(&message).await;
+    info!("Closing {closing} connection(s)");
+
+    let drained = tokio::time::timeout_at(deadline, async {
+        while !pool.is_empty().await {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .is_ok();
+    if !drained {
+        warn!("Connections still open at the shutdown deadline, shutting down anyway");
+    }
+    drained
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+    use warp::ws::Message;
+
+    use super::*;
+
+    /// Rule: draining pushes the restart notice then a close frame, and
+    /// finishes as soon as the connections leave the pool.
+    #[tokio::test]
+    async fn test_drain_notifies_and_waits_for_connections() {
+        let pool = ConnectionPool::new();
+        let (tx, mut rx) = mpsc::channel::<Message>(4);
+        pool.add("r".to_string(), "conn-a".to_string(), tx).await;
+
+        let driver = tokio::spawn({
+            let pool = pool.clone();
+            async move {
+                let notice = rx.recv().await.unwrap();
+                let close = rx.recv().await.unwrap();
+                pool.remove("r", "conn-a").await;
+                (notice, close)
+            }
+        });
+
+        assert!(drain_connections(&pool, Instant::now() + Duration::from_secs(5)).await);
+        let (notice, close) = driver.await.unwrap();
+        assert!(
+            notice
+                .to_str()
+                .unwrap()
+                .starts_with(r#"{"type":"ServerRestarting""#)
+        );
+        assert!(close.is_close());
+    }
+
+    /// Rule: a connection that never goes away does not hold up shutdown
+    /// past the deadline.
+    #[tokio::test]
+    async fn test_drain_gives_up_after_timeout() {
+        let pool = ConnectionPool::new();
+        let (tx, _rx) = mpsc::channel::<Message>(4);
+        pool.add("r".to_string(), "conn-a".to_string(), tx).await;
+
+        assert!(!drain_connections(&pool, Instant::now() + Duration::from_millis(100)).await);
+    }
+}
@@ -0,0 +1,248 @@
+use crate::structs::{CardCount, GameState, RoundSummary, Vote};
+
+/// Summarise the votes cast in `state`. Players at or above `overflow_index`
+/// are spectators and are left out.
+pub fn summarize_round(state: &GameState, overflow_index: usize) -> RoundSummary {
+    let cards = state.voting_sequence.cards();
+    let delegates: Vec<_> = state
+        .players
+        .iter()
+        .filter(|p| p.player_id < overflow_index)
+        .collect();
+
+    // (player_id, index of the card they voted for)
+    let votes: Vec<(usize, usize)> = delegates
+        .iter()
+        .filter_map(|p| {
+            let value = p.value?.card()?;
+            let index = cards.iter().position(|c| c.value == value)?;
+            Some((p.player_id, index))
+        })
+        .collect();
+
+    let mut distribution: Vec<CardCount> = cards
+        .iter()
+        .map(|c| CardCount {
+            value: c.value,
+            label: c.label.clone(),
+            count: 0,
+        })
+        .collect();
+    for &(_, index) in &votes {
+        distribution[index].count += 1;
+    }
+
+    let count = |vote: Vote| delegates.iter().filter(|p| p.value == Some(vote)).count();
+    let unknown = count(Vote::Unknown);
+    let mut summary = RoundSummary {
+        vote_count: votes.len(),
+        abstentions: count(Vote::Abstain),
+        unknown,
+        coffee: count(Vote::Coffee),
+        not_voted: delegates.iter().filter(|p| p.value.is_none()).count(),
+        consensus: unknown == 0
+            && votes
+                .first()
+                .is_some_and(|&(_, first)| votes.iter().all(|&(_, i)| i == first)),
+        ..Default::default()
+    };
+
+    if !votes.is_empty() && state.voting_sequence.is_numeric() {
+        let mut numbers: Vec<f64> = votes
+            .iter()
+            .filter_map(|&(_, i)| cards[i].label.parse().ok())
+            .collect();
+        numbers.sort_by(f64::total_cmp);
+
+        summary.mean = Some(numbers.iter().sum::<f64>() / numbers.len() as f64);
+        summary.median = Some(median(&numbers));
+        summary.min = numbers.first().copied();
+        summary.max = numbers.last().copied();
+
+        let top = distribution.iter().map(|c| c.count).max().unwrap_or(0);
+        let mut leaders = distribution.iter().filter(|c| c.count == top);
+        if let (Some(leader), None) = (leaders.next(), leaders.next()) {
+            summary.mode = leader.label.parse().ok();
+        }
+
+        // Outliers are measured in card steps rather than raw values, so a
+        // 21 next to a 13 on the Fibonacci scale is not flagged.
+        let mut positions: Vec<f64> = votes.iter().map(|&(_, i)| i as f64).collect();
+        positions.sort_by(f64::total_cmp);
+        let middle = median(&positions);
+        summary.outliers = votes
+            .iter()
+            .filter(|&&(_, i)| (i as f64 - middle).abs() > 1.0)
+            .map(|&(player_id, _)| player_id)
+            .collect();
+    }
+
+    summary.distribution = distribution;
+    summary
+}
+
+fn median(sorted: &[f64]) -> f64 {
+    let mid = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::{Card, PlayerState, VotingSequence};
+
+    fn state_with_votes(sequence: VotingSequence, votes: &[(usize, Option<u8>)]) -> GameState {
+        GameState {
+            players: votes
+                .iter()
+                .map(|&(player_id, value)| PlayerState {
+                    player_id,
+                    value: value.map(Vote::Card),
+                    ..Default::default()
+                })
+                .collect(),
+            all_revealed: true,
+            voting_sequence: sequence,
+            ..Default::default()
+        }
+    }
+
+    // ── Numeric sequences ────────────────────────────────────────────────────
+
+    /// Rule: numeric sequences get mean, median, mode and range over the cast
+    /// votes; delegates without a vote are counted separately.
+    #[test]
+    fn test_numeric_summary() {
+        let state = state_with_votes(
+            VotingSequence::Fibonacci,
+            &[
+                (0, Some(3)),
+                (1, Some(5)),
+                (2, Some(5)),
+                (3, Some(8)),
+                (4, None),
+            ],
+        );
+        let summary = summarize_round(&state, 100);
+        assert_eq!(summary.vote_count, 4);
+        assert_eq!(summary.not_voted, 1);
+        assert_eq!(summary.mean, Some(5.25));
+        assert_eq!(summary.median, Some(5.0));
+        assert_eq!(summary.mode, Some(5.0));
+        assert_eq!(summary.min, Some(3.0));
+        assert_eq!(summary.max, Some(8.0));
+        assert!(!summary.consensus);
+        assert!(summary.outliers.is_empty());
+    }
+
+    /// Rule: a tie for the most common vote has no mode.
+    #[test]
+    fn test_tied_votes_have_no_mode() {
+        let state = state_with_votes(VotingSequence::Linear, &[(0, Some(2)), (1, Some(4))]);
+        let summary = summarize_round(&state, 100);
+        assert_eq!(summary.mode, None);
+        assert_eq!(summary.median, Some(3.0));
+    }
+
+    /// Rule: votes more than one card away from the median are outliers.
+    #[test]
+    fn test_outliers_are_far_from_median() {
+        let state = state_with_votes(
+            VotingSequence::Fibonacci,
+            &[(0, Some(3)), (1, Some(3)), (2, Some(5)), (3, Some(21))],
+        );
+        let summary = summarize_round(&state, 100);
+        assert_eq!(summary.outliers, vec![3]);
+    }
+
+    /// Rule: identical votes are a consensus.
+    #[test]
+    fn test_identical_votes_are_consensus() {
+        let state = state_with_votes(VotingSequence::Fibonacci, &[(0, Some(8)), (1, Some(8))]);
+        assert!(summarize_round(&state, 100).consensus);
+    }
+
+    /// Rule: spectators are left out entirely and delegates without a vote
+    /// count as not voted.
+    #[test]
+    fn test_spectators_are_ignored() {
+        let state = state_with_votes(
+            VotingSequence::Fibonacci,
+            &[(0, None), (1, Some(5)), (100, Some(21))],
+        );
+        let summary = summarize_round(&state, 100);
+        assert_eq!(summary.vote_count, 1);
+        assert_eq!(summary.not_voted, 1);
+        assert_eq!(summary.max, Some(5.0));
+    }
+
+    // ── Categorical sequences ────────────────────────────────────────────────
+
+    /// Rule: non-numeric sequences only get a distribution, listed in
+    /// sequence order with zero counts included.
+    #[test]
+    fn test_categorical_summary_has_distribution_only() {
+        let state = state_with_votes(
+            VotingSequence::YeaNea,
+            &[(0, Some(1)), (1, Some(1)), (2, Some(2))],
+        );
+        let summary = summarize_round(&state, 100);
+        let counts: Vec<_> = summary
+            .distribution
+            .iter()
+            .map(|c| (c.label.as_str(), c.count))
+            .collect();
+        assert_eq!(counts, [("Yea", 2), ("Nea", 1)]);
+        assert_eq!(summary.mean, None);
+        assert_eq!(summary.mode, None);
+        assert!(summary.outliers.is_empty());
+    }
+
+    /// Rule: a custom sequence is numeric only if every label is a number.
+    #[test]
+    fn test_custom_sequence_numeric_detection() {
+        let card = |value: u8, label: &str| Card {
+            value,
+            label: label.to_string(),
+        };
+        let numeric = VotingSequence::Custom(vec![card(1, "0.5"), card(2, "1"), card(3, "2")]);
+        let state = state_with_votes(numeric, &[(0, Some(1)), (1, Some(3))]);
+        assert_eq!(summarize_round(&state, 100).mean, Some(1.25));
+
+        let mixed = VotingSequence::Custom(vec![card(1, "1"), card(2, "?")]);
+        let state = state_with_votes(mixed, &[(0, Some(1))]);
+        assert_eq!(summarize_round(&state, 100).mean, None);
+    }
+
+    // ── Special votes ────────────────────────────────────────────────────────
+
+    /// Rule: abstain, "?" and coffee votes are counted on their own and kept
+    /// out of the numeric statistics; a "?" blocks consensus.
+    #[test]
+    fn test_special_votes_are_counted_separately() {
+        let mut state = state_with_votes(
+            VotingSequence::Fibonacci,
+            &[(0, Some(5)), (1, Some(5)), (2, None), (3, None), (4, None)],
+        );
+        state.players[2].value = Some(Vote::Abstain);
+        state.players[3].value = Some(Vote::Coffee);
+
+        let summary = summarize_round(&state, 100);
+        assert_eq!(summary.vote_count, 2);
+        assert_eq!(
+            (summary.abstentions, summary.coffee, summary.not_voted),
+            (1, 1, 1)
+        );
+        assert_eq!(summary.mean, Some(5.0));
+        assert!(summary.consensus);
+
+        state.players[4].value = Some(Vote::Unknown);
+        let summary = summarize_round(&state, 100);
+        assert_eq!(summary.unknown, 1);
+        assert!(!summary.consensus);
+    }
+}
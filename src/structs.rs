@@ -1,21 +1,140 @@
+use std::fmt;
+use std::sync::Arc;
+
 use futures::stream::{SplitSink, SplitStream};
 use serde::{Deserialize, Serialize};
-use tokio::sync::broadcast::{Receiver, Sender};
+use tokio::sync::broadcast::Receiver;
+use tokio::sync::mpsc;
 use warp::ws::{Message, WebSocket};
 
+use crate::passphrase::{Passphrase, Secret};
+use crate::room_registry::RoomRegistry;
+
 #[derive(Clone, Debug)]
 pub struct RoomUpdate {
     pub room: String,
     pub state: GameState,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq)]
 pub struct PlayerState {
     pub player_id: usize,
     pub player_name: String,
-    pub value: Option<u8>,
+    pub value: Option<Vote>,
+    /// Whether the player has cast a vote. Filled in when the state is
+    /// projected for a recipient, so clients can show who is ready without
+    /// seeing the value itself.
+    #[serde(default)]
+    pub has_voted: bool,
+    /// Set when the client has stopped answering pings.
+    #[serde(default)]
+    pub away: bool,
+    /// Set on a spectator the captain moved off their seat. They may only
+    /// be seated again by the captain.
+    #[serde(default)]
+    pub benched: bool,
     #[serde(default, skip_serializing, skip_deserializing)]
     pub connection_id: String,
+    /// Resumes the seat, so it also opens a locked room and authorizes the
+    /// captain's imports. Left out of `Debug` to keep it out of the logs.
+    #[serde(default, skip_serializing, skip_deserializing)]
+    pub resume_token: String,
+}
+
+impl fmt::Debug for PlayerState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PlayerState")
+            .field("player_id", &self.player_id)
+            .field("player_name", &self.player_name)
+            .field("value", &self.value)
+            .field("has_voted", &self.has_voted)
+            .field("away", &self.away)
+            .field("benched", &self.benched)
+            .field("connection_id", &self.connection_id)
+            .field("resume_token", &"***")
+            .finish()
+    }
+}
+
+/// A delegate's vote.
+///
+/// On the wire a card is its number and every other vote is its name, e.g.
+/// `5` or `"Abstain"`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(from = "VoteRepr", into = "VoteRepr")]
+pub enum Vote {
+    /// A card from the room's voting sequence.
+    Card(u8),
+    /// The delegate deliberately sits the round out.
+    Abstain,
+    /// "?": the delegate needs more information.
+    Unknown,
+    /// The delegate asks for a break.
+    Coffee,
+}
+
+impl Vote {
+    /// The chosen card, if any.
+    pub fn card(self) -> Option<u8> {
+        match self {
+            Vote::Card(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// How a non-card vote is shown on reveal.
+    pub fn special_label(self) -> Option<&'static str> {
+        match self {
+            Vote::Card(_) => None,
+            Vote::Abstain => Some("Abstain"),
+            Vote::Unknown => Some("?"),
+            Vote::Coffee => Some("☕"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum VoteRepr {
+    Card(u8),
+    Named(NamedVote),
+}
+
+#[derive(Serialize, Deserialize)]
+enum NamedVote {
+    Abstain,
+    Unknown,
+    Coffee,
+}
+
+impl From<VoteRepr> for Vote {
+    fn from(repr: VoteRepr) -> Self {
+        match repr {
+            VoteRepr::Card(value) => Vote::Card(value),
+            VoteRepr::Named(NamedVote::Abstain) => Vote::Abstain,
+            VoteRepr::Named(NamedVote::Unknown) => Vote::Unknown,
+            VoteRepr::Named(NamedVote::Coffee) => Vote::Coffee,
+        }
+    }
+}
+
+impl From<Vote> for VoteRepr {
+    fn from(vote: Vote) -> Self {
+        match vote {
+            Vote::Card(value) => VoteRepr::Card(value),
+            Vote::Abstain => VoteRepr::Named(NamedVote::Abstain),
+            Vote::Unknown => VoteRepr::Named(NamedVote::Unknown),
+            Vote::Coffee => VoteRepr::Named(NamedVote::Coffee),
+        }
+    }
+}
+
+// A single card in a voting sequence. `value` is what the client sends in
+// `ChangeValue`; `label` is what is shown on the card.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Card {
+    pub value: u8,
+    pub label: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
@@ -25,14 +144,214 @@ pub enum VotingSequence {
     Linear,
     SmMedLgXl,
     YeaNea,
+    /// An ordered list of cards defined by the room captain.
+    Custom(Vec<Card>),
+}
+
+impl VotingSequence {
+    const FIBONACCI: &[(u8, &str)] = &[
+        (1, "1"),
+        (2, "2"),
+        (3, "3"),
+        (5, "5"),
+        (8, "8"),
+        (13, "13"),
+        (21, "21"),
+    ];
+    const LINEAR: &[(u8, &str)] = &[
+        (1, "1"),
+        (2, "2"),
+        (3, "3"),
+        (4, "4"),
+        (5, "5"),
+        (6, "6"),
+        (7, "7"),
+        (8, "8"),
+        (9, "9"),
+        (10, "10"),
+    ];
+    const SM_MED_LG_XL: &[(u8, &str)] = &[(1, "S"), (2, "M"), (3, "L"), (4, "XL")];
+    const YEA_NEA: &[(u8, &str)] = &[(1, "Yea"), (2, "Nea")];
+
+    /// The canonical cards of this sequence, in display order.
+    pub fn cards(&self) -> Vec<Card> {
+        let builtin = match self {
+            VotingSequence::Fibonacci => Self::FIBONACCI,
+            VotingSequence::Linear => Self::LINEAR,
+            VotingSequence::SmMedLgXl => Self::SM_MED_LG_XL,
+            VotingSequence::YeaNea => Self::YEA_NEA,
+            VotingSequence::Custom(cards) => return cards.clone(),
+        };
+        builtin
+            .iter()
+            .map(|&(value, label)| Card {
+                value,
+                label: label.to_string(),
+            })
+            .collect()
+    }
+
+    /// Whether every card label is a number, so votes can be averaged.
+    pub fn is_numeric(&self) -> bool {
+        match self {
+            VotingSequence::Fibonacci | VotingSequence::Linear => true,
+            VotingSequence::SmMedLgXl | VotingSequence::YeaNea => false,
+            VotingSequence::Custom(cards) => cards.iter().all(|c| c.label.parse::<f64>().is_ok()),
+        }
+    }
+
+    /// Whether `value` is a card in this sequence.
+    pub fn contains(&self, value: u8) -> bool {
+        match self {
+            VotingSequence::Custom(cards) => cards.iter().any(|c| c.value == value),
+            _ => self.cards().iter().any(|c| c.value == value),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
 pub struct GameState {
     pub players: Vec<PlayerState>,
     pub all_revealed: bool,
-    pub notify_change: NotifyChange,
+    /// Seat moves made by the last change, so each moved client can follow
+    /// its new ID.
+    #[serde(default)]
+    pub notify_changes: Vec<NotifyChange>,
     pub voting_sequence: VotingSequence,
+    /// Statistics for the current round. Only present while votes are
+    /// revealed.
+    #[serde(default)]
+    pub round_summary: Option<RoundSummary>,
+    /// Topics still waiting to be estimated, in order.
+    #[serde(default)]
+    pub agenda: Vec<Topic>,
+    /// The topic the current round is about, if any.
+    #[serde(default)]
+    pub current_topic: Option<Topic>,
+    /// Completed rounds, oldest first. Entries are never modified, so a
+    /// connection is sent the whole history in its first update and
+    /// afterwards only the rounds it has not seen yet. It is shared between
+    /// copies of the state, since every update copies the state once per
+    /// connection.
+    #[serde(default, skip_serializing_if = "<[RoundRecord]>::is_empty")]
+    pub history: Arc<Vec<RoundRecord>>,
+    /// Seconds since the Unix epoch at which the current round started.
+    #[serde(default)]
+    pub round_started_at: u64,
+    #[serde(default)]
+    pub revealed_at: Option<u64>,
+    /// Number of the current round, counting from 1 when the room is created.
+    #[serde(default)]
+    pub round: u64,
+    #[serde(default)]
+    pub auto_reveal: AutoReveal,
+    /// When a pending auto-reveal fires, in seconds since the Unix epoch.
+    #[serde(default)]
+    pub auto_reveal_at: Option<u64>,
+    /// The discussion timer for the current round, if the captain started one.
+    #[serde(default)]
+    pub timer: Option<Timer>,
+    /// The delegate who may change the room's settings and agenda. It only
+    /// changes hands when transferred or when the captain leaves.
+    #[serde(default)]
+    pub captain_id: Option<usize>,
+    /// Whether joining the room needs a passphrase.
+    #[serde(default)]
+    pub locked: bool,
+    #[serde(skip)]
+    pub passphrase: Option<Passphrase>,
+}
+
+// A countdown run by the server. Timestamps are seconds since the Unix epoch.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Timer {
+    pub started_at: u64,
+    pub ends_at: u64,
+    /// Seconds left when the state was sent, so clients need not trust their
+    /// own clock.
+    #[serde(default)]
+    pub remaining_secs: u64,
+    pub reveal_on_expiry: bool,
+    #[serde(default)]
+    pub expired: bool,
+}
+
+// Reveal the votes by themselves once every delegate has voted.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct AutoReveal {
+    pub enabled: bool,
+    /// Seconds to count down before revealing; 0 reveals at once.
+    #[serde(default)]
+    pub countdown_secs: u64,
+}
+
+// An item on a room's agenda, e.g. a story or a resolution.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Topic {
+    pub id: String,
+    pub title: String,
+    #[serde(default)]
+    pub link: Option<String>,
+    /// The item's ID in an imported backlog, e.g. a tracker ticket number.
+    #[serde(default)]
+    pub key: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct VoteRecord {
+    pub player_id: usize,
+    pub player_name: String,
+    /// `None` when the delegate did not vote.
+    pub value: Option<Vote>,
+    pub label: Option<String>,
+}
+
+// A completed round. Timestamps are seconds since the Unix epoch.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct RoundRecord {
+    #[serde(default)]
+    pub round: u64,
+    pub topic: Option<Topic>,
+    pub votes: Vec<VoteRecord>,
+    pub summary: RoundSummary,
+    pub started_at: u64,
+    pub revealed_at: u64,
+    pub completed_at: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct CardCount {
+    pub value: u8,
+    pub label: String,
+    pub count: usize,
+}
+
+// Statistics over the votes of a revealed round. The numeric fields are only
+// filled in for sequences whose card labels are all numbers.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct RoundSummary {
+    /// Card votes.
+    pub vote_count: usize,
+    pub abstentions: usize,
+    /// "?" votes.
+    pub unknown: usize,
+    pub coffee: usize,
+    /// Seated delegates who did not vote at all.
+    pub not_voted: usize,
+    /// Votes per card, in sequence order.
+    pub distribution: Vec<CardCount>,
+    pub mean: Option<f64>,
+    pub median: Option<f64>,
+    /// The single most common vote, if one card leads outright.
+    pub mode: Option<f64>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    /// Every card vote landed on the same card and nobody voted "?".
+    pub consensus: bool,
+    /// Players whose vote is more than one card away from the median vote.
+    pub outliers: Vec<usize>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
@@ -47,19 +366,92 @@ pub struct NotifyChange {
 pub enum ClientMessage {
     ChangeValue {
         player_id: usize,
-        value: u8,
+        value: Vote,
+    },
+    /// Take back the vote cast this round, leaving the player unvoted.
+    WithdrawVote {
+        player_id: usize,
     },
     ChangeName {
         player_id: usize,
         name: String,
     },
+    /// Show or hide the votes. Hiding revealed votes starts a new round, as
+    /// `StartNewRound` does; it is kept for older clients.
     RevealNumbers {
         value: bool,
     },
+    /// Close the current round and clear every vote.
+    StartNewRound,
+    StartTimer {
+        player_id: usize,
+        seconds: u64,
+        #[serde(default)]
+        reveal_on_expiry: bool,
+    },
+    StopTimer {
+        player_id: usize,
+    },
+    TransferCaptain {
+        player_id: usize,
+        new_captain_id: usize,
+    },
+    /// Lock the room with a passphrase, or unlock it with `None`.
+    SetPassphrase {
+        player_id: usize,
+        #[serde(default)]
+        passphrase: Option<Secret>,
+    },
+    /// Remove a player from the room and close their connection.
+    KickPlayer {
+        player_id: usize,
+        target_id: usize,
+    },
+    /// Move a delegate to the spectators.
+    DemotePlayer {
+        player_id: usize,
+        target_id: usize,
+    },
+    /// Seat a spectator as a delegate, in `seat` or else the lowest free one.
+    PromoteSpectator {
+        player_id: usize,
+        target_id: usize,
+        #[serde(default)]
+        seat: Option<usize>,
+    },
     ChangeSequence {
         player_id: usize,
         sequence: VotingSequence,
     },
+    ChangeAutoReveal {
+        player_id: usize,
+        auto_reveal: AutoReveal,
+    },
+    AddTopic {
+        player_id: usize,
+        title: String,
+        #[serde(default)]
+        link: Option<String>,
+    },
+    /// Move a topic to `index` within the agenda.
+    MoveTopic {
+        player_id: usize,
+        topic_id: String,
+        index: usize,
+    },
+    RemoveTopic {
+        player_id: usize,
+        topic_id: String,
+    },
+    /// Append the items of a pasted CSV or JSON backlog to the agenda.
+    ImportTopics {
+        player_id: usize,
+        data: String,
+    },
+    /// Close the current round and start voting on the next agenda topic.
+    AdvanceTopic {
+        player_id: usize,
+    },
     Pong {
         player_id: usize,
     },
@@ -74,16 +466,85 @@ pub enum ClientMessage {
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum ServerMessage {
-    UpdateState(GameState),
-    PlayerAssigned { player_id: usize },
-    ErrorMessage(String),
-    Ping { data: usize },
+    UpdateState(Box<GameState>),
+    PlayerAssigned {
+        player_id: usize,
+        resume_token: String,
+        /// Number of delegate seats in the room.
+        max_room_size: usize,
+        /// First player ID handed to spectators.
+        overflow_index: usize,
+    },
+    ErrorMessage {
+        message: String,
+    },
+    Ping {
+        data: usize,
+    },
+    /// The room is locked and the connection did not present its
+    /// passphrase. It is closed without being given a seat.
+    PassphraseRequired {
+        message: String,
+    },
+    /// The connection no longer holds a seat in the room, e.g. because the
+    /// captain kicked it, and is about to be closed.
+    Removed {
+        message: String,
+    },
+    /// The server is shutting down and is about to close the connection.
+    /// Clients should reconnect to resume their seat.
+    ServerRestarting {
+        message: String,
+    },
+}
+
+// Query parameters accepted on the WebSocket upgrade.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ConnectionQuery {
+    pub resume: Option<String>,
+    /// Needed to join a locked room. Joining a room that does not exist yet
+    /// with a passphrase creates it locked.
+    pub passphrase: Option<String>,
 }
 
 // A simple structure to help tidy the connections between functions.
 pub struct ConnectionContext {
-    pub tx: Sender<RoomUpdate>,
+    pub rooms: Arc<RoomRegistry>,
     pub rx: Receiver<RoomUpdate>,
     pub ws_tx: SplitSink<WebSocket, Message>,
     pub ws_rx: SplitStream<WebSocket>,
+    /// Messages pushed to this connection through the `ConnectionPool`.
+    pub outbox: mpsc::Receiver<Message>,
+    /// Number of history rounds sent to this connection, once its first
+    /// update has gone out.
+    pub history_sent: Option<usize>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Rule: a player's resume token never shows up in debug output.
+    #[test]
+    fn test_player_debug_redacts_resume_token() {
+        let player = PlayerState {
+            player_id: 3,
+            resume_token: "secret-token".to_string(),
+            ..Default::default()
+        };
+        let debug = format!("{player:?}");
+        assert!(debug.contains("player_id: 3"));
+        assert!(!debug.contains("secret-token"));
+    }
+
+    /// Rule: a passphrase sent to lock the room never shows up in debug
+    /// output of the message.
+    #[test]
+    fn test_set_passphrase_debug_redacts_passphrase() {
+        let message: ClientMessage = serde_json::from_str(
+            r#"{"type":"SetPassphrase","player_id":0,"passphrase":"hunter2"}"#,
+        )
+        .unwrap();
+        assert!(!format!("{message:?}").contains("hunter2"));
+    }
 }
@@ -4,30 +4,68 @@
 //! `warp::test::ws()` and exercises the full message flow that a real browser
 //! client would experience.
 //!
-//! Because `Game::instance()` is a process-wide singleton, every test uses a
-//! unique room name so that parallel test runs do not share game state.
+//! Every test builds its own `Game`, so tests running in parallel never share
+//! rooms.
 
+use model_un::config::Config;
 use model_un::connection_pool::ConnectionPool;
+use model_un::game::Game;
 use model_un::interface::GameWebSocket;
-use model_un::structs::{ClientMessage, GameState, RoomUpdate, ServerMessage};
-use tokio::sync::broadcast;
+use std::sync::Arc;
+
+use model_un::room_registry::RoomRegistry;
+use model_un::room_store::{MemoryRoomStore, RoomStore};
+use model_un::structs::{
+    AutoReveal, ClientMessage, ConnectionQuery, GameState, ServerMessage, Vote,
+};
 use warp::Filter;
 
 // ── Helper ────────────────────────────────────────────────────────────────────
 
+fn new_game() -> Arc<Game> {
+    Game::new(&Config::default())
+}
+
+fn new_rooms() -> Arc<RoomRegistry> {
+    RoomRegistry::with_capacity(Config::default().channel_capacity)
+}
+
 /// Builds a warp filter for the `/ws/<room>` route that mirrors the setup in
-/// `main()`.  Each call creates an independent broadcast channel and connection
-/// pool so tests remain isolated from each other.
+/// `main()`, serving `game` with its own connection pool.
 fn build_ws_filter(
-    tx: broadcast::Sender<RoomUpdate>,
+    game: Arc<Game>,
+    rooms: Arc<RoomRegistry>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    build_ws_filter_with_pool(game, rooms, ConnectionPool::new())
+}
+
+fn build_ws_filter_with_pool(
+    game: Arc<Game>,
+    rooms: Arc<RoomRegistry>,
+    pool: Arc<ConnectionPool>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    build_ws_filter_with_config(game, rooms, pool, Config::default())
+}
+
+fn build_ws_filter_with_config(
+    game: Arc<Game>,
+    rooms: Arc<RoomRegistry>,
+    pool: Arc<ConnectionPool>,
+    config: Config,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-    let tx_filter = warp::any().map(move || tx.clone());
-    let pool_filter = warp::any().map(ConnectionPool::new);
+    let game_filter = warp::any().map(move || game.clone());
+    let rooms_filter = warp::any().map(move || rooms.clone());
+    let pool_filter = warp::any().map(move || pool.clone());
+    let config = Arc::new(config);
+    let config_filter = warp::any().map(move || config.clone());
     warp::path("ws")
         .and(warp::path::param::<String>())
+        .and(warp::query::<ConnectionQuery>())
         .and(warp::ws())
-        .and(tx_filter)
+        .and(game_filter)
+        .and(rooms_filter)
         .and(pool_filter)
+        .and(config_filter)
         .and_then(GameWebSocket::handle_connection)
 }
 
@@ -49,6 +87,29 @@ async fn recv_next_non_ping(client: &mut warp::test::WsClient) -> ServerMessage
     }
 }
 
+/// Waits for the server to close the socket. Room updates and pings already
+/// in flight when the close was queued may still arrive first.
+async fn recv_until_closed(client: &mut warp::test::WsClient) {
+    loop {
+        let Ok(msg) = client.recv().await else {
+            return;
+        };
+        if msg.is_close() {
+            continue;
+        }
+        let server_msg: ServerMessage =
+            serde_json::from_str(msg.to_str().expect("Message should be text"))
+                .expect("Should deserialize to ServerMessage");
+        assert!(
+            matches!(
+                server_msg,
+                ServerMessage::UpdateState(_) | ServerMessage::Ping { .. }
+            ),
+            "Expected the socket to close, got: {server_msg:?}"
+        );
+    }
+}
+
 // ── Connection handshake
 // ──────────────────────────────────────────────────────
 
@@ -56,8 +117,7 @@ async fn recv_next_non_ping(client: &mut warp::test::WsClient) -> ServerMessage
 /// `PlayerAssigned` message containing the new player's ID.
 #[tokio::test]
 async fn test_connection_receives_player_assigned() {
-    let (tx, _rx) = broadcast::channel::<RoomUpdate>(255);
-    let filter = build_ws_filter(tx);
+    let filter = build_ws_filter(new_game(), new_rooms());
 
     let mut client = warp::test::ws()
         .path("/ws/it-player-assigned")
@@ -72,12 +132,42 @@ async fn test_connection_receives_player_assigned() {
     );
 }
 
+/// `PlayerAssigned` carries the room size and spectator IDs the server was
+/// configured with, so the client does not have to assume the defaults.
+#[tokio::test]
+async fn test_player_assigned_carries_room_layout() {
+    let config = Config {
+        max_room_size: 4,
+        overflow_index: 10,
+        ..Config::default()
+    };
+    let filter = build_ws_filter(Game::new(&config), new_rooms());
+
+    let mut client = warp::test::ws()
+        .path("/ws/it-room-layout")
+        .handshake(filter)
+        .await
+        .expect("WebSocket handshake should succeed");
+
+    let server_msg = recv_next_non_ping(&mut client).await;
+    assert!(
+        matches!(
+            server_msg,
+            ServerMessage::PlayerAssigned {
+                max_room_size: 4,
+                overflow_index: 10,
+                ..
+            }
+        ),
+        "got: {server_msg:?}"
+    );
+}
+
 /// The second message sent on connect is always an `UpdateState` carrying the
 /// current room snapshot.
 #[tokio::test]
 async fn test_connection_receives_initial_state() {
-    let (tx, _rx) = broadcast::channel::<RoomUpdate>(255);
-    let filter = build_ws_filter(tx);
+    let filter = build_ws_filter(new_game(), new_rooms());
 
     let mut client = warp::test::ws()
         .path("/ws/it-initial-state")
@@ -104,8 +194,7 @@ async fn test_connection_receives_initial_state() {
 /// list.
 #[tokio::test]
 async fn test_initial_state_contains_connecting_player() {
-    let (tx, _rx) = broadcast::channel::<RoomUpdate>(255);
-    let filter = build_ws_filter(tx);
+    let filter = build_ws_filter(new_game(), new_rooms());
 
     let mut client = warp::test::ws()
         .path("/ws/it-initial-player-list")
@@ -115,16 +204,16 @@ async fn test_initial_state_contains_connecting_player() {
 
     // Capture the assigned player_id
     let player_id = loop {
-        match recv_next_non_ping(&mut client).await {
-            ServerMessage::PlayerAssigned { player_id } => break player_id,
-            _ => {}
+        if let ServerMessage::PlayerAssigned { player_id, .. } =
+            recv_next_non_ping(&mut client).await
+        {
+            break player_id;
         }
     };
 
     let state = loop {
-        match recv_next_non_ping(&mut client).await {
-            ServerMessage::UpdateState(s) => break s,
-            _ => {}
+        if let ServerMessage::UpdateState(s) = recv_next_non_ping(&mut client).await {
+            break s;
         }
     };
 
@@ -140,9 +229,8 @@ async fn test_initial_state_contains_connecting_player() {
 /// Reads messages, skipping Pings, until an `UpdateState` is found.
 async fn recv_update_state(client: &mut warp::test::WsClient) -> GameState {
     loop {
-        match recv_next_non_ping(client).await {
-            ServerMessage::UpdateState(s) => return s,
-            _ => {}
+        if let ServerMessage::UpdateState(s) = recv_next_non_ping(client).await {
+            return *s;
         }
     }
 }
@@ -150,7 +238,7 @@ async fn recv_update_state(client: &mut warp::test::WsClient) -> GameState {
 /// Reads messages until `PlayerAssigned` is found and returns the player_id.
 async fn recv_player_assigned(client: &mut warp::test::WsClient) -> usize {
     loop {
-        if let ServerMessage::PlayerAssigned { player_id } = recv_next_non_ping(client).await {
+        if let ServerMessage::PlayerAssigned { player_id, .. } = recv_next_non_ping(client).await {
             return player_id;
         }
     }
@@ -160,8 +248,7 @@ async fn recv_player_assigned(client: &mut warp::test::WsClient) -> usize {
 /// the player's name reflects the requested change.
 #[tokio::test]
 async fn test_change_name_updates_state() {
-    let (tx, _rx) = broadcast::channel::<RoomUpdate>(255);
-    let filter = build_ws_filter(tx);
+    let filter = build_ws_filter(new_game(), new_rooms());
 
     let mut client = warp::test::ws()
         .path("/ws/it-change-name")
@@ -195,8 +282,7 @@ async fn test_change_name_updates_state() {
 /// the player's vote value reflects the requested change.
 #[tokio::test]
 async fn test_change_value_updates_state() {
-    let (tx, _rx) = broadcast::channel::<RoomUpdate>(255);
-    let filter = build_ws_filter(tx);
+    let filter = build_ws_filter(new_game(), new_rooms());
 
     let mut client = warp::test::ws()
         .path("/ws/it-change-value")
@@ -211,7 +297,7 @@ async fn test_change_value_updates_state() {
         .send_text(
             serde_json::to_string(&ClientMessage::ChangeValue {
                 player_id,
-                value: 5,
+                value: Vote::Card(5),
             })
             .unwrap(),
         )
@@ -223,15 +309,14 @@ async fn test_change_value_updates_state() {
         .iter()
         .find(|p| p.player_id == player_id)
         .expect("Player must be in state");
-    assert_eq!(player.value, Some(5));
+    assert_eq!(player.value, Some(Vote::Card(5)));
 }
 
 /// Sending `RevealNumbers { true }` sets `all_revealed` to true in the
 /// broadcast state.
 #[tokio::test]
 async fn test_reveal_numbers_sets_all_revealed_flag() {
-    let (tx, _rx) = broadcast::channel::<RoomUpdate>(255);
-    let filter = build_ws_filter(tx);
+    let filter = build_ws_filter(new_game(), new_rooms());
 
     let mut client = warp::test::ws()
         .path("/ws/it-reveal")
@@ -250,12 +335,73 @@ async fn test_reveal_numbers_sets_all_revealed_flag() {
     assert!(state.all_revealed, "all_revealed must be true after reveal");
 }
 
-/// Sending `RevealNumbers { false }` after a reveal resets every player's value
-/// to `Some(0)` and clears `all_revealed`.
+/// The round history is sent whole in a connection's first update, even when
+/// empty; later updates only carry the rounds completed since.
+#[tokio::test]
+async fn test_history_is_only_sent_when_it_grows() {
+    let filter = build_ws_filter(new_game(), new_rooms());
+
+    let mut client = warp::test::ws()
+        .path("/ws/it-history")
+        .handshake(filter)
+        .await
+        .expect("WebSocket handshake should succeed");
+
+    let player_id = recv_player_assigned(&mut client).await;
+    let initial = client.recv().await.expect("initial UpdateState");
+    let initial: serde_json::Value = serde_json::from_str(initial.to_str().unwrap()).unwrap();
+    assert_eq!(initial["type"], "UpdateState");
+    assert_eq!(
+        initial["history"],
+        serde_json::json!([]),
+        "the first update must replace the client's history"
+    );
+
+    for (round, card) in [(1, 3), (2, 5)] {
+        for message in [
+            ClientMessage::ChangeValue {
+                player_id,
+                value: Vote::Card(card),
+            },
+            ClientMessage::RevealNumbers { value: true },
+        ] {
+            client
+                .send_text(serde_json::to_string(&message).unwrap())
+                .await;
+            let _ = recv_update_state(&mut client).await;
+        }
+
+        client
+            .send_text(serde_json::to_string(&ClientMessage::StartNewRound).unwrap())
+            .await;
+        let state = recv_update_state(&mut client).await;
+        assert_eq!(state.round, round + 1);
+        assert_eq!(state.history.len(), 1, "only the completed round is sent");
+        assert_eq!(state.history[0].round, round);
+        assert_eq!(state.history[0].votes[0].value, Some(Vote::Card(card)));
+    }
+
+    client
+        .send_text(
+            serde_json::to_string(&ClientMessage::ChangeName {
+                player_id,
+                name: "Delegate".to_string(),
+            })
+            .unwrap(),
+        )
+        .await;
+    let state = recv_update_state(&mut client).await;
+    assert!(
+        state.history.is_empty(),
+        "unchanged history must be omitted"
+    );
+}
+
+/// Sending `RevealNumbers { false }` after a reveal clears every player's
+/// value and `all_revealed`.
 #[tokio::test]
 async fn test_hide_numbers_resets_values() {
-    let (tx, _rx) = broadcast::channel::<RoomUpdate>(255);
-    let filter = build_ws_filter(tx);
+    let filter = build_ws_filter(new_game(), new_rooms());
 
     let mut client = warp::test::ws()
         .path("/ws/it-hide")
@@ -271,7 +417,7 @@ async fn test_hide_numbers_resets_values() {
         .send_text(
             serde_json::to_string(&ClientMessage::ChangeValue {
                 player_id,
-                value: 8,
+                value: Vote::Card(8),
             })
             .unwrap(),
         )
@@ -284,7 +430,7 @@ async fn test_hide_numbers_resets_values() {
         .await;
     let _ = recv_update_state(&mut client).await; // UpdateState revealed
 
-    // Hide – values must be zeroed
+    // Hide – values must be cleared
     client
         .send_text(serde_json::to_string(&ClientMessage::RevealNumbers { value: false }).unwrap())
         .await;
@@ -293,10 +439,10 @@ async fn test_hide_numbers_resets_values() {
     assert!(!state.all_revealed, "all_revealed must be false after hide");
     if let Some(player) = state.players.iter().find(|p| p.player_id == player_id) {
         assert_eq!(
-            player.value,
-            Some(0),
-            "Player value must be reset to 0 after hide"
+            player.value, None,
+            "Player value must be cleared after hide"
         );
+        assert!(!player.has_voted, "Player must not appear to have voted");
     }
 }
 
@@ -304,8 +450,7 @@ async fn test_hide_numbers_resets_values() {
 /// same state back to confirm the Pong was processed.
 #[tokio::test]
 async fn test_pong_does_not_change_game_state() {
-    let (tx, _rx) = broadcast::channel::<RoomUpdate>(255);
-    let filter = build_ws_filter(tx);
+    let filter = build_ws_filter(new_game(), new_rooms());
 
     let mut client = warp::test::ws()
         .path("/ws/it-pong")
@@ -329,15 +474,144 @@ async fn test_pong_does_not_change_game_state() {
     );
 }
 
+/// With auto-reveal on and a countdown, the last vote broadcasts the
+/// deadline and the server reveals the round by itself once it passes.
+#[tokio::test]
+async fn test_auto_reveal_countdown_reveals_round() {
+    let filter = build_ws_filter(new_game(), new_rooms());
+
+    let mut client = warp::test::ws()
+        .path("/ws/it-auto-reveal")
+        .handshake(filter)
+        .await
+        .expect("WebSocket handshake should succeed");
+
+    let player_id = recv_player_assigned(&mut client).await;
+    let _ = recv_update_state(&mut client).await; // initial UpdateState
+
+    client
+        .send_text(
+            serde_json::to_string(&ClientMessage::ChangeAutoReveal {
+                player_id,
+                auto_reveal: AutoReveal {
+                    enabled: true,
+                    countdown_secs: 1,
+                },
+            })
+            .unwrap(),
+        )
+        .await;
+    let _ = recv_update_state(&mut client).await;
+
+    client
+        .send_text(
+            serde_json::to_string(&ClientMessage::ChangeValue {
+                player_id,
+                value: Vote::Card(5),
+            })
+            .unwrap(),
+        )
+        .await;
+    let state = recv_update_state(&mut client).await;
+    assert!(!state.all_revealed);
+    assert!(
+        state.auto_reveal_at.is_some(),
+        "countdown must be broadcast"
+    );
+
+    let state = tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        recv_update_state(&mut client),
+    )
+    .await
+    .expect("the server must reveal when the countdown ends");
+    assert!(state.all_revealed);
+    assert_eq!(state.auto_reveal_at, None);
+}
+
+/// A timer started by the captain reaches clients that join mid-countdown,
+/// and the server reveals the round when it runs out.
+#[tokio::test]
+async fn test_timer_is_shared_and_reveals_on_expiry() {
+    let game = new_game();
+    let rooms = new_rooms();
+
+    let mut captain = warp::test::ws()
+        .path("/ws/it-timer")
+        .handshake(build_ws_filter(game.clone(), rooms.clone()))
+        .await
+        .expect("WebSocket handshake should succeed");
+    let player_id = recv_player_assigned(&mut captain).await;
+    let _ = recv_update_state(&mut captain).await; // initial UpdateState
+
+    captain
+        .send_text(
+            serde_json::to_string(&ClientMessage::StartTimer {
+                player_id,
+                seconds: 1,
+                reveal_on_expiry: true,
+            })
+            .unwrap(),
+        )
+        .await;
+    let state = recv_update_state(&mut captain).await;
+    let timer = state.timer.expect("timer must be broadcast");
+    assert!(!timer.expired);
+
+    let mut late = warp::test::ws()
+        .path("/ws/it-timer")
+        .handshake(build_ws_filter(game.clone(), rooms.clone()))
+        .await
+        .expect("WebSocket handshake should succeed");
+    let _ = recv_player_assigned(&mut late).await;
+    let state = recv_update_state(&mut late).await;
+    assert_eq!(
+        state.timer.map(|t| t.ends_at),
+        Some(timer.ends_at),
+        "late joiners get the server's deadline"
+    );
+
+    let state = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        loop {
+            let state = recv_update_state(&mut captain).await;
+            if state.timer.as_ref().is_some_and(|t| t.expired) {
+                return state;
+            }
+        }
+    })
+    .await
+    .expect("the server must end the timer");
+    assert!(state.all_revealed);
+}
+
 // ── Multi-client broadcast
 // ────────────────────────────────────────────────────
 
+/// Two servers built from separate games share nothing, even rooms with the
+/// same name.
+#[tokio::test]
+async fn test_separate_games_are_isolated() {
+    for _ in 0..2 {
+        let mut client = warp::test::ws()
+            .path("/ws/it-isolated")
+            .handshake(build_ws_filter(new_game(), new_rooms()))
+            .await
+            .expect("WebSocket handshake should succeed");
+        assert_eq!(recv_player_assigned(&mut client).await, 0);
+        let state = recv_update_state(&mut client).await;
+        assert_eq!(
+            state.players.len(),
+            1,
+            "the other game's player must not show"
+        );
+    }
+}
+
 /// When one client sends a message, all clients in the same room receive the
 /// resulting `UpdateState` broadcast.
 #[tokio::test]
 async fn test_multiple_clients_receive_state_updates() {
-    let (tx, _rx) = broadcast::channel::<RoomUpdate>(255);
-    let filter = build_ws_filter(tx);
+    let filter = build_ws_filter(new_game(), new_rooms());
 
     // Connect client 1
     let mut client1 = warp::test::ws()
@@ -389,6 +663,471 @@ async fn test_multiple_clients_receive_state_updates() {
     assert_eq!(p2.player_name, "Broadcaster");
 }
 
+/// Votes stay secret until the reveal: other clients only learn that a
+/// delegate has voted, not what they voted.
+#[tokio::test]
+async fn test_votes_hidden_from_other_clients_until_reveal() {
+    let filter = build_ws_filter(new_game(), new_rooms());
+
+    let mut client1 = warp::test::ws()
+        .path("/ws/it-secret-votes")
+        .handshake(filter.clone())
+        .await
+        .expect("Client 1 handshake should succeed");
+    let player_id1 = recv_player_assigned(&mut client1).await;
+    let _ = recv_update_state(&mut client1).await;
+
+    let mut client2 = warp::test::ws()
+        .path("/ws/it-secret-votes")
+        .handshake(filter.clone())
+        .await
+        .expect("Client 2 handshake should succeed");
+    let _ = recv_player_assigned(&mut client2).await;
+    let _ = recv_update_state(&mut client2).await;
+
+    client1
+        .send_text(
+            serde_json::to_string(&ClientMessage::ChangeValue {
+                player_id: player_id1,
+                value: Vote::Card(13),
+            })
+            .unwrap(),
+        )
+        .await;
+
+    let own_view = recv_update_state(&mut client1).await;
+    let p1 = own_view
+        .players
+        .iter()
+        .find(|p| p.player_id == player_id1)
+        .expect("Player 1 must be in state");
+    assert_eq!(
+        p1.value,
+        Some(Vote::Card(13)),
+        "A client must see its own vote"
+    );
+
+    let other_view = recv_update_state(&mut client2).await;
+    let p1 = other_view
+        .players
+        .iter()
+        .find(|p| p.player_id == player_id1)
+        .expect("Player 1 must also be visible to client 2");
+    assert_eq!(p1.value, None, "Votes must not leak before the reveal");
+    assert!(p1.has_voted, "Other clients must still see who has voted");
+
+    client2
+        .send_text(serde_json::to_string(&ClientMessage::RevealNumbers { value: true }).unwrap())
+        .await;
+    let revealed = recv_update_state(&mut client2).await;
+    let p1 = revealed
+        .players
+        .iter()
+        .find(|p| p.player_id == player_id1)
+        .expect("Player 1 must be in state");
+    assert_eq!(
+        p1.value,
+        Some(Vote::Card(13)),
+        "Votes must be visible after the reveal"
+    );
+}
+
+/// A player kicked by the captain is told so and their socket is closed.
+#[tokio::test]
+async fn test_kicked_player_is_disconnected() {
+    let filter = build_ws_filter(new_game(), new_rooms());
+
+    let mut captain = warp::test::ws()
+        .path("/ws/it-kick")
+        .handshake(filter.clone())
+        .await
+        .expect("Captain handshake should succeed");
+    let captain_id = recv_player_assigned(&mut captain).await;
+    let _ = recv_update_state(&mut captain).await;
+
+    let mut troll = warp::test::ws()
+        .path("/ws/it-kick")
+        .handshake(filter.clone())
+        .await
+        .expect("Troll handshake should succeed");
+    let troll_id = recv_player_assigned(&mut troll).await;
+    let _ = recv_update_state(&mut troll).await;
+
+    captain
+        .send_text(
+            serde_json::to_string(&ClientMessage::KickPlayer {
+                player_id: captain_id,
+                target_id: troll_id,
+            })
+            .unwrap(),
+        )
+        .await;
+
+    let message = loop {
+        match recv_next_non_ping(&mut troll).await {
+            ServerMessage::UpdateState(_) => continue,
+            other => break other,
+        }
+    };
+    assert!(
+        matches!(message, ServerMessage::Removed { .. }),
+        "Kicked player must be told, got: {message:?}"
+    );
+    recv_until_closed(&mut troll).await;
+}
+
+/// Messages the server pushes through the shared pool reach every connection
+/// in the room, and only that room.
+#[tokio::test]
+async fn test_pool_pushes_reach_room() {
+    let pool = ConnectionPool::new();
+    let filter = build_ws_filter_with_pool(new_game(), new_rooms(), pool.clone());
+
+    let mut clients = Vec::new();
+    for room in ["it-push", "it-push", "it-push-other"] {
+        let mut client = warp::test::ws()
+            .path(&format!("/ws/{room}"))
+            .handshake(filter.clone())
+            .await
+            .expect("WebSocket handshake should succeed");
+        let _ = recv_player_assigned(&mut client).await;
+        let _ = recv_update_state(&mut client).await;
+        clients.push(client);
+    }
+
+    let notice = ServerMessage::ErrorMessage {
+        message: "Heads up".to_string(),
+    };
+    assert_eq!(pool.send_to_room("it-push", &notice).await, 2);
+    for client in &mut clients[..2] {
+        let message = recv_next_non_ping(client).await;
+        assert!(
+            matches!(&message, ServerMessage::ErrorMessage { message } if message == "Heads up"),
+            "got: {message:?}"
+        );
+    }
+}
+
+/// A client that stops answering pings is first shown as away, then evicted
+/// with its seat freed.
+#[tokio::test]
+async fn test_silent_client_goes_away_then_is_evicted() {
+    let config = Config {
+        ping_interval_secs: 1,
+        away_after_secs: 2,
+        stale_timeout_secs: 3,
+        ..Config::default()
+    };
+    let filter = build_ws_filter_with_config(
+        Game::new(&config),
+        new_rooms(),
+        ConnectionPool::new(),
+        config,
+    );
+
+    let mut silent = warp::test::ws()
+        .path("/ws/it-presence")
+        .handshake(filter.clone())
+        .await
+        .expect("Silent client handshake should succeed");
+    let silent_id = recv_player_assigned(&mut silent).await;
+    let _ = recv_update_state(&mut silent).await;
+
+    let mut observer = warp::test::ws()
+        .path("/ws/it-presence")
+        .handshake(filter.clone())
+        .await
+        .expect("Observer handshake should succeed");
+    let observer_id = recv_player_assigned(&mut observer).await;
+    let _ = recv_update_state(&mut observer).await;
+
+    // The observer keeps answering pings; the silent client never does.
+    let mut seen_away = false;
+    let evicted = tokio::time::timeout(std::time::Duration::from_secs(10), async {
+        loop {
+            let msg = observer
+                .recv()
+                .await
+                .expect("Observer should stay connected");
+            let Ok(text) = msg.to_str() else { continue };
+            match serde_json::from_str::<ServerMessage>(text).unwrap() {
+                ServerMessage::Ping { .. } => {
+                    observer
+                        .send_text(
+                            serde_json::to_string(&ClientMessage::Pong {
+                                player_id: observer_id,
+                            })
+                            .unwrap(),
+                        )
+                        .await;
+                }
+                ServerMessage::UpdateState(state) => {
+                    match state.players.iter().find(|p| p.player_id == silent_id) {
+                        Some(player) => seen_away |= player.away,
+                        None => return state,
+                    }
+                }
+                _ => {}
+            }
+        }
+    })
+    .await
+    .expect("Silent client should be evicted within the stale timeout");
+
+    assert!(seen_away, "Silent client must be shown as away first");
+    assert_eq!(evicted.players.len(), 1, "only the observer is left");
+    assert!(
+        !evicted.players[0].away,
+        "an answering client stays present"
+    );
+}
+
+/// A client whose socket drops keeps its seat for the grace period, shown as
+/// away to the rest of the room.
+#[tokio::test]
+async fn test_dropped_client_is_shown_away() {
+    let filter = build_ws_filter(new_game(), new_rooms());
+
+    let mut dropped = warp::test::ws()
+        .path("/ws/it-dropped")
+        .handshake(filter.clone())
+        .await
+        .expect("Dropped client handshake should succeed");
+    let dropped_id = recv_player_assigned(&mut dropped).await;
+    let _ = recv_update_state(&mut dropped).await;
+
+    let mut observer = warp::test::ws()
+        .path("/ws/it-dropped")
+        .handshake(filter.clone())
+        .await
+        .expect("Observer handshake should succeed");
+    let _ = recv_player_assigned(&mut observer).await;
+    let _ = recv_update_state(&mut observer).await;
+    drop(dropped);
+
+    let state = tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        recv_update_state(&mut observer),
+    )
+    .await
+    .expect("the room must hear that the client dropped");
+    let player = state
+        .players
+        .iter()
+        .find(|p| p.player_id == dropped_id)
+        .expect("the seat is held during the grace period");
+    assert!(player.away);
+}
+
+/// Draining for shutdown tells every client the server is restarting, closes
+/// their sockets, and finishes once they are gone.
+#[tokio::test]
+async fn test_shutdown_drain_closes_clients() {
+    let pool = ConnectionPool::new();
+    let filter = build_ws_filter_with_pool(new_game(), new_rooms(), pool.clone());
+
+    let mut clients = Vec::new();
+    for room in ["it-drain", "it-drain-other"] {
+        let mut client = warp::test::ws()
+            .path(&format!("/ws/{room}"))
+            .handshake(filter.clone())
+            .await
+            .expect("WebSocket handshake should succeed");
+        let _ = recv_player_assigned(&mut client).await;
+        let _ = recv_update_state(&mut client).await;
+        clients.push(client);
+    }
+
+    let drained = tokio::spawn({
+        let pool = pool.clone();
+        async move {
+            model_un::shutdown::drain_connections(
+                &pool,
+                tokio::time::Instant::now() + std::time::Duration::from_secs(5),
+            )
+            .await
+        }
+    });
+    for client in &mut clients {
+        let message = recv_next_non_ping(client).await;
+        assert!(
+            matches!(message, ServerMessage::ServerRestarting { .. }),
+            "Client must be told about the restart, got: {message:?}"
+        );
+        recv_until_closed(client).await;
+    }
+    assert!(
+        drained.await.unwrap(),
+        "drain must finish before the deadline"
+    );
+}
+
+/// A client that sends a message on behalf of another player's ID receives an
+/// `ErrorMessage` and the other player's state is left untouched.
+#[tokio::test]
+async fn test_spoofed_player_id_is_rejected() {
+    let filter = build_ws_filter(new_game(), new_rooms());
+
+    let mut client1 = warp::test::ws()
+        .path("/ws/it-spoofed-id")
+        .handshake(filter.clone())
+        .await
+        .expect("Client 1 handshake should succeed");
+    let player_id1 = recv_player_assigned(&mut client1).await;
+    let _ = recv_update_state(&mut client1).await;
+
+    let mut client2 = warp::test::ws()
+        .path("/ws/it-spoofed-id")
+        .handshake(filter.clone())
+        .await
+        .expect("Client 2 handshake should succeed");
+    let _ = recv_player_assigned(&mut client2).await;
+    let _ = recv_update_state(&mut client2).await;
+
+    // Client 2 tries to rename client 1.
+    client2
+        .send_text(
+            serde_json::to_string(&ClientMessage::ChangeName {
+                player_id: player_id1,
+                name: "Impostor".to_string(),
+            })
+            .unwrap(),
+        )
+        .await;
+
+    let server_msg = recv_next_non_ping(&mut client2).await;
+    assert!(
+        matches!(server_msg, ServerMessage::ErrorMessage { .. }),
+        "Spoofed message must be answered with ErrorMessage, got: {server_msg:?}"
+    );
+
+    // Client 1's own rename still goes through and shows no impostor.
+    client1
+        .send_text(
+            serde_json::to_string(&ClientMessage::ChangeName {
+                player_id: player_id1,
+                name: "Genuine".to_string(),
+            })
+            .unwrap(),
+        )
+        .await;
+    let state = recv_update_state(&mut client1).await;
+    let p1 = state
+        .players
+        .iter()
+        .find(|p| p.player_id == player_id1)
+        .expect("Player 1 must be in state");
+    assert_eq!(p1.player_name, "Genuine");
+}
+
+/// A client reconnecting with the resume token from `PlayerAssigned` gets its
+/// old seat and name back instead of a new seat.
+#[tokio::test]
+async fn test_reconnect_with_resume_token_keeps_seat() {
+    let filter = build_ws_filter(new_game(), new_rooms());
+
+    let mut client = warp::test::ws()
+        .path("/ws/it-resume")
+        .handshake(filter.clone())
+        .await
+        .expect("WebSocket handshake should succeed");
+
+    let (player_id, resume_token) = loop {
+        if let ServerMessage::PlayerAssigned {
+            player_id,
+            resume_token,
+            ..
+        } = recv_next_non_ping(&mut client).await
+        {
+            break (player_id, resume_token);
+        }
+    };
+    let _ = recv_update_state(&mut client).await;
+
+    client
+        .send_text(
+            serde_json::to_string(&ClientMessage::ChangeName {
+                player_id,
+                name: "Returning".to_string(),
+            })
+            .unwrap(),
+        )
+        .await;
+    let _ = recv_update_state(&mut client).await;
+    drop(client);
+
+    let mut client = warp::test::ws()
+        .path(&format!("/ws/it-resume?resume={resume_token}"))
+        .handshake(filter.clone())
+        .await
+        .expect("Reconnect handshake should succeed");
+
+    let resumed_id = recv_player_assigned(&mut client).await;
+    assert_eq!(resumed_id, player_id, "Resumed client must keep its seat");
+
+    let state = recv_update_state(&mut client).await;
+    let player = state
+        .players
+        .iter()
+        .find(|p| p.player_id == player_id)
+        .expect("Player must be in state");
+    assert_eq!(player.player_name, "Returning");
+    assert_eq!(
+        state.players.len(),
+        1,
+        "Resume must not allocate a new seat"
+    );
+}
+
+/// Resuming a seat that is still held by an open socket moves it to the new
+/// connection and closes the old one.
+#[tokio::test]
+async fn test_resume_from_second_socket_closes_first() {
+    let filter = build_ws_filter(new_game(), new_rooms());
+
+    let mut first = warp::test::ws()
+        .path("/ws/it-resume-twice")
+        .handshake(filter.clone())
+        .await
+        .expect("First handshake should succeed");
+    let (player_id, resume_token) = loop {
+        if let ServerMessage::PlayerAssigned {
+            player_id,
+            resume_token,
+            ..
+        } = recv_next_non_ping(&mut first).await
+        {
+            break (player_id, resume_token);
+        }
+    };
+    let _ = recv_update_state(&mut first).await;
+
+    let mut second = warp::test::ws()
+        .path(&format!("/ws/it-resume-twice?resume={resume_token}"))
+        .handshake(filter.clone())
+        .await
+        .expect("Second handshake should succeed");
+    assert_eq!(recv_player_assigned(&mut second).await, player_id);
+    let state = recv_update_state(&mut second).await;
+    assert_eq!(
+        state.players.len(),
+        1,
+        "Resume must not allocate a new seat"
+    );
+
+    let message = loop {
+        match recv_next_non_ping(&mut first).await {
+            ServerMessage::UpdateState(_) => continue,
+            other => break other,
+        }
+    };
+    assert!(
+        matches!(message, ServerMessage::Removed { .. }),
+        "First socket must be told, got: {message:?}"
+    );
+    recv_until_closed(&mut first).await;
+}
+
 // ── HTTP routes
 // ───────────────────────────────────────────────────────────────
 
@@ -396,14 +1135,7 @@ async fn test_multiple_clients_receive_state_updates() {
 /// HTTP 301 (Moved Permanently).
 #[tokio::test]
 async fn test_index_route_redirects_to_room() {
-    let game_state = model_un::game::Game::instance();
-    let index_route = warp::path::end().and_then(async move || {
-        let room_name = game_state.random_name_generator().await;
-        Ok::<_, warp::Rejection>(warp::redirect(
-            warp::http::Uri::from_maybe_shared(format!("/index.html?room={room_name}")).unwrap(),
-        ))
-    });
-    let routes = warp::get().and(index_route);
+    let (routes, _rooms, _pool) = model_un::build_routes(Config::default(), new_game());
 
     let response = warp::test::request()
         .method("GET")
@@ -424,3 +1156,293 @@ async fn test_index_route_redirects_to_room() {
         "Location must point to /index.html?room=…, got: {location}"
     );
 }
+
+/// A store other than the default, keeping its rooms in a wrapped
+/// `MemoryRoomStore`.
+#[derive(Default)]
+struct WrappedStore {
+    rooms: MemoryRoomStore,
+}
+
+impl RoomStore for WrappedStore {
+    async fn get(&self, room: &str) -> Option<GameState> {
+        self.rooms.get(room).await
+    }
+
+    async fn list(&self) -> Vec<String> {
+        self.rooms.list().await
+    }
+
+    async fn update<R: Send>(
+        &self,
+        room: &str,
+        f: impl FnOnce(&mut Option<GameState>) -> R + Send,
+    ) -> R {
+        self.rooms.update(room, f).await
+    }
+}
+
+/// The routes serve a `Game` backed by any `RoomStore`.
+#[tokio::test]
+async fn test_routes_serve_any_room_store() {
+    let game = Game::<WrappedStore>::new(&Config::default());
+    let (routes, _rooms, _pool) = model_un::build_routes(Config::default(), game.clone());
+
+    let mut client = warp::test::ws()
+        .path("/ws/it-store")
+        .handshake(routes)
+        .await
+        .expect("WebSocket handshake should succeed");
+    recv_player_assigned(&mut client).await;
+    let state = recv_update_state(&mut client).await;
+
+    assert_eq!(state.players.len(), 1);
+    assert!(game.get_room_state("it-store").await.is_some());
+}
+
+// ── Passphrases ──────────────────────────────────────────────────────────────
+
+/// A room created with a passphrase turns away connections without it before
+/// they get a seat, and admits those that present it.
+#[tokio::test]
+async fn test_locked_room_refuses_connection_without_passphrase() {
+    let game = new_game();
+    let filter = build_ws_filter(game.clone(), new_rooms());
+
+    let mut creator = warp::test::ws()
+        .path("/ws/it-locked?passphrase=hunter2")
+        .handshake(filter.clone())
+        .await
+        .expect("Creator handshake should succeed");
+    let _ = recv_player_assigned(&mut creator).await;
+    let state = recv_update_state(&mut creator).await;
+    assert!(state.locked);
+
+    let mut stranger = warp::test::ws()
+        .path("/ws/it-locked")
+        .handshake(filter.clone())
+        .await
+        .expect("Stranger handshake should succeed");
+    let message = recv_next_non_ping(&mut stranger).await;
+    assert!(
+        matches!(message, ServerMessage::PassphraseRequired { .. }),
+        "Stranger must be refused, got: {message:?}"
+    );
+    assert!(
+        stranger.recv_closed().await.is_ok(),
+        "socket must be closed"
+    );
+
+    let mut guest = warp::test::ws()
+        .path("/ws/it-locked?passphrase=hunter2")
+        .handshake(filter.clone())
+        .await
+        .expect("Guest handshake should succeed");
+    let _ = recv_player_assigned(&mut guest).await;
+    let state = recv_update_state(&mut guest).await;
+    assert_eq!(
+        state.players.len(),
+        2,
+        "only the creator and guest are seated"
+    );
+
+    let export = warp::test::request()
+        .path("/rooms/it-locked/export.json")
+        .reply(&model_un::build_export_route(game.clone()))
+        .await;
+    assert_eq!(export.status(), 403);
+    let export = warp::test::request()
+        .path("/rooms/it-locked/export.json?passphrase=hunter2")
+        .reply(&model_un::build_export_route(game.clone()))
+        .await;
+    assert_eq!(export.status(), 200);
+}
+
+// ── Export ────────────────────────────────────────────────────────────────────
+
+/// Exports are served per room with a format-specific content type; unknown
+/// rooms and formats are 404s.
+#[tokio::test]
+async fn test_export_route_serves_room_results() {
+    let game = new_game();
+    let routes = model_un::build_export_route(game.clone());
+    game.new_player("it-export").await;
+
+    let response = warp::test::request()
+        .path("/rooms/it-export/export.csv")
+        .reply(&routes)
+        .await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.headers()["content-type"],
+        "text/csv; charset=utf-8"
+    );
+    assert!(
+        response
+            .body()
+            .starts_with(b"round,key,topic,link,player_id,player,vote")
+    );
+
+    let response = warp::test::request()
+        .path("/rooms/it-export/export.json")
+        .reply(&routes)
+        .await;
+    let export: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(export["room"], "it-export");
+
+    for path in [
+        "/rooms/it-export/export.xlsx",
+        "/rooms/it-export-missing/export.md",
+    ] {
+        let response = warp::test::request().path(path).reply(&routes).await;
+        assert_eq!(response.status(), 404, "{path} must not be found");
+    }
+}
+
+// ── Import ────────────────────────────────────────────────────────────────────
+
+/// The captain can upload a backlog with their resume token; everyone else is
+/// turned away and invalid rows are reported.
+#[tokio::test]
+async fn test_import_route_seeds_agenda() {
+    let game = new_game();
+    let routes = model_un::build_import_route(new_rooms(), game.clone());
+    let (_, token, _) = game
+        .join_with_connection(
+            "it-import",
+            "it-import-conn".to_string(),
+            &ConnectionQuery::default(),
+        )
+        .await
+        .unwrap();
+
+    let response = warp::test::request()
+        .method("POST")
+        .path("/rooms/it-import/import")
+        .body("title\nLogin\n")
+        .reply(&routes)
+        .await;
+    assert_eq!(response.status(), 403, "missing token must be rejected");
+
+    let response = warp::test::request()
+        .method("POST")
+        .path("/rooms/it-import/import")
+        .header("authorization", format!("Bearer {token}"))
+        .body("title,link\nLogin,ftp://nope\n")
+        .reply(&routes)
+        .await;
+    assert_eq!(response.status(), 400);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert!(body["errors"][0].as_str().unwrap().starts_with("Row 1:"));
+
+    let response = warp::test::request()
+        .method("POST")
+        .path("/rooms/it-import/import")
+        .header("authorization", format!("Bearer {token}"))
+        .body(r#"[{"id": "MUN-1", "title": "Login"}, {"id": "MUN-2", "title": "Logout"}]"#)
+        .reply(&routes)
+        .await;
+    assert_eq!(response.status(), 200);
+
+    let state = game.get_room_state("it-import").await.unwrap();
+    assert_eq!(state.agenda.len(), 2);
+    assert_eq!(state.agenda[1].key.as_deref(), Some("MUN-2"));
+}
+
+// ── Restarts ──────────────────────────────────────────────────────────────────
+
+/// Restored seats nobody resumes are freed after the grace period, and the
+/// room hears about it, including the spectator promoted into a freed seat.
+#[tokio::test]
+async fn test_release_unclaimed_seats_updates_room() {
+    let config = Config {
+        max_room_size: 2,
+        ..Config::default()
+    };
+    let game: Arc<Game> = Game::new(&config);
+    let mut tokens = Vec::new();
+    for connection_id in ["conn-a", "conn-b", "conn-c"] {
+        let (_, token, _) = game
+            .join_with_connection(
+                "it-release",
+                connection_id.to_string(),
+                &ConnectionQuery::default(),
+            )
+            .await
+            .unwrap();
+        tokens.push(token);
+    }
+
+    let restored: Arc<Game> = Game::new(&config);
+    let rooms = new_rooms();
+    let seats = GameWebSocket::restore_rooms(&restored, &rooms, game.snapshot().await).await;
+    let filter = build_ws_filter_with_config(
+        restored.clone(),
+        rooms.clone(),
+        ConnectionPool::new(),
+        config,
+    );
+    let mut client = warp::test::ws()
+        .path(&format!("/ws/it-release?resume={}", tokens[2]))
+        .handshake(filter)
+        .await
+        .expect("WebSocket handshake should succeed");
+    assert_eq!(recv_player_assigned(&mut client).await, 100);
+    let _ = recv_update_state(&mut client).await;
+
+    GameWebSocket::release_unclaimed_seats(
+        restored.clone(),
+        rooms,
+        seats,
+        std::time::Duration::ZERO,
+    )
+    .await;
+    let state = recv_update_state(&mut client).await;
+    assert!(
+        state
+            .notify_changes
+            .iter()
+            .any(|change| change.current_id == 100 && change.new_id == 0),
+        "the spectator must learn it took seat 0"
+    );
+    let state = recv_update_state(&mut client).await;
+    assert_eq!(state.players.len(), 1);
+}
+
+/// A countdown running when the server stopped still reveals the round after
+/// a restart.
+#[tokio::test]
+async fn test_restored_countdown_reveals_round() {
+    let game = new_game();
+    let room = "it-restore-countdown";
+    game.join_with_connection(room, "conn-a".to_string(), &ConnectionQuery::default())
+        .await
+        .unwrap();
+    for message in [
+        ClientMessage::ChangeAutoReveal {
+            player_id: 0,
+            auto_reveal: AutoReveal {
+                enabled: true,
+                countdown_secs: 1,
+            },
+        },
+        ClientMessage::ChangeValue {
+            player_id: 0,
+            value: Vote::Card(5),
+        },
+    ] {
+        game.process_connection_message(room, "conn-a", message)
+            .await
+            .unwrap();
+    }
+
+    let restored = new_game();
+    GameWebSocket::restore_rooms(&restored, &new_rooms(), game.snapshot().await).await;
+    tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        while !restored.get_room_state(room).await.unwrap().all_revealed {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("the server must reveal when the countdown ends");
+}
@@ -10,7 +10,9 @@ use std::time::Duration;
 
 use futures::{SinkExt, StreamExt};
 use model_un::build_ws_route;
-use model_un::structs::{ClientMessage, GameState, ServerMessage};
+use model_un::config::Config;
+use model_un::game::Game;
+use model_un::structs::{ClientMessage, GameState, ServerMessage, Vote};
 use tokio::net::TcpStream;
 use tokio::sync::Barrier;
 use tokio::task::JoinHandle;
@@ -24,7 +26,8 @@ use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, connect_async};
 
 /// Start a warp server on an OS-assigned port and return the address.
 async fn start_server() -> SocketAddr {
-    let (ws_route, _tx) = build_ws_route();
+    let (ws_route, _rooms, _pool) =
+        build_ws_route(Config::default(), <Game>::new(&Config::default()));
     let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0u16))
         .await
         .expect("failed to bind");
@@ -62,7 +65,7 @@ async fn connect_client(
         match timeout(Duration::from_secs(5), stream.next()).await {
             Ok(Some(Ok(msg))) => {
                 if let Ok(text) = msg.into_text()
-                    && let Ok(ServerMessage::PlayerAssigned { player_id: pid }) =
+                    && let Ok(ServerMessage::PlayerAssigned { player_id: pid, .. }) =
                         serde_json::from_str::<ServerMessage>(&text)
                 {
                     player_id = Some(pid);
@@ -101,8 +104,11 @@ async fn simulate_client_activity(
         // Pick a Fibonacci value to vote.
         let value = vote_values[round % vote_values.len()];
 
-        let change_value =
-            serde_json::to_string(&ClientMessage::ChangeValue { player_id, value }).unwrap();
+        let change_value = serde_json::to_string(&ClientMessage::ChangeValue {
+            player_id,
+            value: Vote::Card(value),
+        })
+        .unwrap();
         if ws.send(Message::Text(change_value.into())).await.is_err() {
             return (false, last_state);
         }
@@ -142,7 +148,7 @@ async fn simulate_client_activity(
                 && let Ok(ServerMessage::UpdateState(state)) =
                     serde_json::from_str::<ServerMessage>(&text)
             {
-                last_state = Some(state);
+                last_state = Some(*state);
             }
         }
 
@@ -168,7 +174,7 @@ async fn drain_final_state(
             && let Ok(ServerMessage::UpdateState(state)) =
                 serde_json::from_str::<ServerMessage>(&text)
         {
-            last_state = Some(state);
+            last_state = Some(*state);
         }
     }
     last_state
@@ -415,7 +421,7 @@ async fn test_find_maximum_connections() {
 
         // Move to next room every `clients_per_room`
         // connections to spread the load.
-        if current_count % clients_per_room == 0 {
+        if current_count.is_multiple_of(clients_per_room) {
             room_index += 1;
         }
     }